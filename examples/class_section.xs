@@ -0,0 +1,10 @@
+section Shapes {
+    snippet Named {
+        let name: Word = "shape";
+    }
+
+    class Circle {
+        include Named;
+        let radius: Number = 1;
+    }
+}
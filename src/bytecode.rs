@@ -0,0 +1,1513 @@
+use super::codegen;
+use super::codegen::EnumLayout;
+use super::diagnostic::Diagnostic;
+use super::parser::ASTNode;
+use super::parser::Location;
+use super::parser::Production;
+use super::resolver;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::rc::Rc;
+
+/// One instruction of the stack machine. Operands index the constant pool,
+/// slots, cells, patterns or jump tables of the function the instruction
+/// belongs to, or its code for jumps.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Op {
+    /// Pushes a constant of the pool.
+    Constant(u16),
+    Nothing,
+    Null,
+    True,
+    False,
+    Pop,
+    /// Pushes a copy of the value on top.
+    Dup,
+    /// Pushes copies of the two values on top, keeping their order.
+    Dup2,
+    GetLocal(u16),
+    /// Pops the value on top into a slot.
+    SetLocal(u16),
+    /// Pops the value on top into a new cell, where the closures created
+    /// from then on share it with the function.
+    NewCell(u16),
+    GetCell(u16),
+    SetCell(u16),
+    /// Pushes the value of a cell the running closure captured.
+    GetUpvalue(u16),
+    SetUpvalue(u16),
+    /// Replaces the instance on top with the field in the given slot.
+    GetField(u16),
+    /// Pops a value and an instance, setting the field in the given slot.
+    SetField(u16),
+    /// Replaces the value on top with its member named by a constant.
+    GetMember(u16),
+    SetMember(u16),
+    GetIndex,
+    SetIndex,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Less,
+    Greater,
+    LessEqual,
+    GreaterEqual,
+    Equal,
+    NotEqual,
+    Negate,
+    /// Replaces the `Boolean` on top with its opposite.
+    Not,
+    /// Checks that the value on top is a `Boolean`.
+    Condition,
+    Jump(u32),
+    /// Pops a `Boolean`, jumping if it is `false`.
+    JumpIfFalse(u32),
+    /// Jumps if the value on top is `false`, leaving it, and pops it
+    /// otherwise. The left operand of `&&`.
+    JumpIfFalseKeep(u32),
+    /// The left operand of `||`.
+    JumpIfTrueKeep(u32),
+    /// The left operand of `??`.
+    JumpIfNotNullKeep(u32),
+    /// Jumps if the value on top is `null`, leaving it either way. The
+    /// object of `?.`.
+    JumpIfNull(u32),
+    /// Pops a list into the given slot, copied so that the loop goes over
+    /// the elements it had to begin with, and sets the next slot to 0.
+    Iterate(u16),
+    /// Pushes the next element of the list in the given slot, or jumps when
+    /// there are no more.
+    Next(u16, u32),
+    MakeList(u16),
+    /// Pops the given number of keys and values.
+    MakeMap(u16),
+    /// Pops the given number of values into a variant named by a constant,
+    /// with the given tag.
+    MakeVariant(u16, u16, u16),
+    /// Pushes a closure of the function constant, capturing the cells its
+    /// `captures` name.
+    Closure(u16),
+    /// Replaces the instance on top with a method of the program bound to
+    /// it. `super.method`.
+    Bind(u16),
+    /// Calls the function below the given number of arguments.
+    Call(u16),
+    /// Calls the method named by a constant of the object below the given
+    /// number of arguments.
+    Invoke(u16, u16),
+    /// Calls the method in the given vtable slot of the instance below the
+    /// arguments.
+    InvokeVirtual(u16, u16),
+    /// Calls a method of the program on the instance below the arguments.
+    /// `super.method(...)`.
+    InvokeStatic(u16, u16),
+    /// Puts a new instance of a class of the program below the given
+    /// number of arguments and initialises its fields.
+    New(u16, u16),
+    /// Runs `init` on the instance below the arguments, if its class has
+    /// one.
+    Init(u16),
+    /// Replaces an `Ok` on top with its value, or returns an `Err`.
+    Try,
+    /// Tests the value on top against a pattern. It is popped and the names
+    /// the pattern binds are set if it fits, otherwise the jump is taken.
+    Match(u16, u32),
+    /// Jumps to the arm of a jump table for the tag of the value on top,
+    /// popping it, or to the table's default leaving it.
+    JumpTable(u16),
+    /// Fails with the value on top, which no arm of a `match` fits.
+    NoMatch,
+    Print,
+    Return,
+}
+
+impl Op {
+    /// The jump, with its target replaced.
+    fn with_target(self, target: u32) -> Op {
+        match self {
+            Op::Jump(_) => Op::Jump(target),
+            Op::JumpIfFalse(_) => Op::JumpIfFalse(target),
+            Op::JumpIfFalseKeep(_) => Op::JumpIfFalseKeep(target),
+            Op::JumpIfTrueKeep(_) => Op::JumpIfTrueKeep(target),
+            Op::JumpIfNotNullKeep(_) => Op::JumpIfNotNullKeep(target),
+            Op::JumpIfNull(_) => Op::JumpIfNull(target),
+            Op::Next(slot, _) => Op::Next(slot, target),
+            Op::Match(pattern, _) => Op::Match(pattern, target),
+            other => other,
+        }
+    }
+}
+
+/// Value of the constant pool of a function.
+#[derive(Debug, Clone)]
+pub enum Constant {
+    Number(i64),
+    /// A word, or the name of a member or variant.
+    Word(Rc<str>),
+    /// A function literal.
+    Function(Rc<Function>),
+}
+
+impl PartialEq for Constant {
+    fn eq(&self, other: &Constant) -> bool {
+        match (self, other) {
+            (Constant::Number(a), Constant::Number(b)) => a == b,
+            (Constant::Word(a), Constant::Word(b)) => a == b,
+            (Constant::Function(a), Constant::Function(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+/// Where a variable lives in the frame of the function declaring it. Locals
+/// captured by a closure live in cells, which the frame and the closures
+/// share, the others in slots of the frame.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Var {
+    Slot(u16),
+    Cell(u16),
+}
+
+/// Cell a closure captures when it is created: a cell of the function
+/// creating it, or one that function captured itself.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Capture {
+    Cell(u16),
+    Upvalue(u16),
+}
+
+/// Pattern of a `match` arm.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Pattern {
+    Wildcard,
+    Binding(Var),
+    Number(i64),
+    Word(Rc<str>),
+    Boolean(bool),
+    /// Full name of the variant and patterns for the values it carries.
+    Variant(Rc<str>, Vec<Pattern>),
+}
+
+/// Arms of a `match` over a unit only enum, by tag.
+#[derive(Debug, PartialEq, Clone)]
+pub struct JumpTable {
+    /// Full names of the variants, by tag.
+    pub variants: Vec<Rc<str>>,
+    pub targets: Vec<u32>,
+    /// Where values that are no variant of the enum go.
+    pub default: u32,
+}
+
+/// Compiled code of a method, function literal or field initialiser.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Function {
+    /// Name the function is shown with in stack traces.
+    pub name: String,
+    /// Number of parameters, not counting `this`.
+    pub arity: usize,
+    /// Whether slot 0 holds the instance the function was called on,
+    /// followed by the arguments. Other functions find the arguments from
+    /// slot 0.
+    pub method: bool,
+    pub slots: usize,
+    pub cells: usize,
+    pub captures: Vec<Capture>,
+    /// Whether the function is left out of stack traces, being run on
+    /// behalf of the caller.
+    pub hidden: bool,
+    pub code: Vec<Op>,
+    /// Location of each instruction.
+    pub locations: Vec<Location>,
+    pub constants: Vec<Constant>,
+    pub patterns: Vec<Pattern>,
+    pub tables: Vec<JumpTable>,
+}
+
+impl Function {
+    fn new(name: String, method: bool) -> Function {
+        Function {
+            name,
+            arity: 0,
+            method,
+            slots: usize::from(method),
+            cells: 0,
+            captures: vec![],
+            hidden: false,
+            code: vec![],
+            locations: vec![],
+            constants: vec![],
+            patterns: vec![],
+            tables: vec![],
+        }
+    }
+}
+
+/// A class as the machine sees it, laid out by `codegen::layouts`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Class {
+    pub name: String,
+    /// Slot of each field.
+    pub fields: HashMap<String, usize>,
+    pub field_count: usize,
+    /// Methods of the program, by vtable slot.
+    pub vtable: Vec<usize>,
+    /// Vtable slot of each method.
+    pub methods: HashMap<String, usize>,
+    /// Sets the fields of a new instance, which it gets as `this`. `None`
+    /// when no field has an initialiser.
+    pub initializer: Option<Rc<Function>>,
+}
+
+/// A compiled program.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Program {
+    pub classes: Vec<Rc<Class>>,
+    pub methods: Vec<Rc<Function>>,
+    /// Creates an instance of `Main` and calls its `main` method.
+    pub entry: Rc<Function>,
+}
+
+/// Compiles the function being compiled and those it is nested in.
+struct FunctionState {
+    function: Function,
+    /// Names visible in each block, innermost last.
+    scopes: Vec<Vec<(String, Var)>>,
+    /// Names of the locals kept in cells, those a function nested in this
+    /// one captures.
+    boxed: Vec<String>,
+    /// Names of the cells captured, by upvalue index.
+    upvalues: Vec<String>,
+    /// Start of each loop the code is in and the breaks out of it to
+    /// patch, innermost last.
+    loops: Vec<(usize, Vec<usize>)>,
+}
+
+/// Names captured by the function literals nested in `node`.
+fn captured_below(
+    node: &ASTNode,
+    captures: &HashMap<Location, Vec<String>>,
+    names: &mut Vec<String>,
+) {
+    for child in &node.children {
+        if child.is(Production::FunctionDec) {
+            for name in captures.get(&child.location()).into_iter().flatten() {
+                if !names.contains(name) {
+                    names.push(name.clone());
+                }
+            }
+        }
+        captured_below(child, captures, names);
+    }
+}
+
+fn contains_function(node: &ASTNode) -> bool {
+    node.is(Production::FunctionDec) || node.children.iter().any(contains_function)
+}
+
+fn too_many(what: &str, place: &str, at: &ASTNode) -> Diagnostic {
+    Diagnostic::at(
+        format!("Too many {} in {} for the virtual machine", what, place),
+        at,
+    )
+}
+
+/// Fails unless a `u16` operand can index each of `count` things in `place`.
+fn indexable(count: usize, what: &str, place: &str, at: &ASTNode) -> Compiled<()> {
+    if count > usize::from(u16::MAX) + 1 {
+        return Err(too_many(what, place, at));
+    }
+    Ok(())
+}
+
+/// Compiles a program the checker accepted, whose sections have been
+/// flattened, snippets included and operators lowered, to bytecode.
+struct Compiler<'a> {
+    classes: Vec<&'a ASTNode>,
+    layouts: Vec<codegen::ClassLayout>,
+    enums: Vec<EnumLayout>,
+    /// Index of each method of the program, by class and name.
+    method_index: HashMap<(String, String), usize>,
+    captures: HashMap<Location, Vec<String>>,
+    /// Index of the class whose members are being compiled.
+    class: usize,
+    functions: Vec<FunctionState>,
+}
+
+type Compiled<T> = Result<T, Diagnostic>;
+
+impl<'a> Compiler<'a> {
+    fn state(&mut self) -> &mut FunctionState {
+        self.functions
+            .last_mut()
+            .expect("no function being compiled")
+    }
+
+    fn emit(&mut self, op: Op, at: &ASTNode) -> usize {
+        let function = &mut self.state().function;
+        function.code.push(op);
+        function.locations.push(at.location());
+        function.code.len() - 1
+    }
+
+    fn here(&mut self) -> u32 {
+        self.state().function.code.len() as u32
+    }
+
+    /// Points the jump at `jump` to the next instruction.
+    fn patch(&mut self, jump: usize) {
+        let target = self.here();
+        let code = &mut self.state().function.code;
+        code[jump] = code[jump].with_target(target);
+    }
+
+    fn constant(&mut self, constant: Constant) -> u16 {
+        let constants = &mut self.state().function.constants;
+        let index = match constants.iter().position(|c| *c == constant) {
+            Some(index) => index,
+            None => {
+                constants.push(constant);
+                constants.len() - 1
+            }
+        };
+        // checked by `leave`, like the other operands indexing the function
+        index as u16
+    }
+
+    fn word(&mut self, word: &str) -> u16 {
+        self.constant(Constant::Word(Rc::from(word)))
+    }
+
+    /// A new variable named `name` in the innermost block, in a cell if a
+    /// nested function captures it.
+    fn declare(&mut self, name: &str) -> Var {
+        let state = self.state();
+        let var = if state.boxed.iter().any(|b| b == name) {
+            state.function.cells += 1;
+            Var::Cell(state.function.cells as u16 - 1)
+        } else {
+            state.function.slots += 1;
+            Var::Slot(state.function.slots as u16 - 1)
+        };
+        if let Some(scope) = state.scopes.last_mut() {
+            scope.push((String::from(name), var));
+        }
+        var
+    }
+
+    /// A slot no name refers to.
+    fn hidden_slots(&mut self, count: usize) -> u16 {
+        let function = &mut self.state().function;
+        function.slots += count;
+        (function.slots - count) as u16
+    }
+
+    fn lookup(&self, depth: usize, name: &str) -> Option<Var> {
+        self.functions[depth]
+            .scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.iter().rev().find(|(n, _)| n == name))
+            .map(|(_, var)| *var)
+    }
+
+    /// Index of the cell `name` of an enclosing function among those the
+    /// function at `depth` captures.
+    fn upvalue(&mut self, depth: usize, name: &str) -> Option<u16> {
+        if let Some(index) = self.functions[depth]
+            .upvalues
+            .iter()
+            .position(|u| u == name)
+        {
+            return Some(index as u16);
+        }
+        if depth == 0 {
+            return None;
+        }
+        let capture = match self.lookup(depth - 1, name) {
+            Some(Var::Cell(cell)) => Capture::Cell(cell),
+            // only captured locals are in slots, see `boxed`
+            Some(Var::Slot(_)) => return None,
+            None => Capture::Upvalue(self.upvalue(depth - 1, name)?),
+        };
+        let state = &mut self.functions[depth];
+        state.upvalues.push(String::from(name));
+        state.function.captures.push(capture);
+        Some(state.upvalues.len() as u16 - 1)
+    }
+
+    fn load(&mut self, name: &str, at: &ASTNode) -> Compiled<()> {
+        let depth = self.functions.len() - 1;
+        let op = match self.lookup(depth, name) {
+            Some(Var::Slot(slot)) => Op::GetLocal(slot),
+            Some(Var::Cell(cell)) => Op::GetCell(cell),
+            None => match self.upvalue(depth, name) {
+                Some(upvalue) => Op::GetUpvalue(upvalue),
+                None => return Err(Diagnostic::at(format!("Unknown name `{}`", name), at)),
+            },
+        };
+        self.emit(op, at);
+        Ok(())
+    }
+
+    fn store(&mut self, name: &str, at: &ASTNode) -> Compiled<()> {
+        let depth = self.functions.len() - 1;
+        let op = match self.lookup(depth, name) {
+            Some(Var::Slot(slot)) => Op::SetLocal(slot),
+            Some(Var::Cell(cell)) => Op::SetCell(cell),
+            None => match self.upvalue(depth, name) {
+                Some(upvalue) => Op::SetUpvalue(upvalue),
+                None => return Err(Diagnostic::at(format!("Unknown name `{}`", name), at)),
+            },
+        };
+        self.emit(op, at);
+        Ok(())
+    }
+
+    /// Stores the value on top in a variable declared just now.
+    fn define(&mut self, var: Var, at: &ASTNode) {
+        match var {
+            Var::Slot(slot) => self.emit(Op::SetLocal(slot), at),
+            Var::Cell(cell) => self.emit(Op::NewCell(cell), at),
+        };
+    }
+
+    /// Starts compiling a function of the class being compiled, whose
+    /// locals named in `boxed` are kept in cells. Parameters and `this`
+    /// among them are moved to cells on entry.
+    fn enter(
+        &mut self,
+        mut function: Function,
+        params: &[ASTNode],
+        boxed: Vec<String>,
+        at: &ASTNode,
+    ) {
+        function.arity = params.len();
+        let mut parameters = vec![];
+        if function.method {
+            parameters.push((String::from("this"), Var::Slot(0)));
+        }
+        for param in params {
+            parameters.push((param.name(), Var::Slot(function.slots as u16)));
+            function.slots += 1;
+        }
+        self.functions.push(FunctionState {
+            function,
+            scopes: vec![vec![]],
+            boxed,
+            upvalues: vec![],
+            loops: vec![],
+        });
+        for (name, slot) in parameters {
+            match slot {
+                Var::Slot(slot) if self.state().boxed.contains(&name) => {
+                    self.emit(Op::GetLocal(slot), at);
+                    let var = self.declare(&name);
+                    self.define(var, at);
+                }
+                _ => self.state().scopes[0].push((name, slot)),
+            }
+        }
+    }
+
+    /// Finishes the function being compiled, or fails if its operands
+    /// could not index all of its constants, variables, patterns or jump
+    /// tables, or its jumps all of its code.
+    fn leave(&mut self, at: &ASTNode) -> Compiled<Function> {
+        let function = self
+            .functions
+            .pop()
+            .expect("no function being compiled")
+            .function;
+        let counts = [
+            (function.constants.len(), "constants"),
+            (function.slots, "local variables"),
+            (function.cells, "captured variables"),
+            (function.captures.len(), "captures"),
+            (function.patterns.len(), "patterns"),
+            (function.tables.len(), "jump tables"),
+        ];
+        let place = format!("`{}`", function.name);
+        for (count, what) in &counts {
+            indexable(*count, what, &place, at)?;
+        }
+        if u32::try_from(function.code.len()).is_err() {
+            return Err(too_many("instructions", &place, at));
+        }
+        Ok(function)
+    }
+
+    /// Compiles a method or function literal.
+    fn function(&mut self, name: String, method: bool, node: &'a ASTNode) -> Compiled<Function> {
+        let params = node
+            .find(Production::Params)
+            .map_or(&[][..], |p| &p.children[..]);
+        let mut boxed = vec![];
+        captured_below(node, &self.captures, &mut boxed);
+        self.enter(Function::new(name, method), params, boxed, node);
+        if let Some(body) = node.find(Production::BlockBody) {
+            self.block(body)?;
+        }
+        self.emit(Op::Nothing, node);
+        self.emit(Op::Return, node);
+        self.leave(node)
+    }
+
+    /// Compiles the field initialisers of a class and of its ancestors into
+    /// a function run on every new instance.
+    fn initializer(&mut self, class: usize) -> Compiled<Option<Rc<Function>>> {
+        let mut fields = vec![];
+        let mut current = Some(class);
+        while let Some(index) = current {
+            let declaration = self.classes[index];
+            fields.splice(
+                0..0,
+                declaration
+                    .members()
+                    .iter()
+                    .filter(|m| !m.is_method() && m.initializer().is_some())
+                    .map(|m| (index, m)),
+            );
+            current = declaration
+                .find(Production::Extends)
+                .and_then(|e| self.class_index(&e.name()));
+        }
+        if fields.is_empty() {
+            return Ok(None);
+        }
+        let mut boxed = vec![];
+        for (_, field) in &fields {
+            captured_below(field, &self.captures, &mut boxed);
+            if contains_function(field) {
+                // function literals in initialisers use the `this` of the
+                // instance being initialised
+                boxed.push(String::from("this"));
+            }
+        }
+        let declaration = self.classes[class];
+        let mut function = Function::new(declaration.name(), true);
+        function.hidden = true;
+        self.enter(function, &[], boxed, declaration);
+        for (owner, field) in fields {
+            self.class = owner;
+            self.emit(Op::GetLocal(0), field);
+            self.expression(field.initializer().unwrap_or(field))?;
+            let slot = self.layouts[class]
+                .fields
+                .iter()
+                .position(|f| *f == field.name())
+                .unwrap_or_default();
+            self.emit(Op::SetField(slot as u16), field);
+        }
+        self.class = class;
+        self.emit(Op::Nothing, declaration);
+        self.emit(Op::Return, declaration);
+        Ok(Some(Rc::new(self.leave(declaration)?)))
+    }
+
+    fn class_index(&self, name: &str) -> Option<usize> {
+        self.classes.iter().position(|c| c.name() == name)
+    }
+
+    fn block(&mut self, block: &'a ASTNode) -> Compiled<()> {
+        self.state().scopes.push(vec![]);
+        for statement in &block.children {
+            self.statement(statement)?;
+        }
+        self.state().scopes.pop();
+        Ok(())
+    }
+
+    fn statement(&mut self, statement: &'a ASTNode) -> Compiled<()> {
+        match &statement.production {
+            Some(Production::Vardec) => {
+                let name = statement.name();
+                match statement.initializer() {
+                    // declared first so that a local function can call itself
+                    Some(initializer) if initializer.is(Production::FunctionDec) => {
+                        let var = self.declare(&name);
+                        if let Var::Cell(cell) = var {
+                            // the function captures the cell it is put in
+                            self.emit(Op::Null, statement);
+                            self.emit(Op::NewCell(cell), statement);
+                        }
+                        self.expression(initializer)?;
+                        match var {
+                            Var::Cell(cell) => self.emit(Op::SetCell(cell), statement),
+                            Var::Slot(slot) => self.emit(Op::SetLocal(slot), statement),
+                        };
+                    }
+                    Some(initializer) => {
+                        self.expression(initializer)?;
+                        let var = self.declare(&name);
+                        self.define(var, statement);
+                    }
+                    None => {
+                        self.emit(Op::Null, statement);
+                        let var = self.declare(&name);
+                        self.define(var, statement);
+                    }
+                }
+            }
+            Some(Production::If) => {
+                self.expression(&statement.children[0])?;
+                let skip = self.emit(Op::JumpIfFalse(0), &statement.children[0]);
+                self.block(&statement.children[1])?;
+                match statement.children.get(2) {
+                    Some(otherwise) => {
+                        let end = self.emit(Op::Jump(0), statement);
+                        self.patch(skip);
+                        if otherwise.is(Production::If) {
+                            self.statement(otherwise)?;
+                        } else {
+                            self.block(otherwise)?;
+                        }
+                        self.patch(end);
+                    }
+                    None => self.patch(skip),
+                }
+            }
+            Some(Production::Return) => {
+                match statement.children.first() {
+                    Some(value) => self.expression(value)?,
+                    None => {
+                        self.emit(Op::Nothing, statement);
+                    }
+                }
+                self.emit(Op::Return, statement);
+            }
+            Some(Production::Print) => {
+                self.expression(&statement.children[0])?;
+                self.emit(Op::Print, statement);
+            }
+            Some(Production::For) => {
+                let slot = self.hidden_slots(2);
+                self.expression(&statement.children[0])?;
+                self.emit(Op::Iterate(slot), &statement.children[0]);
+                let start = self.here() as usize;
+                let next = self.emit(Op::Next(slot, 0), statement);
+                self.state().loops.push((start, vec![next]));
+                self.state().scopes.push(vec![]);
+                let var = self.declare(&statement.name());
+                self.define(var, statement);
+                self.block(&statement.children[1])?;
+                self.state().scopes.pop();
+                self.emit(Op::Jump(start as u32), statement);
+                let (_, breaks) = self.state().loops.pop().unwrap_or_default();
+                for jump in breaks {
+                    self.patch(jump);
+                }
+            }
+            Some(Production::Break) => {
+                let jump = self.emit(Op::Jump(0), statement);
+                if let Some((_, breaks)) = self.state().loops.last_mut() {
+                    breaks.push(jump);
+                }
+            }
+            Some(Production::Continue) => {
+                let start = self.state().loops.last().map_or(0, |(start, _)| *start);
+                self.emit(Op::Jump(start as u32), statement);
+            }
+            Some(Production::Assign) => self.assign(statement)?,
+            _ => {
+                self.expression(statement)?;
+                self.emit(Op::Pop, statement);
+            }
+        }
+        Ok(())
+    }
+
+    /// Slot of the field `name` of the class being compiled, which `this`
+    /// has whatever subclass it is an instance of.
+    fn own_field(&self, object: &ASTNode, name: &str) -> Option<u16> {
+        if !object.is(Production::This) {
+            return None;
+        }
+        let fields = &self.layouts[self.class].fields;
+        fields
+            .iter()
+            .position(|f| f == name)
+            .map(|slot| slot as u16)
+    }
+
+    /// Vtable slot of the method `name` of the class being compiled.
+    fn own_method(&self, object: &ASTNode, name: &str) -> Option<u16> {
+        if !object.is(Production::This) || self.own_field(object, name).is_some() {
+            return None;
+        }
+        let vtable = &self.layouts[self.class].vtable;
+        vtable
+            .iter()
+            .position(|e| e.method == name)
+            .map(|slot| slot as u16)
+    }
+
+    /// Index of the method of the parent of the class being compiled that
+    /// `super.name` calls.
+    fn parent_method(&self, node: &ASTNode) -> Compiled<u16> {
+        let layout = &self.layouts[self.class];
+        layout
+            .parent
+            .as_ref()
+            .and_then(|parent| self.layouts.iter().find(|l| l.name == *parent))
+            .and_then(|parent| parent.vtable.iter().find(|e| e.method == node.name()))
+            .and_then(|entry| {
+                self.method_index
+                    .get(&(entry.class.clone(), entry.method.clone()))
+            })
+            .map(|index| *index as u16)
+            .ok_or_else(|| Diagnostic::at(format!("No parent method `{}`", node.name()), node))
+    }
+
+    fn binary(&mut self, operator: &str, node: &ASTNode) -> Compiled<()> {
+        let op = match operator {
+            "+" => Op::Add,
+            "-" => Op::Subtract,
+            "*" => Op::Multiply,
+            "/" => Op::Divide,
+            "<" => Op::Less,
+            ">" => Op::Greater,
+            "<=" => Op::LessEqual,
+            ">=" => Op::GreaterEqual,
+            "==" => Op::Equal,
+            "!=" => Op::NotEqual,
+            _ => {
+                return Err(Diagnostic::at(
+                    format!("Cannot compile the operator `{}`", operator),
+                    node,
+                ))
+            }
+        };
+        self.emit(op, node);
+        Ok(())
+    }
+
+    fn assign(&mut self, node: &'a ASTNode) -> Compiled<()> {
+        let (target, value) = (&node.children[0], &node.children[1]);
+        let operator: Option<String> = node.operator.as_ref().map(|o| o.iter().collect());
+        let value = |compiler: &mut Compiler<'a>| -> Compiled<()> {
+            compiler.expression(value)?;
+            match &operator {
+                Some(operator) => compiler.binary(operator, node),
+                None => Ok(()),
+            }
+        };
+        match &target.production {
+            Some(Production::Ident) => {
+                if operator.is_some() {
+                    self.load(&target.name(), target)?;
+                }
+                value(self)?;
+                self.store(&target.name(), target)?;
+            }
+            Some(Production::Member) => {
+                let object = &target.children[0];
+                self.expression(object)?;
+                let field = self.own_field(object, &target.name());
+                if operator.is_some() {
+                    self.emit(Op::Dup, target);
+                    match field {
+                        Some(slot) => self.emit(Op::GetField(slot), target),
+                        None => {
+                            let name = self.word(&target.name());
+                            self.emit(Op::GetMember(name), target)
+                        }
+                    };
+                }
+                value(self)?;
+                match field {
+                    Some(slot) => self.emit(Op::SetField(slot), target),
+                    None => {
+                        let name = self.word(&target.name());
+                        self.emit(Op::SetMember(name), target)
+                    }
+                };
+            }
+            Some(Production::Index) => {
+                self.expression(&target.children[0])?;
+                self.expression(&target.children[1])?;
+                if operator.is_some() {
+                    self.emit(Op::Dup2, target);
+                    self.emit(Op::GetIndex, &target.children[1]);
+                }
+                value(self)?;
+                self.emit(Op::SetIndex, &target.children[1]);
+            }
+            _ => {
+                return Err(Diagnostic::at(
+                    String::from("Cannot assign to this"),
+                    target,
+                ))
+            }
+        }
+        Ok(())
+    }
+
+    /// Layout of the enum `node` names, if it does.
+    fn enum_named(&self, node: &ASTNode) -> Option<&EnumLayout> {
+        let path = node.path()?;
+        self.enums.iter().find(|e| e.name == path)
+    }
+
+    /// Constant naming the variant `name` of `layout`, and its tag.
+    fn variant(&mut self, layout: &EnumLayout, name: &str, at: &ASTNode) -> Compiled<(u16, u16)> {
+        let tag = layout
+            .variants
+            .iter()
+            .find(|v| v.name == name)
+            .map(|v| v.tag)
+            .ok_or_else(|| {
+                Diagnostic::at(format!("`{}` has no variant `{}`", layout.name, name), at)
+            })?;
+        let full = if layout.name == "Result" {
+            String::from(name)
+        } else {
+            format!("{}.{}", layout.name, name)
+        };
+        Ok((self.word(&full), tag as u16))
+    }
+
+    fn arguments(&mut self, arguments: &'a [ASTNode], at: &ASTNode) -> Compiled<u16> {
+        for argument in arguments {
+            self.expression(argument)?;
+        }
+        let place = if at.is(Production::ListLiteral) {
+            "one list"
+        } else {
+            "one call"
+        };
+        u16::try_from(arguments.len()).map_err(|_| too_many("values", place, at))
+    }
+
+    fn expression(&mut self, node: &'a ASTNode) -> Compiled<()> {
+        match &node.production {
+            Some(Production::NumberLiteral) => {
+                let number = node
+                    .name()
+                    .parse()
+                    .map_err(|_| Diagnostic::at(String::from("Number too large"), node))?;
+                let constant = self.constant(Constant::Number(number));
+                self.emit(Op::Constant(constant), node);
+            }
+            Some(Production::WordLiteral) => {
+                let constant = self.word(&node.name());
+                self.emit(Op::Constant(constant), node);
+            }
+            Some(Production::BooleanLiteral) => {
+                let op = if node.name() == "true" {
+                    Op::True
+                } else {
+                    Op::False
+                };
+                self.emit(op, node);
+            }
+            Some(Production::NullLiteral) => {
+                self.emit(Op::Null, node);
+            }
+            Some(Production::Ident) => self.load(&node.name(), node)?,
+            Some(Production::This) => self.load("this", node)?,
+            Some(Production::ListLiteral) => {
+                let count = self.arguments(&node.children, node)?;
+                self.emit(Op::MakeList(count), node);
+            }
+            Some(Production::MapLiteral) => {
+                for entry in &node.children {
+                    self.expression(&entry.children[0])?;
+                    self.expression(&entry.children[1])?;
+                }
+                let count = u16::try_from(node.children.len())
+                    .map_err(|_| too_many("entries", "one map", node))?;
+                self.emit(Op::MakeMap(count), node);
+            }
+            Some(Production::FunctionDec) => {
+                let function = self.function(String::from("function literal"), false, node)?;
+                let constant = self.constant(Constant::Function(Rc::new(function)));
+                self.emit(Op::Closure(constant), node);
+            }
+            Some(Production::New) => {
+                let class = self.class_index(&node.name()).ok_or_else(|| {
+                    Diagnostic::at(format!("Unknown class `{}`", node.name()), node)
+                })?;
+                let count = self.arguments(&node.children, node)?;
+                self.emit(Op::New(class as u16, count), node);
+                self.emit(Op::Init(count), node);
+            }
+            Some(Production::Unary) => {
+                self.expression(&node.children[0])?;
+                let op = match node.operator.as_deref() {
+                    Some(['!']) => Op::Not,
+                    _ => Op::Negate,
+                };
+                self.emit(op, node);
+            }
+            Some(Production::Expression) => {
+                let operator: String = node.operator.iter().flatten().collect();
+                self.expression(&node.children[0])?;
+                let jump = match operator.as_str() {
+                    "&&" => Op::JumpIfFalseKeep(0),
+                    "||" => Op::JumpIfTrueKeep(0),
+                    "??" => Op::JumpIfNotNullKeep(0),
+                    _ => {
+                        self.expression(&node.children[1])?;
+                        return self.binary(&operator, node);
+                    }
+                };
+                let jump = self.emit(jump, node);
+                self.expression(&node.children[1])?;
+                if operator != "??" {
+                    self.emit(Op::Condition, &node.children[1]);
+                }
+                self.patch(jump);
+            }
+            Some(Production::Member) => self.member(node)?,
+            Some(Production::Call) => self.call(node)?,
+            Some(Production::Index) => {
+                self.expression(&node.children[0])?;
+                self.expression(&node.children[1])?;
+                self.emit(Op::GetIndex, &node.children[1]);
+            }
+            Some(Production::Ok) | Some(Production::Err) => {
+                let name = if node.is(Production::Ok) { "Ok" } else { "Err" };
+                self.expression(&node.children[0])?;
+                let result = self
+                    .enums
+                    .iter()
+                    .find(|e| e.name == "Result")
+                    .cloned()
+                    .expect("`Result` is always laid out");
+                let (name, tag) = self.variant(&result, name, node)?;
+                self.emit(Op::MakeVariant(name, tag, 1), node);
+            }
+            Some(Production::Try) => {
+                self.expression(&node.children[0])?;
+                self.emit(Op::Try, node);
+            }
+            Some(Production::Match) => self.matching(node)?,
+            _ => return Err(Diagnostic::at(String::from("Cannot compile this"), node)),
+        }
+        Ok(())
+    }
+
+    /// `object.name` outside of a call.
+    fn member(&mut self, node: &'a ASTNode) -> Compiled<()> {
+        let object = &node.children[0];
+        if let Some(layout) = self.enum_named(object).cloned() {
+            let (name, tag) = self.variant(&layout, &node.name(), node)?;
+            self.emit(Op::MakeVariant(name, tag, 0), node);
+            return Ok(());
+        }
+        if object.is(Production::Super) {
+            let method = self.parent_method(node)?;
+            self.load("this", node)?;
+            self.emit(Op::Bind(method), node);
+            return Ok(());
+        }
+        self.expression(object)?;
+        if let Some(slot) = self.own_field(object, &node.name()) {
+            self.emit(Op::GetField(slot), node);
+            return Ok(());
+        }
+        let skip = node
+            .operator
+            .is_some()
+            .then(|| self.emit(Op::JumpIfNull(0), node));
+        let name = self.word(&node.name());
+        self.emit(Op::GetMember(name), node);
+        if let Some(skip) = skip {
+            self.patch(skip);
+        }
+        Ok(())
+    }
+
+    fn call(&mut self, node: &'a ASTNode) -> Compiled<()> {
+        let callee = &node.children[0];
+        let arguments = &node.children[1..];
+        if !callee.is(Production::Member) {
+            self.expression(callee)?;
+            let count = self.arguments(arguments, node)?;
+            self.emit(Op::Call(count), node);
+            return Ok(());
+        }
+        let object = &callee.children[0];
+        if let Some(layout) = self.enum_named(object).cloned() {
+            let (name, tag) = self.variant(&layout, &callee.name(), callee)?;
+            let count = self.arguments(arguments, node)?;
+            self.emit(Op::MakeVariant(name, tag, count), node);
+            return Ok(());
+        }
+        if object.is(Production::Super) {
+            let method = self.parent_method(callee)?;
+            self.load("this", callee)?;
+            let count = self.arguments(arguments, node)?;
+            self.emit(Op::InvokeStatic(method, count), node);
+            return Ok(());
+        }
+        self.expression(object)?;
+        if let Some(slot) = self.own_method(object, &callee.name()) {
+            let count = self.arguments(arguments, node)?;
+            self.emit(Op::InvokeVirtual(slot, count), node);
+            return Ok(());
+        }
+        let skip = callee
+            .operator
+            .is_some()
+            .then(|| self.emit(Op::JumpIfNull(0), callee));
+        let count = self.arguments(arguments, node)?;
+        let name = self.word(&callee.name());
+        self.emit(Op::Invoke(name, count), node);
+        if let Some(skip) = skip {
+            self.patch(skip);
+        }
+        Ok(())
+    }
+
+    /// Compiles a pattern, declaring the names it binds in the innermost
+    /// block.
+    fn pattern(&mut self, pattern: &ASTNode) -> Pattern {
+        match &pattern.production {
+            Some(Production::BindingPattern) => Pattern::Binding(self.declare(&pattern.name())),
+            Some(Production::NumberLiteral) => match pattern.name().parse() {
+                Ok(number) => Pattern::Number(number),
+                Err(_) => Pattern::Word(Rc::from(pattern.name())),
+            },
+            Some(Production::WordLiteral) => Pattern::Word(Rc::from(pattern.name())),
+            Some(Production::BooleanLiteral) => Pattern::Boolean(pattern.name() == "true"),
+            Some(Production::VariantPattern) => Pattern::Variant(
+                Rc::from(pattern.name()),
+                pattern.children.iter().map(|p| self.pattern(p)).collect(),
+            ),
+            _ => Pattern::Wildcard,
+        }
+    }
+
+    /// The unit only enum every arm of `node` matches a variant of, if
+    /// there is one and no arm binds the value.
+    fn jump_table_enum(&self, node: &ASTNode) -> Option<EnumLayout> {
+        let arms = &node.children[1..];
+        let variant = arms
+            .iter()
+            .map(|arm| &arm.children[0])
+            .find(|p| p.is(Production::VariantPattern))?;
+        let name = variant.name();
+        let enum_name = &name[..name.rfind('.')?];
+        let layout = self.enums.iter().find(|e| e.name == enum_name)?;
+        let fits = arms.iter().map(|arm| &arm.children[0]).all(|p| {
+            p.is(Production::WildcardPattern)
+                || p.is(Production::VariantPattern)
+                    && p.name().starts_with(enum_name)
+                    && p.name()[enum_name.len()..].starts_with('.')
+        });
+        (layout.unit_only && fits).then(|| layout.clone())
+    }
+
+    /// Compiles the body of a `match` arm, leaving its value.
+    fn arm_body(&mut self, body: &'a ASTNode) -> Compiled<()> {
+        if body.is(Production::BlockBody) {
+            self.block(body)?;
+            self.emit(Op::Nothing, body);
+            Ok(())
+        } else {
+            self.expression(body)
+        }
+    }
+
+    /// A `match` over a unit only enum jumps straight to the arm for the
+    /// tag of the value, other `match`es test the arms in turn.
+    fn matching(&mut self, node: &'a ASTNode) -> Compiled<()> {
+        self.expression(&node.children[0])?;
+        let mut ends = vec![];
+        if let Some(layout) = self.jump_table_enum(node) {
+            let table = self.state().function.tables.len() as u16;
+            self.state().function.tables.push(JumpTable {
+                variants: layout
+                    .variants
+                    .iter()
+                    .map(|v| Rc::from(format!("{}.{}", layout.name, v.name)))
+                    .collect(),
+                targets: vec![],
+                default: 0,
+            });
+            self.emit(Op::JumpTable(table), node);
+            let mut targets: Vec<Option<u32>> = vec![None; layout.variants.len()];
+            for arm in &node.children[1..] {
+                let start = self.here();
+                let pattern = &arm.children[0];
+                for (variant, target) in layout.variants.iter().zip(targets.iter_mut()) {
+                    let fits = pattern.is(Production::WildcardPattern)
+                        || pattern.name() == format!("{}.{}", layout.name, variant.name);
+                    if fits && target.is_none() {
+                        *target = Some(start);
+                    }
+                }
+                self.state().scopes.push(vec![]);
+                self.arm_body(&arm.children[1])?;
+                self.state().scopes.pop();
+                ends.push(self.emit(Op::Jump(0), arm));
+            }
+            let default = self.here();
+            let table = &mut self.state().function.tables[table as usize];
+            table.targets = targets.into_iter().map(|t| t.unwrap_or(default)).collect();
+            table.default = default;
+        } else {
+            for arm in &node.children[1..] {
+                self.state().scopes.push(vec![]);
+                let pattern = self.pattern(&arm.children[0]);
+                let patterns = &mut self.state().function.patterns;
+                patterns.push(pattern);
+                let pattern = patterns.len() as u16 - 1;
+                let next = self.emit(Op::Match(pattern, 0), &arm.children[0]);
+                self.arm_body(&arm.children[1])?;
+                self.state().scopes.pop();
+                ends.push(self.emit(Op::Jump(0), arm));
+                self.patch(next);
+            }
+        }
+        self.emit(Op::NoMatch, node);
+        for end in ends {
+            self.patch(end);
+        }
+        Ok(())
+    }
+}
+
+/// Compiles a program the checker accepted, whose sections have been
+/// flattened, snippets included and operators lowered, to bytecode that
+/// starts by calling `main` on a new instance of `Main`. Methods are laid
+/// out in vtables as `codegen::layouts` has them, and a `match` over a unit
+/// only enum becomes a jump table.
+pub fn compile(program: &ASTNode) -> Result<Program, Diagnostic> {
+    let classes: Vec<&ASTNode> = program
+        .children
+        .iter()
+        .filter(|d| d.is(Production::ClassDec))
+        .collect();
+    let layouts = codegen::layouts(program);
+    // `layouts` puts parents first, the compiler indexes them as declared
+    let layouts: Vec<codegen::ClassLayout> = classes
+        .iter()
+        .map(|c| {
+            layouts
+                .iter()
+                .find(|l| l.name == c.name())
+                .cloned()
+                .expect("every class is laid out")
+        })
+        .collect();
+    indexable(classes.len(), "classes", "the program", program)?;
+    for (class, layout) in classes.iter().zip(&layouts) {
+        let place = format!("`{}`", layout.name);
+        indexable(layout.fields.len(), "fields", &place, class)?;
+        indexable(layout.vtable.len(), "methods", &place, class)?;
+    }
+    let enums = codegen::enum_layouts(program);
+    for layout in &enums {
+        let declaration = program
+            .children
+            .iter()
+            .find(|d| d.is(Production::EnumDec) && d.name() == layout.name)
+            .unwrap_or(program);
+        let place = format!("`{}`", layout.name);
+        indexable(layout.variants.len(), "variants", &place, declaration)?;
+    }
+    let mut method_index = HashMap::new();
+    for class in &classes {
+        for method in class.members().iter().filter(|m| m.is_method()) {
+            let index = method_index.len();
+            method_index.insert((class.name(), method.name()), index);
+        }
+    }
+    indexable(method_index.len(), "methods", "the program", program)?;
+    let captures = resolver::captures(program)
+        .into_iter()
+        .map(|c| ((c.source, c.line_number, c.start_col), c.names))
+        .collect();
+    let mut compiler = Compiler {
+        classes: classes.clone(),
+        layouts,
+        enums,
+        method_index,
+        captures,
+        class: 0,
+        functions: vec![],
+    };
+    let mut methods = vec![];
+    for (index, class) in classes.iter().enumerate() {
+        compiler.class = index;
+        for method in class.members().iter().filter(|m| m.is_method()) {
+            let name = format!("{}.{}", class.name(), method.name());
+            let function = method.initializer().unwrap_or(method);
+            methods.push(Rc::new(compiler.function(name, true, function)?));
+        }
+    }
+    let mut compiled = vec![];
+    for (index, layout) in compiler.layouts.clone().into_iter().enumerate() {
+        compiler.class = index;
+        let initializer = compiler.initializer(index)?;
+        let vtable: Vec<usize> = layout
+            .vtable
+            .iter()
+            .map(|e| compiler.method_index[&(e.class.clone(), e.method.clone())])
+            .collect();
+        compiled.push(Rc::new(Class {
+            name: layout.name.clone(),
+            fields: layout
+                .fields
+                .iter()
+                .enumerate()
+                .map(|(slot, name)| (name.clone(), slot))
+                .collect(),
+            field_count: layout.fields.len(),
+            methods: layout
+                .vtable
+                .iter()
+                .enumerate()
+                .map(|(slot, e)| (e.method.clone(), slot))
+                .collect(),
+            vtable,
+            initializer,
+        }));
+    }
+    let main = compiler
+        .class_index("Main")
+        .filter(|main| compiled[*main].methods.contains_key("main"))
+        .ok_or_else(|| {
+            Diagnostic::error(
+                String::from("There is no `Main` class with a `main` method to run"),
+                program.line_number,
+                program.start_col,
+            )
+        })?;
+    let declaration = classes[main];
+    let mut entry = Function::new(String::from("<entry>"), false);
+    entry.hidden = true;
+    compiler.enter(entry, &[], vec![], declaration);
+    compiler.emit(Op::New(main as u16, 0), declaration);
+    compiler.emit(Op::Init(0), declaration);
+    let name = compiler.word("main");
+    compiler.emit(Op::Invoke(name, 0), declaration);
+    compiler.emit(Op::Return, declaration);
+    let entry = Rc::new(compiler.leave(declaration)?);
+    Ok(Program {
+        classes: compiled,
+        methods,
+        entry,
+    })
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let text = format!("{:?}", self);
+        match text.find('(') {
+            Some(open) => write!(
+                f,
+                "{:<18}{}",
+                &text[..open],
+                text[open + 1..text.len() - 1].replace(',', "")
+            ),
+            None => write!(f, "{}", text),
+        }
+    }
+}
+
+fn disassemble_function(function: &Function, text: &mut String) {
+    text.push_str(&format!(
+        "== {} ({} parameter(s), {} slot(s), {} cell(s)) ==\n",
+        function.name, function.arity, function.slots, function.cells
+    ));
+    let mut line = None;
+    for (offset, (op, at)) in function.code.iter().zip(&function.locations).enumerate() {
+        let shown = if line == Some(at.1) {
+            String::from("|")
+        } else {
+            at.1.to_string()
+        };
+        line = Some(at.1);
+        let note = match op {
+            Op::Constant(c) | Op::GetMember(c) | Op::SetMember(c) | Op::Invoke(c, _) => {
+                match &function.constants[*c as usize] {
+                    Constant::Number(number) => format!("  ; {}", number),
+                    Constant::Word(word) => format!("  ; {:?}", word),
+                    Constant::Function(f) => format!("  ; <{}>", f.name),
+                }
+            }
+            Op::MakeVariant(c, ..) => match &function.constants[*c as usize] {
+                Constant::Word(word) => format!("  ; {}", word),
+                _ => String::new(),
+            },
+            Op::Closure(c) => match &function.constants[*c as usize] {
+                Constant::Function(f) => format!("  ; captures {:?}", f.captures),
+                _ => String::new(),
+            },
+            Op::Match(p, _) => format!("  ; {:?}", function.patterns[*p as usize]),
+            Op::JumpTable(t) => {
+                let table = &function.tables[*t as usize];
+                format!("  ; {:?} else {}", table.targets, table.default)
+            }
+            _ => String::new(),
+        };
+        text.push_str(&format!("{:04} {:>4} {}{}\n", offset, shown, op, note));
+    }
+    for constant in &function.constants {
+        if let Constant::Function(nested) = constant {
+            text.push('\n');
+            disassemble_function(nested, text);
+        }
+    }
+}
+
+/// The bytecode of a program as text: every function with the offset and
+/// source line of each instruction, `|` standing for the line above.
+pub fn disassemble(program: &Program) -> String {
+    let mut text = String::new();
+    let mut functions: Vec<&Function> = vec![&program.entry];
+    functions.extend(
+        program
+            .classes
+            .iter()
+            .filter_map(|c| c.initializer.as_deref()),
+    );
+    functions.extend(program.methods.iter().map(|m| m.as_ref()));
+    for (index, function) in functions.into_iter().enumerate() {
+        if index > 0 {
+            text.push('\n');
+        }
+        disassemble_function(function, &mut text);
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_text;
+
+    fn compile_text(text: &str) -> Program {
+        compile(&parse_text(text)).unwrap()
+    }
+
+    fn method<'p>(program: &'p Program, name: &str) -> &'p Function {
+        program.methods.iter().find(|m| m.name == name).unwrap()
+    }
+
+    #[test]
+    fn instructions_are_compact() {
+        assert!(std::mem::size_of::<Op>() <= 8);
+    }
+
+    #[test]
+    fn methods_dispatch_through_vtables() {
+        let program = compile_text(
+            "class A {
+                let size: Number = 2;
+                let speak: Function = () ~Number { return this.size; }
+                let walk: Function = () ~Number { return this.speak(); }
+             }
+             class B extends A {
+                let speak: Function = () ~Number { return super.speak() + 1; }
+             }
+             class Main { let main: Function = () { print(new B().walk()); } }",
+        );
+        let b = &program.classes[1];
+        assert_eq!(b.fields["size"], 0);
+        assert_eq!(
+            method(&program, "A.walk").code[..2],
+            [Op::GetLocal(0), Op::InvokeVirtual(0, 0)]
+        );
+        let overridden = program.methods[b.vtable[0]].name.as_str();
+        assert_eq!(overridden, "B.speak");
+        let speak = method(&program, "B.speak");
+        assert_eq!(speak.code[..2], [Op::GetLocal(0), Op::InvokeStatic(0, 0)]);
+        assert_eq!(method(&program, "A.speak").code[1], Op::GetField(0));
+        // fields are set by the initialiser of each class, inherited ones too
+        assert!(b
+            .initializer
+            .as_ref()
+            .unwrap()
+            .code
+            .contains(&Op::SetField(0)));
+    }
+
+    #[test]
+    fn captured_locals_live_in_cells() {
+        let program = compile_text(
+            "class Main {
+                let make: Function = (start: Number) ~Function {
+                    let unused: Number = 0;
+                    return () ~Number { start += 1; return start; };
+                }
+                let main: Function = () { }
+             }",
+        );
+        let make = method(&program, "Main.make");
+        assert_eq!((make.slots, make.cells), (3, 1));
+        assert_eq!(make.code[..2], [Op::GetLocal(1), Op::NewCell(0)]);
+        let literal = make.constants.iter().find_map(|c| match c {
+            Constant::Function(f) => Some(f),
+            _ => None,
+        });
+        assert_eq!(literal.unwrap().captures, vec![Capture::Cell(0)]);
+        assert!(literal.unwrap().code.contains(&Op::SetUpvalue(0)));
+    }
+
+    #[test]
+    fn matches_over_unit_only_enums_use_jump_tables() {
+        let program = compile_text(
+            "enum Day { Mon, Tue, Wed }
+             enum Shape { Circle(Number), Empty }
+             class Main {
+                let weekend: Function = (day: Day) ~Boolean {
+                    return match (day) { Day.Tue => false, _ => true, Day.Mon => false };
+                }
+                let area: Function = (shape: Shape) ~Number {
+                    return match (shape) { Shape.Circle(r) => r * r, Shape.Empty => 0 };
+                }
+                let main: Function = () { }
+             }",
+        );
+        let weekend = method(&program, "Main.weekend");
+        assert_eq!(weekend.code[1], Op::JumpTable(0));
+        let table = &weekend.tables[0];
+        // `Mon` goes to the wildcard, the first arm that fits it
+        assert_eq!(table.targets, vec![4, 2, 4]);
+        assert_eq!(weekend.code[table.default as usize], Op::NoMatch);
+        let area = method(&program, "Main.area");
+        assert!(area.tables.is_empty());
+        assert_eq!(area.patterns.len(), 2);
+    }
+
+    #[test]
+    fn lists_too_long_for_an_operand_are_an_error() {
+        let numbers = vec!["1"; 70_000].join(", ");
+        let text = format!(
+            "class Main {{
+                let main: Function = () {{
+                    let xs: List<Number> = [{}];
+                }}
+             }}",
+            numbers
+        );
+        let error = compile(&parse_text(&text)).unwrap_err();
+        assert_eq!(
+            error.message,
+            "Too many values in one list for the virtual machine"
+        );
+        assert_eq!(error.line_number, 3);
+    }
+
+    #[test]
+    fn disassembly() {
+        let program = compile_text(
+            "class Main {
+                let main: Function = () {
+                    let word: Word = \"hi\";
+                    print(word);
+                }
+             }",
+        );
+        let text = disassemble(&program);
+        assert!(text.contains("== Main.main (0 parameter(s), 2 slot(s), 0 cell(s)) ==\n"));
+        assert!(text
+            .contains("0000    3 Constant          0  ; \"hi\"\n0001    | SetLocal          1\n"));
+    }
+}
@@ -0,0 +1,444 @@
+use super::diagnostic::Diagnostic;
+use super::parser::ASTNode;
+use super::parser::Production;
+use super::types::Type;
+
+/// How control leaves a basic block.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Terminator<'a> {
+    /// On to the block at this index.
+    Goto(usize),
+    /// An `if`: on to the first block when its condition holds and to the
+    /// second when it does not.
+    Branch(&'a ASTNode, usize, usize),
+    /// The head of a `for`: on to the loop body while there are elements
+    /// left, then to the block after the loop.
+    Iterate(&'a ASTNode, usize, usize),
+    /// A `match` statement: on to the block of one of its arms, in order.
+    /// Matches are exhaustive, so there is no way past them.
+    Match(&'a ASTNode, Vec<usize>),
+    /// A `return` statement, leaving the function.
+    Return(&'a ASTNode),
+    /// The end of the function body, reached without a `return`.
+    End,
+}
+
+impl<'a> Terminator<'a> {
+    /// Blocks control can go to next.
+    pub fn successors(&self) -> Vec<usize> {
+        match self {
+            Terminator::Goto(next) => vec![*next],
+            Terminator::Branch(_, then, otherwise) => vec![*then, *otherwise],
+            Terminator::Iterate(_, body, done) => vec![*body, *done],
+            Terminator::Match(_, arms) => arms.clone(),
+            Terminator::Return(_) | Terminator::End => vec![],
+        }
+    }
+
+    /// Statement the terminator was built from, if any.
+    pub fn node(&self) -> Option<&'a ASTNode> {
+        match self {
+            Terminator::Branch(node, ..)
+            | Terminator::Iterate(node, ..)
+            | Terminator::Match(node, _)
+            | Terminator::Return(node) => Some(node),
+            Terminator::Goto(_) | Terminator::End => None,
+        }
+    }
+}
+
+/// Statements that always run one after another, and how control leaves
+/// them.
+#[derive(Debug, PartialEq, Clone)]
+pub struct BasicBlock<'a> {
+    pub statements: Vec<&'a ASTNode>,
+    pub terminator: Terminator<'a>,
+}
+
+/// Control flow graph of the body of one function. Function literals
+/// nested in it get graphs of their own.
+///
+/// Block 0 is the entry. Statements following a `return`, `break` or
+/// `continue` start a block nothing leads to, which is how unreachable code
+/// shows up; such blocks are kept so that every statement of the body is in
+/// the graph.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Cfg<'a> {
+    pub blocks: Vec<BasicBlock<'a>>,
+}
+
+/// Loop being built: the block of its head and the block after it.
+struct Loop {
+    head: usize,
+    done: usize,
+}
+
+struct Builder<'a> {
+    blocks: Vec<BasicBlock<'a>>,
+    loops: Vec<Loop>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Builder<'a> {
+    /// Adds an empty block, ending the function until it is terminated
+    /// otherwise.
+    fn block(&mut self) -> usize {
+        self.blocks.push(BasicBlock {
+            statements: vec![],
+            terminator: Terminator::End,
+        });
+        self.blocks.len() - 1
+    }
+
+    /// Ends `block` with `terminator` and returns the block the statements
+    /// after it go into.
+    fn terminate(&mut self, block: usize, terminator: Terminator<'a>) -> usize {
+        self.blocks[block].terminator = terminator;
+        self.block()
+    }
+
+    /// Adds the statements of `body` to the graph starting at `block`,
+    /// returning the block control reaches after them.
+    fn statements(&mut self, body: &'a [ASTNode], mut block: usize) -> usize {
+        for statement in body {
+            block = self.statement(statement, block);
+        }
+        block
+    }
+
+    fn statement(&mut self, statement: &'a ASTNode, block: usize) -> usize {
+        match &statement.production {
+            Some(Production::If) => {
+                let then = self.block();
+                let otherwise = self.block();
+                self.blocks[block].terminator = Terminator::Branch(statement, then, otherwise);
+                let then_end = self.statements(&statement.children[1].children, then);
+                let otherwise_end = match statement.children.get(2) {
+                    Some(chained) if chained.is(Production::If) => {
+                        self.statement(chained, otherwise)
+                    }
+                    Some(block) => self.statements(&block.children, otherwise),
+                    None => otherwise,
+                };
+                let after = self.block();
+                self.blocks[then_end].terminator = Terminator::Goto(after);
+                self.blocks[otherwise_end].terminator = Terminator::Goto(after);
+                after
+            }
+            Some(Production::For) => {
+                let head = self.block();
+                self.blocks[block].terminator = Terminator::Goto(head);
+                let body = self.block();
+                let done = self.block();
+                self.blocks[head].terminator = Terminator::Iterate(statement, body, done);
+                self.loops.push(Loop { head, done });
+                let body_end = self.statements(&statement.children[1].children, body);
+                self.loops.pop();
+                self.blocks[body_end].terminator = Terminator::Goto(head);
+                done
+            }
+            Some(Production::Match) => {
+                let after = self.block();
+                let mut arms = vec![];
+                for arm in &statement.children[1..] {
+                    let start = self.block();
+                    arms.push(start);
+                    let body = &arm.children[1];
+                    let end = if body.is(Production::BlockBody) {
+                        self.statements(&body.children, start)
+                    } else {
+                        self.blocks[start].statements.push(body);
+                        start
+                    };
+                    self.blocks[end].terminator = Terminator::Goto(after);
+                }
+                self.blocks[block].terminator = Terminator::Match(statement, arms);
+                after
+            }
+            Some(Production::Return) => self.terminate(block, Terminator::Return(statement)),
+            Some(Production::Break) | Some(Production::Continue) => {
+                let target = self.loops.last().map(|l| {
+                    if statement.is(Production::Break) {
+                        l.done
+                    } else {
+                        l.head
+                    }
+                });
+                match target {
+                    Some(target) => self.terminate(block, Terminator::Goto(target)),
+                    None => {
+                        let keyword = if statement.is(Production::Break) {
+                            "break"
+                        } else {
+                            "continue"
+                        };
+                        self.diagnostics.push(Diagnostic::at(
+                            format!("`{}` can only be used inside a `for` loop", keyword),
+                            statement,
+                        ));
+                        block
+                    }
+                }
+            }
+            _ => {
+                self.blocks[block].statements.push(statement);
+                block
+            }
+        }
+    }
+}
+
+impl<'a> Cfg<'a> {
+    /// Builds the graph of a `FunctionDec`, along with errors for `break`
+    /// and `continue` outside loops.
+    pub fn build(function: &'a ASTNode) -> (Cfg<'a>, Vec<Diagnostic>) {
+        let mut builder = Builder {
+            blocks: vec![],
+            loops: vec![],
+            diagnostics: vec![],
+        };
+        let entry = builder.block();
+        if let Some(body) = function.find(Production::BlockBody) {
+            builder.statements(&body.children, entry);
+        }
+        let cfg = Cfg {
+            blocks: builder.blocks,
+        };
+        (cfg, builder.diagnostics)
+    }
+
+    /// Blocks before each block, by index.
+    pub fn predecessors(&self) -> Vec<Vec<usize>> {
+        let mut predecessors = vec![vec![]; self.blocks.len()];
+        for (i, block) in self.blocks.iter().enumerate() {
+            for successor in block.terminator.successors() {
+                predecessors[successor].push(i);
+            }
+        }
+        predecessors
+    }
+
+    /// Whether control can get to each block from the entry.
+    pub fn reachable(&self) -> Vec<bool> {
+        let mut reached = vec![false; self.blocks.len()];
+        let mut pending = vec![0];
+        while let Some(block) = pending.pop() {
+            if reached[block] {
+                continue;
+            }
+            reached[block] = true;
+            pending.extend(self.blocks[block].terminator.successors());
+        }
+        reached
+    }
+
+    /// First statement of a block, whether a plain one or the one its
+    /// terminator was built from.
+    fn first_statement(&self, block: usize) -> Option<&'a ASTNode> {
+        let block = &self.blocks[block];
+        block
+            .statements
+            .first()
+            .copied()
+            .or_else(|| block.terminator.node())
+    }
+}
+
+/// Errors when a function with a return type can reach the end of its body
+/// and warnings for code that can never run, for `function` and the
+/// function literals below it.
+fn check_function(function: &ASTNode, diagnostics: &mut Vec<Diagnostic>) {
+    let (cfg, found) = Cfg::build(function);
+    diagnostics.extend(found);
+    let reachable = cfg.reachable();
+    let predecessors = cfg.predecessors();
+    // unreachable blocks holding code or coming after such a block; only
+    // the first of a run of unreachable statements is reported
+    let mut covered = vec![false; cfg.blocks.len()];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for i in 0..cfg.blocks.len() {
+            let dead = !reachable[i]
+                && (cfg.first_statement(i).is_some()
+                    || predecessors[i].iter().any(|&p| covered[p]));
+            if dead && !covered[i] {
+                covered[i] = true;
+                changed = true;
+            }
+        }
+    }
+    for i in 0..cfg.blocks.len() {
+        if reachable[i] || predecessors[i].iter().any(|&p| covered[p]) {
+            continue;
+        }
+        if let Some(statement) = cfg.first_statement(i) {
+            diagnostics.push(Diagnostic::warning_at(
+                String::from("Unreachable code"),
+                statement,
+            ));
+        }
+    }
+    let returns = function
+        .find(Production::TypeDec)
+        .map(|t| Type::from_type_dec(Some(t)));
+    let falls_off = cfg
+        .blocks
+        .iter()
+        .enumerate()
+        .any(|(i, block)| reachable[i] && block.terminator == Terminator::End);
+    if let Some(returns) = returns.filter(|_| falls_off) {
+        diagnostics.push(Diagnostic::at(
+            format!(
+                "This function returns `{}` but can reach its end without a `return`",
+                returns
+            ),
+            function,
+        ));
+    }
+}
+
+fn check_functions(node: &ASTNode, diagnostics: &mut Vec<Diagnostic>) {
+    if node.is(Production::FunctionDec) {
+        check_function(node, diagnostics);
+    }
+    for child in &node.children {
+        check_functions(child, diagnostics);
+    }
+}
+
+/// Checks the control flow of every function of a program, see
+/// `check_function`.
+pub fn check(program: &ASTNode) -> Vec<Diagnostic> {
+    let mut diagnostics = vec![];
+    check_functions(program, &mut diagnostics);
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_text;
+
+    fn parse(text: &str) -> ASTNode {
+        parse_text(text)
+    }
+
+    fn diagnostics(text: &str) -> Vec<(String, i32)> {
+        check(&parse(text))
+            .into_iter()
+            .map(|d| {
+                let severity = if d.is_error() { "error" } else { "warning" };
+                (format!("{}: {}", severity, d.message), d.line_number)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn graph_of_if_and_for() {
+        let program = parse(
+            "class A { let f: Function = (xs: List<Number>) ~Number {
+                for (x in xs) {
+                    if (x > 1) { break; }
+                    print(x);
+                }
+                return 1;
+             } }",
+        );
+        let function = program.children[0].members()[0].initializer().unwrap();
+        let (cfg, found) = Cfg::build(function);
+        assert!(found.is_empty());
+        let successors: Vec<Vec<usize>> = cfg
+            .blocks
+            .iter()
+            .map(|b| b.terminator.successors())
+            .collect();
+        assert_eq!(
+            successors,
+            vec![
+                vec![1],
+                vec![2, 3],
+                vec![4, 5],
+                vec![],
+                vec![3],
+                vec![7],
+                vec![7],
+                vec![1],
+                // what would follow the `return`
+                vec![],
+            ]
+        );
+        assert_eq!(
+            cfg.blocks[7].statements[0].production,
+            Some(Production::Print)
+        );
+        assert!(matches!(cfg.blocks[3].terminator, Terminator::Return(_)));
+        assert_eq!(
+            cfg.reachable(),
+            vec![true, true, true, true, true, true, false, true, false]
+        );
+    }
+
+    #[test]
+    fn missing_returns() {
+        let found = diagnostics(
+            "class A {
+                let sign: Function = (n: Number) ~Number {
+                    if (n < 0) { return -1; }
+                }
+                let pick: Function = (flag: Boolean) ~Word {
+                    if (flag) { return \"yes\"; } else { return \"no\"; }
+                }
+                let loop: Function = (xs: List<Number>) ~Number {
+                    for (x in xs) { return x; }
+                }
+                let nothing: Function = () { }
+             }",
+        );
+        assert_eq!(
+            found,
+            vec![
+                (
+                    String::from(
+                        "error: This function returns `Number` but can reach its end without a `return`"
+                    ),
+                    2
+                ),
+                (
+                    String::from(
+                        "error: This function returns `Number` but can reach its end without a `return`"
+                    ),
+                    8
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn unreachable_code() {
+        let found = diagnostics(
+            "class A {
+                let f: Function = (xs: List<Number>, flag: Boolean) ~Number {
+                    for (x in xs) {
+                        continue;
+                        print(x);
+                        print(x);
+                    }
+                    if (flag) { return 1; } else { return 2; }
+                    if (flag) { print(1); }
+                    return 3;
+                }
+                let g: Function = () { break; }
+             }",
+        );
+        let found: Vec<(&str, i32)> = found.iter().map(|(m, l)| (m.as_str(), *l)).collect();
+        assert_eq!(
+            found,
+            vec![
+                ("warning: Unreachable code", 5),
+                ("warning: Unreachable code", 9),
+                ("error: `break` can only be used inside a `for` loop", 12),
+            ]
+        );
+    }
+}
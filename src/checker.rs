@@ -0,0 +1,2612 @@
+use super::diagnostic::Diagnostic;
+use super::parser::ASTNode;
+use super::parser::Location;
+use super::parser::Production;
+use super::types::Type;
+use std::collections::HashMap;
+use std::fmt;
+
+/// Function literal a method is initialised with, or the method signature
+/// itself.
+fn function_of(member: &ASTNode) -> Option<&ASTNode> {
+    if member.is(Production::MethodSig) {
+        Some(member)
+    } else {
+        member.initializer()
+    }
+}
+
+/// Parameter types of a function literal or method signature.
+fn param_types(function: &ASTNode) -> Vec<Type> {
+    function
+        .find(Production::Params)
+        .map(|params| {
+            params
+                .children
+                .iter()
+                .map(|p| Type::from_type_dec(p.find(Production::TypeDec)))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Type parameters of a generic class or function with their bounds.
+fn type_params(node: &ASTNode) -> Vec<(String, Option<Type>)> {
+    node.find(Production::TypeParams)
+        .map(|params| {
+            params
+                .children
+                .iter()
+                .map(|p| {
+                    let bound = p.children.first().map(|b| Type::from_type_dec(Some(b)));
+                    (p.name(), bound)
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Binds the type parameters in `vars` by matching the type a parameter
+/// was declared with against the type of the argument passed for it. The
+/// first argument to mention a parameter decides its type.
+fn infer(param: &Type, argument: &Type, vars: &[String], bindings: &mut HashMap<String, Type>) {
+    match (param, argument) {
+        (Type::Named(name, args), _)
+            if args.is_empty() && vars.contains(name) && *argument != Type::Null =>
+        {
+            bindings
+                .entry(name.clone())
+                .or_insert_with(|| argument.clone());
+        }
+        (Type::List(param), Type::List(argument)) => infer(param, argument, vars, bindings),
+        (Type::Map(key, value), Type::Map(argument_key, argument_value)) => {
+            infer(key, argument_key, vars, bindings);
+            infer(value, argument_value, vars, bindings);
+        }
+        (Type::Option(param), Type::Option(argument)) => infer(param, argument, vars, bindings),
+        (Type::Option(param), argument) => infer(param, argument, vars, bindings),
+        (Type::Result(value, error), Type::Result(argument_value, argument_error)) => {
+            infer(value, argument_value, vars, bindings);
+            infer(error, argument_error, vars, bindings);
+        }
+        (Type::Named(name, params), Type::Named(argument_name, arguments))
+            if name == argument_name =>
+        {
+            for (param, argument) in params.iter().zip(arguments) {
+                infer(param, argument, vars, bindings);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Whether `found` mentions one of the type parameters in `vars`.
+fn mentions(found: &Type, vars: &[String]) -> bool {
+    match found {
+        Type::Named(name, args) => {
+            (args.is_empty() && vars.contains(name)) || args.iter().any(|a| mentions(a, vars))
+        }
+        Type::List(element) | Type::Option(element) => mentions(element, vars),
+        Type::Map(key, value) | Type::Result(key, value) => {
+            mentions(key, vars) || mentions(value, vars)
+        }
+        _ => false,
+    }
+}
+
+/// What using a member of a value gives.
+#[derive(Debug, Clone)]
+struct MemberType<'a> {
+    /// Parameter types when the member is a method.
+    params: Option<Vec<Type>>,
+    /// Type of the field, or of what the method returns.
+    returns: Type,
+    /// Type parameters of a generic method with their bounds.
+    type_params: Vec<(String, Option<Type>)>,
+    /// Declaration of a `const` field, which cannot be assigned to.
+    constant: Option<&'a ASTNode>,
+}
+
+fn describe((params, returns): &(Vec<Type>, Type)) -> String {
+    Type::FunctionOf(params.clone(), Box::new(returns.clone())).to_string()
+}
+
+/// A type the checker inferred for a binding or function literal written
+/// without one, located where it is declared so that editors can show it
+/// on hover.
+#[derive(Debug, PartialEq, Clone)]
+pub struct InferredType {
+    pub source: usize,
+    pub line_number: i32,
+    pub start_col: usize,
+    pub inferred: Type,
+}
+
+/// Method that gives an operator its meaning for instances of a class,
+/// whether `a op b` calls it on `b` rather than `a`, and whether the result
+/// is negated: `a > b` is `b.lessThan(a)` and `a != b` is `!a.equals(b)`.
+fn operator_method(operator: &str) -> Option<(&'static str, bool, bool)> {
+    match operator {
+        "+" => Some(("add", false, false)),
+        "-" => Some(("subtract", false, false)),
+        "*" => Some(("multiply", false, false)),
+        "/" => Some(("divide", false, false)),
+        "==" => Some(("equals", false, false)),
+        "!=" => Some(("equals", false, true)),
+        "<" => Some(("lessThan", false, false)),
+        ">" => Some(("lessThan", true, false)),
+        "<=" => Some(("lessThan", true, true)),
+        ">=" => Some(("lessThan", false, true)),
+        _ => None,
+    }
+}
+
+/// An operator applied to instances of a class that defines it, to be
+/// lowered to a call of `method`. Found by where its operands were parsed;
+/// for a compound assignment these are the target and the value.
+#[derive(Debug, PartialEq, Clone)]
+pub struct OperatorCall {
+    pub lhs: Location,
+    pub rhs: Location,
+    pub method: String,
+    /// The method is called on the right operand with the left one.
+    pub swapped: bool,
+    /// The result of the method is negated.
+    pub negated: bool,
+}
+
+/// What a pattern tests the matched value for.
+#[derive(Debug, PartialEq, Clone)]
+enum Constructor {
+    /// Variant of the enum named first.
+    Variant(String, String),
+    Boolean(bool),
+    /// Number or word literal, by its type and spelling. There are too many
+    /// of these to ever list them all, so only a catch-all covers them.
+    Literal(Type, String),
+    /// `Ok` when true, `Err` otherwise.
+    Result(bool),
+}
+
+impl Constructor {
+    /// Type of the values the constructor tests, `None` for results whose
+    /// value and error types the pattern does not tell.
+    fn type_of(&self) -> Option<Type> {
+        match self {
+            Constructor::Variant(name, _) => Some(Type::Named(name.clone(), vec![])),
+            Constructor::Boolean(_) => Some(Type::Boolean),
+            Constructor::Literal(literal_type, _) => Some(literal_type.clone()),
+            Constructor::Result(_) => None,
+        }
+    }
+}
+
+/// A pattern reduced to what matters for exhaustiveness: bindings are
+/// wildcards that happen to name the value.
+#[derive(Debug, PartialEq, Clone)]
+enum Pattern {
+    Wildcard,
+    Constructor(Constructor, Vec<Pattern>),
+}
+
+impl Pattern {
+    fn from(node: &ASTNode) -> Pattern {
+        let literal = |literal_type: Type| {
+            Pattern::Constructor(Constructor::Literal(literal_type, node.name()), vec![])
+        };
+        match &node.production {
+            Some(Production::NumberLiteral) => literal(Type::Number),
+            Some(Production::WordLiteral) => literal(Type::Word),
+            Some(Production::BooleanLiteral) => {
+                Pattern::Constructor(Constructor::Boolean(node.name() == "true"), vec![])
+            }
+            Some(Production::VariantPattern) if node.name() == "Ok" || node.name() == "Err" => {
+                Pattern::Constructor(
+                    Constructor::Result(node.name() == "Ok"),
+                    node.children.iter().map(Pattern::from).collect(),
+                )
+            }
+            Some(Production::VariantPattern) => {
+                let name = node.name();
+                let dot = name.rfind('.').unwrap_or(0);
+                let variant = Constructor::Variant(
+                    String::from(&name[..dot]),
+                    String::from(name[dot..].trim_start_matches('.')),
+                );
+                Pattern::Constructor(variant, node.children.iter().map(Pattern::from).collect())
+            }
+            _ => Pattern::Wildcard,
+        }
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Pattern::Wildcard => write!(f, "_"),
+            Pattern::Constructor(Constructor::Boolean(value), _) => write!(f, "{}", value),
+            Pattern::Constructor(Constructor::Literal(Type::Word, value), _) => {
+                write!(f, "\"{}\"", value)
+            }
+            Pattern::Constructor(Constructor::Literal(_, value), _) => write!(f, "{}", value),
+            Pattern::Constructor(Constructor::Result(ok), fields) => {
+                let field = fields.first().map(|p| p.to_string());
+                let name = if *ok { "Ok" } else { "Err" };
+                write!(f, "{}({})", name, field.as_deref().unwrap_or("_"))
+            }
+            Pattern::Constructor(Constructor::Variant(name, variant), fields) => {
+                write!(f, "{}.{}", name, variant)?;
+                if !fields.is_empty() {
+                    let fields: Vec<String> = fields.iter().map(|p| p.to_string()).collect();
+                    write!(f, "({})", fields.join(", "))?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Rows of a pattern matrix starting with `constructor`, its fields taking
+/// the place of the first column. Rows starting with a wildcard match any
+/// fields.
+fn specialize(rows: &[Vec<Pattern>], constructor: &Constructor, arity: usize) -> Vec<Vec<Pattern>> {
+    rows.iter()
+        .filter_map(|row| {
+            let mut specialized = match &row[0] {
+                Pattern::Wildcard => vec![Pattern::Wildcard; arity],
+                Pattern::Constructor(c, fields) if c == constructor => fields.clone(),
+                Pattern::Constructor(..) => return None,
+            };
+            specialized.extend_from_slice(&row[1..]);
+            Some(specialized)
+        })
+        .collect()
+}
+
+/// Rows of a pattern matrix that match any value in the first column,
+/// without that column.
+fn default_rows(rows: &[Vec<Pattern>]) -> Vec<Vec<Pattern>> {
+    rows.iter()
+        .filter(|row| row[0] == Pattern::Wildcard)
+        .map(|row| row[1..].to_vec())
+        .collect()
+}
+
+fn head_constructors(rows: &[Vec<Pattern>]) -> Vec<Constructor> {
+    let mut heads = vec![];
+    for row in rows {
+        if let Pattern::Constructor(c, _) = &row[0] {
+            if !heads.contains(c) {
+                heads.push(c.clone());
+            }
+        }
+    }
+    heads
+}
+
+/// A variable visible in a method body.
+#[derive(Debug, Clone)]
+struct Local<'a> {
+    /// Type it was declared with or inferred to have, `None` when it could
+    /// not be inferred.
+    declared: Option<Type>,
+    /// Narrower type a null check proved, which holds until the variable is
+    /// assigned.
+    narrowed: Option<Type>,
+    /// Declaration of a `const`, which cannot be assigned to.
+    constant: Option<&'a ASTNode>,
+}
+
+/// Type checks a lowered program, i.e. one with sections flattened and
+/// snippets included, whose names the resolver accepted.
+pub struct Checker<'a> {
+    classes: HashMap<String, &'a ASTNode>,
+    interfaces: HashMap<String, &'a ASTNode>,
+    enums: HashMap<String, &'a ASTNode>,
+    /// Class whose members are being checked.
+    class: Option<&'a ASTNode>,
+    /// Variables visible at the current point of a method body, innermost
+    /// scope last.
+    locals: Vec<HashMap<String, Local<'a>>>,
+    /// Whether the member being checked is the constructor, which may set
+    /// `const` fields declared without a value.
+    constructor: bool,
+    /// Return type of the function whose body is being checked.
+    returns: Type,
+    /// Types returned so far by the function being checked when its return
+    /// type is inferred, `None` for those that cannot be told.
+    returned: Option<Vec<Option<Type>>>,
+    /// Types of variables declared without one and function types of
+    /// literals, by the location of their declaration.
+    inferred: HashMap<Location, Type>,
+    /// Operators resolved to methods of their operands.
+    operator_calls: Vec<OperatorCall>,
+    /// Interfaces bounding the type parameters in scope.
+    bounds: HashMap<String, Type>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// Variables known not to be null on one side of a condition, with their
+/// narrowed types.
+type Facts = Vec<(String, Type)>;
+
+impl<'a> Checker<'a> {
+    pub fn new(program: &'a ASTNode) -> Checker<'a> {
+        let mut classes = HashMap::new();
+        let mut interfaces = HashMap::new();
+        let mut enums = HashMap::new();
+        for declaration in &program.children {
+            if declaration.is(Production::ClassDec) {
+                classes.insert(declaration.name(), declaration);
+            } else if declaration.is(Production::InterfaceDec) {
+                interfaces.insert(declaration.name(), declaration);
+            } else if declaration.is(Production::EnumDec) {
+                enums.insert(declaration.name(), declaration);
+            }
+        }
+        Checker {
+            classes,
+            interfaces,
+            enums,
+            class: None,
+            locals: vec![],
+            constructor: false,
+            returns: Type::Void,
+            returned: None,
+            inferred: HashMap::new(),
+            operator_calls: vec![],
+            bounds: HashMap::new(),
+            diagnostics: vec![],
+        }
+    }
+
+    fn parent(&self, class: &ASTNode) -> Option<&'a ASTNode> {
+        let extends = class.find(Production::Extends)?;
+        self.classes.get(&extends.name()).copied()
+    }
+
+    /// `class` and its ancestors, nearest first.
+    fn lineage(&self, class: &'a ASTNode) -> Vec<&'a ASTNode> {
+        let mut lineage = vec![class];
+        while let Some(parent) = self.parent(lineage[lineage.len() - 1]) {
+            if lineage.iter().any(|c| std::ptr::eq(*c, parent)) {
+                break;
+            }
+            lineage.push(parent);
+        }
+        lineage
+    }
+
+    /// Member `name` of a class, looking through its ancestors too.
+    fn find_member(&self, class: &'a ASTNode, name: &str) -> Option<&'a ASTNode> {
+        self.lineage(class)
+            .into_iter()
+            .find_map(|c| c.members().iter().find(|m| m.name() == name))
+    }
+
+    /// Whether the class called `class` or one of its ancestors declares that
+    /// it implements `interface`.
+    fn implements(&self, class: &str, interface: &str) -> bool {
+        let class = match self.classes.get(class) {
+            Some(class) => *class,
+            None => return false,
+        };
+        self.lineage(class).iter().any(|c| {
+            c.children
+                .iter()
+                .any(|i| i.is(Production::Implements) && i.name() == interface)
+        })
+    }
+
+    /// Whether a value of type `from` can be used where `to` is expected:
+    /// instances of a class are also instances of its ancestors and of the
+    /// interfaces they implement, and values of a bounded type parameter
+    /// are instances of its bound. Type arguments have to match exactly.
+    pub fn is_assignable(&self, from: &Type, to: &Type) -> bool {
+        if from == to {
+            return true;
+        }
+        match (from, to) {
+            (Type::Null, Type::Option(_)) => true,
+            (Type::Option(from), Type::Option(to)) => self.is_assignable(from, to),
+            (from, Type::Option(to)) => self.is_assignable(from, to),
+            (Type::Named(from, args), to) if args.is_empty() && self.bounds.contains_key(from) => {
+                self.is_assignable(&self.bounds[from].clone(), to)
+            }
+            // `Function` values may be any function
+            (Type::FunctionOf(..), Type::Function) | (Type::Function, Type::FunctionOf(..)) => true,
+            (Type::Named(from, _), Type::Named(to, _)) => {
+                if from == to {
+                    return false;
+                }
+                if self.interfaces.contains_key(to) {
+                    return self.implements(from, to);
+                }
+                match self.classes.get(from) {
+                    Some(class) => self.lineage(class).iter().any(|c| c.name() == *to),
+                    None => false,
+                }
+            }
+            _ => false,
+        }
+    }
+
+    /// Checks that a class has a method matching every signature of the
+    /// interfaces it implements. Parameter types have to match exactly; the
+    /// return type may be more specific than the required one.
+    fn check_conformance(&mut self, class: &'a ASTNode) {
+        for implements in class
+            .children
+            .iter()
+            .filter(|c| c.is(Production::Implements))
+        {
+            let interface = match self.interfaces.get(&implements.name()) {
+                Some(interface) => *interface,
+                None => continue,
+            };
+            for required in interface.members() {
+                let method = match self.find_member(class, &required.name()) {
+                    Some(method) => method,
+                    None => {
+                        self.diagnostics.push(
+                            Diagnostic::at(
+                                format!(
+                                    "`{}` does not implement `{}` required by interface `{}`",
+                                    class.name(),
+                                    required.name(),
+                                    interface.name()
+                                ),
+                                implements,
+                            )
+                            .with_note(String::from("required here"), required),
+                        );
+                        continue;
+                    }
+                };
+                if !method.is_method() {
+                    self.diagnostics.push(
+                        Diagnostic::at(
+                            format!(
+                                "`{}.{}` has to be a method to implement `{}.{}`",
+                                class.name(),
+                                method.name(),
+                                interface.name(),
+                                required.name()
+                            ),
+                            method,
+                        )
+                        .with_note(String::from("required here"), required),
+                    );
+                    continue;
+                }
+                let (expected, found) = match (self.signature(required), self.signature(method)) {
+                    (Some(expected), Some(found)) => (expected, found),
+                    _ => continue,
+                };
+                let compatible = expected.0 == found.0 && self.is_assignable(&found.1, &expected.1);
+                if !compatible {
+                    self.diagnostics.push(
+                        Diagnostic::at(
+                            format!(
+                                "`{}.{}` does not match `{}.{}`: expected {}, found {}",
+                                class.name(),
+                                method.name(),
+                                interface.name(),
+                                required.name(),
+                                describe(&expected),
+                                describe(&found)
+                            ),
+                            method,
+                        )
+                        .with_note(String::from("required here"), required),
+                    );
+                }
+            }
+        }
+    }
+
+    /// Checks that a method overriding an inherited one can be called
+    /// wherever the overridden one can: each parameter accepts what the
+    /// overridden one does and the return type fits the overridden one.
+    /// The resolver reports overrides taking a different number of
+    /// parameters, and generic methods and classes are not compared.
+    fn check_overrides(&mut self, class: &'a ASTNode) {
+        let parent = match self.parent(class) {
+            Some(parent) => parent,
+            None => return,
+        };
+        for method in class.members() {
+            if !method.is_method() || method.name() == "init" {
+                continue;
+            }
+            let overridden = match self.find_member(parent, &method.name()) {
+                Some(overridden) if overridden.is_method() => overridden,
+                _ => continue,
+            };
+            let owner = match self
+                .lineage(parent)
+                .into_iter()
+                .find(|c| c.members().iter().any(|m| std::ptr::eq(m, overridden)))
+            {
+                Some(owner) => owner,
+                None => continue,
+            };
+            let generic = [owner, overridden, method]
+                .iter()
+                .any(|n| !type_params(n.initializer().unwrap_or(n)).is_empty());
+            if generic || !type_params(owner).is_empty() {
+                continue;
+            }
+            let (expected, found) = match (self.signature(overridden), self.signature(method)) {
+                (Some(expected), Some(found)) if expected.0.len() == found.0.len() => {
+                    (expected, found)
+                }
+                _ => continue,
+            };
+            let compatible = expected
+                .0
+                .iter()
+                .zip(&found.0)
+                .all(|(e, f)| self.is_assignable(e, f))
+                && self.is_assignable(&found.1, &expected.1);
+            if !compatible {
+                self.diagnostics.push(
+                    Diagnostic::at(
+                        format!(
+                            "`{}.{}` does not match `{}.{}` it overrides: expected {}, found {}",
+                            class.name(),
+                            method.name(),
+                            owner.name(),
+                            overridden.name(),
+                            describe(&expected),
+                            describe(&found)
+                        ),
+                        method,
+                    )
+                    .with_note(String::from("overridden method declared here"), overridden),
+                );
+            }
+        }
+    }
+
+    /// Every constructor of the type `constructor` belongs to along with its
+    /// arity, `None` when they cannot be listed.
+    fn constructors(&self, constructor: &Constructor) -> Option<Vec<(Constructor, usize)>> {
+        match constructor {
+            Constructor::Variant(name, _) => {
+                let declaration = self.enums.get(name)?;
+                Some(
+                    declaration
+                        .members()
+                        .iter()
+                        .map(|v| {
+                            (
+                                Constructor::Variant(name.clone(), v.name()),
+                                v.children.len(),
+                            )
+                        })
+                        .collect(),
+                )
+            }
+            Constructor::Boolean(_) => Some(vec![
+                (Constructor::Boolean(true), 0),
+                (Constructor::Boolean(false), 0),
+            ]),
+            Constructor::Result(_) => Some(vec![
+                (Constructor::Result(true), 1),
+                (Constructor::Result(false), 1),
+            ]),
+            Constructor::Literal(..) => None,
+        }
+    }
+
+    /// The constructors of the first column's type when the column mentions
+    /// all of them.
+    fn complete_signature(&self, rows: &[Vec<Pattern>]) -> Option<Vec<(Constructor, usize)>> {
+        let heads = head_constructors(rows);
+        let all = self.constructors(heads.first()?)?;
+        if all.iter().all(|(c, _)| heads.contains(c)) {
+            Some(all)
+        } else {
+            None
+        }
+    }
+
+    /// Whether some value matched by `row` is not matched by any of `rows`.
+    fn useful(&self, rows: &[Vec<Pattern>], row: &[Pattern]) -> bool {
+        let (head, rest) = match row.split_first() {
+            Some(split) => split,
+            None => return rows.is_empty(),
+        };
+        let specialized = |constructor: &Constructor, fields: Vec<Pattern>| {
+            let arity = fields.len();
+            let mut row = fields;
+            row.extend_from_slice(rest);
+            self.useful(&specialize(rows, constructor, arity), &row)
+        };
+        match head {
+            Pattern::Constructor(constructor, fields) => specialized(constructor, fields.clone()),
+            Pattern::Wildcard => match self.complete_signature(rows) {
+                Some(all) => all
+                    .iter()
+                    .any(|(c, arity)| specialized(c, vec![Pattern::Wildcard; *arity])),
+                None => self.useful(&default_rows(rows), rest),
+            },
+        }
+    }
+
+    /// Values of `width` columns that no row matches, as patterns; `None`
+    /// when the rows are exhaustive.
+    fn missing(&self, rows: &[Vec<Pattern>], width: usize) -> Option<Vec<Pattern>> {
+        if width == 0 {
+            return if rows.is_empty() { Some(vec![]) } else { None };
+        }
+        if let Some(all) = self.complete_signature(rows) {
+            for (constructor, arity) in all {
+                let specialized = specialize(rows, &constructor, arity);
+                if let Some(mut fields) = self.missing(&specialized, arity + width - 1) {
+                    let rest = fields.split_off(arity);
+                    let mut witness = vec![Pattern::Constructor(constructor, fields)];
+                    witness.extend(rest);
+                    return Some(witness);
+                }
+            }
+            return None;
+        }
+        let mut witness = self.missing(&default_rows(rows), width - 1)?;
+        let heads = head_constructors(rows);
+        let head = heads
+            .first()
+            .and_then(|c| self.constructors(c))
+            .and_then(|all| all.into_iter().find(|(c, _)| !heads.contains(c)))
+            .map_or(Pattern::Wildcard, |(c, arity)| {
+                Pattern::Constructor(c, vec![Pattern::Wildcard; arity])
+            });
+        witness.insert(0, head);
+        Some(witness)
+    }
+
+    /// Checks that a pattern can match values of type `expected`, returning
+    /// whether it can.
+    fn check_pattern(&mut self, node: &'a ASTNode, expected: &Type) -> bool {
+        let constructor = match Pattern::from(node) {
+            Pattern::Wildcard => return true,
+            Pattern::Constructor(constructor, _) => constructor,
+        };
+        let fits = match (&constructor, constructor.type_of()) {
+            (Constructor::Result(_), _) => matches!(expected, Type::Result(..)),
+            (_, found) => found.as_ref() == Some(expected),
+        };
+        if !fits {
+            let found = match constructor.type_of() {
+                Some(found) => found.to_string(),
+                None => String::from("Result"),
+            };
+            self.diagnostics.push(Diagnostic::at(
+                format!(
+                    "Expected a pattern of type `{}`, found one of type `{}`",
+                    expected, found
+                ),
+                node,
+            ));
+            return false;
+        }
+        if let (Constructor::Result(ok), Type::Result(value, error)) = (&constructor, expected) {
+            let inner = if *ok { value } else { error };
+            return match node.children.first() {
+                Some(field) => self.check_pattern(field, inner),
+                None => true,
+            };
+        }
+        let fields = match &constructor {
+            Constructor::Variant(name, variant) => self
+                .enums
+                .get(name)
+                .and_then(|e| e.members().iter().find(|v| v.name() == *variant))
+                .map(|v| v.children.iter().collect())
+                .unwrap_or_default(),
+            _ => vec![],
+        };
+        let mut matches = true;
+        for (field, field_type) in node.children.iter().zip(fields) {
+            matches = self.check_pattern(field, &Type::from_type_dec(Some(field_type))) && matches;
+        }
+        matches
+    }
+
+    /// Checks that the arms of a match agree on the type of the matched
+    /// value, cover every value of it, and can all be reached. `matched` is
+    /// the type of the matched value if known, otherwise the patterns
+    /// decide.
+    fn check_match(&mut self, node: &'a ASTNode, matched: Option<Type>) {
+        let patterns: Vec<&'a ASTNode> =
+            node.children[1..].iter().map(|a| &a.children[0]).collect();
+        let expected = matched.or_else(|| {
+            patterns.iter().find_map(|p| match Pattern::from(p) {
+                Pattern::Constructor(constructor, _) => constructor.type_of(),
+                Pattern::Wildcard => None,
+            })
+        });
+        if let Some(expected) = expected {
+            let mut well_typed = true;
+            for pattern in &patterns {
+                well_typed = self.check_pattern(pattern, &expected) && well_typed;
+            }
+            if !well_typed {
+                return;
+            }
+        }
+        let mut rows: Vec<Vec<Pattern>> = vec![];
+        for pattern in patterns {
+            let row = vec![Pattern::from(pattern)];
+            if !self.useful(&rows, &row) {
+                self.diagnostics.push(Diagnostic::warning_at(
+                    String::from(
+                        "Unreachable match arm: earlier arms match every value it matches",
+                    ),
+                    pattern,
+                ));
+            }
+            rows.push(row);
+        }
+        if let Some(witness) = self.missing(&rows, 1) {
+            self.diagnostics.push(Diagnostic::at(
+                format!("Non-exhaustive match: `{}` is not covered", witness[0]),
+                node,
+            ));
+        }
+    }
+
+    fn local(&self, name: &str) -> Option<&Local<'a>> {
+        self.locals.iter().rev().find_map(|scope| scope.get(name))
+    }
+
+    /// Type of a variable at the current point, narrowed if a null check
+    /// allows.
+    fn lookup_local(&self, name: &str) -> Option<Type> {
+        self.local(name)
+            .and_then(|l| l.narrowed.clone().or_else(|| l.declared.clone()))
+    }
+
+    fn declare_local(&mut self, name: String, local_type: Type) {
+        self.declare(
+            name,
+            Local {
+                declared: Some(local_type),
+                narrowed: None,
+                constant: None,
+            },
+        );
+    }
+
+    fn declare(&mut self, name: String, local: Local<'a>) {
+        if let Some(scope) = self.locals.last_mut() {
+            scope.insert(name, local);
+        }
+    }
+
+    /// A scope where `facts` hold.
+    fn narrowed_scope(&self, facts: Facts) -> HashMap<String, Local<'a>> {
+        facts
+            .into_iter()
+            .filter_map(|(name, narrowed)| {
+                let local = Local {
+                    narrowed: Some(narrowed),
+                    ..self.local(&name)?.clone()
+                };
+                Some((name, local))
+            })
+            .collect()
+    }
+
+    /// Drops what null checks proved about a variable once it is assigned.
+    fn forget_narrowing(&mut self, name: &str) {
+        for scope in &mut self.locals {
+            if let Some(local) = scope.get_mut(name) {
+                local.narrowed = None;
+            }
+        }
+    }
+
+    /// Reports using a value that may be null where one is required. Returns
+    /// the type when the value is safe to use.
+    fn require_present(&mut self, found: Option<Type>, node: &ASTNode) -> Option<Type> {
+        match found {
+            Some(found) if found.is_nullable() => {
+                self.diagnostics.push(Diagnostic::at(
+                    format!(
+                        "Value of type `{}` may be null; check it against null before using it",
+                        found
+                    ),
+                    node,
+                ));
+                None
+            }
+            found => found,
+        }
+    }
+
+    /// Reports a value of type `found` used where a value of type
+    /// `expected` is needed, singling out values that may be null.
+    fn check_fits(&mut self, found: Option<Type>, expected: &Type, node: &ASTNode) {
+        let found = match found {
+            Some(found) if *expected != Type::Void && !self.is_assignable(&found, expected) => {
+                found
+            }
+            _ => return,
+        };
+        let message = if found.is_nullable() {
+            format!(
+                "Value of type `{}` may be null and cannot be used as `{}`",
+                found, expected
+            )
+        } else {
+            format!("Expected `{}`, found `{}`", expected, found)
+        };
+        self.diagnostics.push(Diagnostic::at(message, node));
+    }
+
+    /// Reports an operand of a built in operator that is not of type
+    /// `expected`.
+    fn check_operand(
+        &mut self,
+        found: Option<Type>,
+        expected: &Type,
+        operator: &str,
+        node: &ASTNode,
+    ) {
+        if let Some(found) = found.filter(|f| !self.is_assignable(f, expected)) {
+            self.diagnostics.push(Diagnostic::at(
+                format!(
+                    "Expected `{}` operands for `{}`, found `{}`",
+                    expected, operator, found
+                ),
+                node,
+            ));
+        }
+    }
+
+    /// Member `name` of values of type `object`. The type parameters of a
+    /// generic class are replaced by the type arguments of `object`; a type
+    /// parameter has the members of its bound.
+    fn member_type(&self, object: &Type, name: &str) -> Option<MemberType<'a>> {
+        let (named, args) = match object {
+            Type::Named(named, args) => (named, args),
+            builtin => {
+                let (params, returns) = builtin.builtin_member(name)?;
+                return Some(MemberType {
+                    params,
+                    returns,
+                    type_params: vec![],
+                    constant: None,
+                });
+            }
+        };
+        if args.is_empty() {
+            if let Some(bound) = self.bounds.get(named) {
+                return self.member_type(bound, name);
+            }
+        }
+        let method = |member: &ASTNode, arguments: &HashMap<String, Type>| {
+            let (params, returns) = self.signature(member)?;
+            let function = member.initializer().unwrap_or(member);
+            Some(MemberType {
+                params: Some(params.iter().map(|p| p.substitute(arguments)).collect()),
+                returns: returns.substitute(arguments),
+                type_params: type_params(function),
+                constant: None,
+            })
+        };
+        if let Some(interface) = self.interfaces.get(named) {
+            let required = interface.members().iter().find(|m| m.name() == name)?;
+            return method(required, &HashMap::new());
+        }
+        let class = *self.classes.get(named)?;
+        let arguments: HashMap<String, Type> = type_params(class)
+            .into_iter()
+            .map(|(param, _)| param)
+            .zip(args.iter().cloned())
+            .collect();
+        let member = self.find_member(class, name)?;
+        if member.is_method() {
+            method(member, &arguments)
+        } else {
+            Some(MemberType {
+                params: None,
+                returns: self.declared_type(member)?.substitute(&arguments),
+                type_params: vec![],
+                constant: Some(member).filter(|m| m.is_const()),
+            })
+        }
+    }
+
+    /// Whether values of type `object` have a member `name`. Unbounded type
+    /// parameters and names that are not declared, which the resolver
+    /// reports, are taken to have every member.
+    fn has_member(&self, object: &Type, name: &str) -> bool {
+        match object {
+            Type::Named(named, args) if args.is_empty() && self.bounds.contains_key(named) => {
+                self.has_member(&self.bounds[named], name)
+            }
+            Type::Named(named, _) => {
+                if let Some(interface) = self.interfaces.get(named) {
+                    interface.members().iter().any(|m| m.name() == name)
+                } else if let Some(class) = self.classes.get(named) {
+                    self.find_member(class, name).is_some()
+                } else {
+                    !self.enums.contains_key(named)
+                }
+            }
+            builtin => builtin.builtin_member(name).is_some(),
+        }
+    }
+
+    /// The class, interface or enum declaring the type `object`.
+    fn declaration_of(&self, object: &Type) -> Option<&'a ASTNode> {
+        let named = match object {
+            Type::Named(named, _) => named,
+            _ => return None,
+        };
+        [&self.classes, &self.interfaces, &self.enums]
+            .iter()
+            .find_map(|declarations| declarations.get(named).copied())
+    }
+
+    /// Types a `Member` node. Accessing a member of a value that may be null
+    /// needs `?.`, which makes the result optional as well.
+    fn type_member(&mut self, node: &'a ASTNode) -> Option<MemberType<'a>> {
+        let object_node = &node.children[0];
+        if let Some(name) = object_node.path() {
+            if self.enums.contains_key(&name) && self.lookup_local(&name).is_none() {
+                return Some(MemberType {
+                    params: None,
+                    returns: Type::Named(name, vec![]),
+                    type_params: vec![],
+                    constant: None,
+                });
+            }
+        }
+        let object = self.type_of(object_node);
+        let safe = node.operator.as_deref() == Some(&['?', '.'][..]);
+        let object = match object {
+            Some(Type::Option(inner)) if safe => *inner,
+            object => self.require_present(object, object_node)?,
+        };
+        if !self.has_member(&object, &node.name()) {
+            let message = format!("`{}` has no member `{}`", object, node.name());
+            let mut diagnostic = Diagnostic::at(message, node);
+            if let Some(declaration) = self.declaration_of(&object) {
+                diagnostic = diagnostic.with_note(String::from("declared here"), declaration);
+            }
+            self.diagnostics.push(diagnostic);
+            return None;
+        }
+        let mut member = self.member_type(&object, &node.name())?;
+        if safe && !member.returns.is_nullable() && member.returns != Type::Void {
+            member.returns = Type::Option(Box::new(member.returns));
+        }
+        Some(member)
+    }
+
+    /// Variables a condition proves non-null when it holds and when it does
+    /// not. Only locals and parameters are narrowed; a field could be set
+    /// to null between the check and the use.
+    fn facts(&self, condition: &ASTNode) -> (Facts, Facts) {
+        let operator: String = condition
+            .operator
+            .as_ref()
+            .map(|o| o.iter().collect())
+            .unwrap_or_default();
+        if !condition.is(Production::Expression) {
+            return (vec![], vec![]);
+        }
+        let (lhs, rhs) = (&condition.children[0], &condition.children[1]);
+        match operator.as_str() {
+            "==" | "!=" => {
+                let checked = if rhs.is(Production::NullLiteral) {
+                    lhs
+                } else if lhs.is(Production::NullLiteral) {
+                    rhs
+                } else {
+                    return (vec![], vec![]);
+                };
+                let fact = match (
+                    checked.is(Production::Ident),
+                    self.lookup_local(&checked.name()),
+                ) {
+                    (true, Some(Type::Option(inner))) => vec![(checked.name(), *inner)],
+                    _ => vec![],
+                };
+                if operator == "!=" {
+                    (fact, vec![])
+                } else {
+                    (vec![], fact)
+                }
+            }
+            "&&" => {
+                let (mut holds, _) = self.facts(lhs);
+                holds.extend(self.facts(rhs).0);
+                (holds, vec![])
+            }
+            "||" => {
+                let (_, mut fails) = self.facts(lhs);
+                fails.extend(self.facts(rhs).1);
+                (vec![], fails)
+            }
+            _ => (vec![], vec![]),
+        }
+    }
+
+    /// Types `node` in a scope where `facts` hold.
+    fn type_narrowed(&mut self, node: &'a ASTNode, facts: Facts) -> Option<Type> {
+        let scope = self.narrowed_scope(facts);
+        self.locals.push(scope);
+        let found = self.type_of(node);
+        self.locals.pop();
+        found
+    }
+
+    fn type_binary(&mut self, node: &'a ASTNode, operator: &str) -> Option<Type> {
+        let (lhs_node, rhs_node) = (&node.children[0], &node.children[1]);
+        match operator {
+            "??" => {
+                let lhs = self.type_of(lhs_node);
+                let rhs = self.type_of(rhs_node);
+                match (lhs, rhs) {
+                    (Some(Type::Null), rhs) => rhs,
+                    (Some(Type::Option(inner)), Some(rhs)) if !rhs.is_nullable() => Some(*inner),
+                    (lhs, _) => lhs,
+                }
+            }
+            "&&" | "||" => {
+                let lhs = self.type_of(lhs_node);
+                self.check_operand(lhs, &Type::Boolean, operator, lhs_node);
+                let (holds, fails) = self.facts(lhs_node);
+                let rhs =
+                    self.type_narrowed(rhs_node, if operator == "&&" { holds } else { fails });
+                self.check_operand(rhs, &Type::Boolean, operator, rhs_node);
+                Some(Type::Boolean)
+            }
+            "==" | "!=" => {
+                let lhs = self.type_of(lhs_node);
+                let rhs = self.type_of(rhs_node);
+                if let (Some(lhs), Some(rhs)) = (&lhs, &rhs) {
+                    let compared = (lhs_node, lhs, rhs_node, rhs);
+                    // without `equals` instances are compared by identity
+                    if *rhs != Type::Null && self.defines(lhs, "equals") {
+                        return self.type_operator_call(compared, operator);
+                    }
+                    let comparable = *lhs == Type::Null
+                        || *rhs == Type::Null
+                        || self.is_assignable(lhs, rhs)
+                        || self.is_assignable(rhs, lhs);
+                    if !comparable {
+                        self.diagnostics.push(Diagnostic::at(
+                            format!(
+                                "`{}` and `{}` cannot be compared with `{}`",
+                                lhs, rhs, operator
+                            ),
+                            node,
+                        ));
+                    }
+                }
+                Some(Type::Boolean)
+            }
+            _ => {
+                let lhs = self.type_of(lhs_node);
+                let lhs = self.require_present(lhs, lhs_node);
+                let rhs = self.type_of(rhs_node);
+                let rhs = self.require_present(rhs, rhs_node);
+                if let (Some(lhs), Some(rhs)) = (&lhs, &rhs) {
+                    return self.type_operator_call((lhs_node, lhs, rhs_node, rhs), operator);
+                }
+                if operator != "+" {
+                    self.check_operand(lhs.clone(), &Type::Number, operator, lhs_node);
+                    self.check_operand(rhs.clone(), &Type::Number, operator, rhs_node);
+                }
+                match operator {
+                    "+" if lhs == Some(Type::Word) || rhs == Some(Type::Word) => Some(Type::Word),
+                    "+" | "-" | "*" | "/" => Some(Type::Number),
+                    _ => Some(Type::Boolean),
+                }
+            }
+        }
+    }
+
+    /// Reports operands a built in arithmetic or comparison operator cannot
+    /// take: `+` joins a `Word` with a `Word`, `Number` or `Boolean`, and
+    /// otherwise both operands have to be numbers.
+    fn check_builtin_operands(
+        &mut self,
+        (lhs_node, lhs, rhs_node, rhs): (&ASTNode, &Type, &ASTNode, &Type),
+        operator: &str,
+    ) {
+        let operands = [(lhs, lhs_node), (rhs, rhs_node)];
+        if operator == "+" && (*lhs == Type::Word || *rhs == Type::Word) {
+            for (operand, node) in operands {
+                if !matches!(operand, Type::Word | Type::Number | Type::Boolean) {
+                    self.check_operand(Some(operand.clone()), &Type::Word, operator, node);
+                }
+            }
+        } else {
+            for (operand, node) in operands {
+                self.check_operand(Some(operand.clone()), &Type::Number, operator, node);
+            }
+        }
+    }
+
+    /// Whether values of type `object` have a method called `method`.
+    fn defines(&self, object: &Type, method: &str) -> bool {
+        self.member_type(object, method)
+            .is_some_and(|m| m.params.is_some())
+    }
+
+    /// Types an arithmetic or comparison operator applied to two present
+    /// values. When the operand it is called on is an instance of a class,
+    /// interface or type parameter, the operator stands for that
+    /// operand's method (see `operator_method`), which has to take one
+    /// parameter the other operand fits; the call is recorded for lowering.
+    fn type_operator_call(
+        &mut self,
+        (lhs_node, lhs, rhs_node, rhs): (&'a ASTNode, &Type, &'a ASTNode, &Type),
+        operator: &str,
+    ) -> Option<Type> {
+        let builtin = match operator {
+            "+" if *lhs == Type::Word || *rhs == Type::Word => Type::Word,
+            "+" | "-" | "*" | "/" => Type::Number,
+            _ => Type::Boolean,
+        };
+        let (method, swapped, negated) = match operator_method(operator) {
+            Some(method) => method,
+            None => {
+                self.check_builtin_operands((lhs_node, lhs, rhs_node, rhs), operator);
+                return Some(builtin);
+            }
+        };
+        let (receiver, argument, argument_node) = if swapped {
+            (rhs, lhs, lhs_node)
+        } else {
+            (lhs, rhs, rhs_node)
+        };
+        if !matches!(receiver, Type::Named(..)) {
+            self.check_builtin_operands((lhs_node, lhs, rhs_node, rhs), operator);
+            return Some(builtin);
+        }
+        let called = match self
+            .member_type(receiver, method)
+            .filter(|m| m.params.is_some())
+        {
+            Some(called) => called,
+            None => {
+                self.diagnostics.push(Diagnostic::at(
+                    format!(
+                        "`{}` has no method `{}`, which `{}` needs",
+                        receiver, method, operator
+                    ),
+                    lhs_node,
+                ));
+                return None;
+            }
+        };
+        let params = called.params.unwrap_or_default();
+        if params.len() != 1 {
+            self.diagnostics.push(Diagnostic::at(
+                format!(
+                    "`{}.{}` has to take one parameter to be used as `{}`",
+                    receiver, method, operator
+                ),
+                lhs_node,
+            ));
+            return None;
+        }
+        let vars: Vec<String> = called.type_params.iter().map(|(v, _)| v.clone()).collect();
+        let mut bindings = HashMap::new();
+        infer(&params[0], argument, &vars, &mut bindings);
+        let param = params[0].substitute(&bindings);
+        if !mentions(&param, &vars) && !self.is_assignable(argument, &param) {
+            self.diagnostics.push(Diagnostic::at(
+                format!(
+                    "`{}` on `{}` takes `{}`, found `{}`",
+                    operator, receiver, param, argument
+                ),
+                argument_node,
+            ));
+        }
+        let returns = called.returns.substitute(&bindings);
+        if builtin == Type::Boolean && returns != Type::Boolean {
+            self.diagnostics.push(Diagnostic::at(
+                format!(
+                    "`{}.{}` has to return `Boolean` to be used as `{}`, found `{}`",
+                    receiver, method, operator, returns
+                ),
+                lhs_node,
+            ));
+        }
+        let call = OperatorCall {
+            lhs: lhs_node.location(),
+            rhs: rhs_node.location(),
+            method: String::from(method),
+            swapped,
+            negated,
+        };
+        if !self.operator_calls.contains(&call) {
+            self.operator_calls.push(call);
+        }
+        if builtin == Type::Boolean {
+            Some(Type::Boolean)
+        } else if mentions(&returns, &vars) {
+            None
+        } else {
+            Some(returns)
+        }
+    }
+
+    /// Checks the arguments of a call against the parameters of `called`,
+    /// inferring its type parameters from them, and returns the type of the
+    /// result. `None` if a type parameter the result mentions could not be
+    /// inferred.
+    fn check_arguments(
+        &mut self,
+        node: &'a ASTNode,
+        arguments: &'a [ASTNode],
+        called: &MemberType<'a>,
+    ) -> Option<Type> {
+        let params = called.params.clone().unwrap_or_default();
+        let vars: Vec<String> = called.type_params.iter().map(|(v, _)| v.clone()).collect();
+        let mut bindings = HashMap::new();
+        let mut found = vec![];
+        for (i, argument) in arguments.iter().enumerate() {
+            let argument_type = match params.get(i).filter(|p| !mentions(p, &vars)) {
+                Some(param) => self.type_expected(argument, param),
+                None => self.type_of(argument),
+            };
+            if let (Some(param), Some(argument_type)) = (params.get(i), &argument_type) {
+                infer(param, argument_type, &vars, &mut bindings);
+            }
+            found.push(argument_type);
+        }
+        for (i, argument_type) in found.into_iter().enumerate() {
+            let expected = params.get(i).map(|p| p.substitute(&bindings));
+            if let Some(expected) = expected.filter(|e| !mentions(e, &vars)) {
+                self.check_fits(argument_type, &expected, &arguments[i]);
+            }
+        }
+        for (var, bound) in &called.type_params {
+            if let (Some(bound), Some(bound_to)) = (bound, bindings.get(var)) {
+                if !self.is_assignable(bound_to, bound) {
+                    self.diagnostics.push(Diagnostic::at(
+                        format!(
+                            "`{}` does not implement `{}`, required by type parameter `{}`",
+                            bound_to, bound, var
+                        ),
+                        node,
+                    ));
+                }
+            }
+        }
+        let returns = called.returns.substitute(&bindings);
+        if mentions(&returns, &vars) {
+            None
+        } else {
+            Some(returns)
+        }
+    }
+
+    fn type_call(&mut self, node: &'a ASTNode) -> Option<Type> {
+        let callee = &node.children[0];
+        let called = if callee.is(Production::Member) {
+            self.type_member(callee)
+        } else {
+            let found = self.type_of(callee);
+            match self.require_present(found, callee) {
+                Some(Type::FunctionOf(params, returns)) => Some(MemberType {
+                    params: Some(params),
+                    returns: *returns,
+                    type_params: vec![],
+                    constant: None,
+                }),
+                _ => None,
+            }
+        };
+        let arguments = &node.children[1..];
+        match called {
+            // enum variants are constructed by calling them
+            Some(MemberType {
+                params: None,
+                returns: Type::Named(name, args),
+                ..
+            }) if self.enums.contains_key(&name) => {
+                for argument in arguments {
+                    self.type_of(argument);
+                }
+                Some(Type::Named(name, args))
+            }
+            Some(called) if called.params.is_some() => {
+                let expected = called.params.as_ref().map_or(0, |p| p.len());
+                if expected != arguments.len() {
+                    self.diagnostics.push(Diagnostic::at(
+                        format!(
+                            "`{}` takes {} argument(s) but {} were given",
+                            callee.path().unwrap_or_else(|| callee.name()),
+                            expected,
+                            arguments.len()
+                        ),
+                        node,
+                    ));
+                }
+                self.check_arguments(node, arguments, &called)
+            }
+            _ => {
+                for argument in arguments {
+                    self.type_of(argument);
+                }
+                None
+            }
+        }
+    }
+
+    /// Types `new Class(arguments)`. The type arguments of a generic class
+    /// are inferred from the arguments passed to its constructor.
+    fn type_new(&mut self, node: &'a ASTNode) -> Option<Type> {
+        let class = match self.classes.get(&node.name()) {
+            Some(class) => *class,
+            None => return None,
+        };
+        let params = type_params(class);
+        let generic = Type::Named(
+            node.name(),
+            params
+                .iter()
+                .map(|(p, _)| Type::Named(p.clone(), vec![]))
+                .collect(),
+        );
+        let constructor = self
+            .find_member(class, "init")
+            .filter(|init| init.is_method())
+            .and_then(function_of)
+            .map(param_types)
+            .unwrap_or_default();
+        let called = MemberType {
+            params: Some(constructor),
+            returns: generic,
+            type_params: params,
+            constant: None,
+        };
+        self.check_arguments(node, &node.children, &called)
+    }
+
+    /// Declares the variables a pattern binds, given the type of the value
+    /// it is matched against.
+    fn bind_pattern(&mut self, pattern: &ASTNode, matched: Option<Type>) {
+        match &pattern.production {
+            Some(Production::BindingPattern) => {
+                if let Some(matched) = matched {
+                    self.declare_local(pattern.name(), matched);
+                }
+            }
+            Some(Production::VariantPattern)
+                if pattern.name() == "Ok" || pattern.name() == "Err" =>
+            {
+                let inner = match matched {
+                    Some(Type::Result(value, _)) if pattern.name() == "Ok" => Some(*value),
+                    Some(Type::Result(_, error)) => Some(*error),
+                    _ => None,
+                };
+                if let Some(field) = pattern.children.first() {
+                    self.bind_pattern(field, inner);
+                }
+            }
+            Some(Production::VariantPattern) => {
+                let name = pattern.name();
+                let (enum_name, variant) = name.split_at(name.rfind('.').unwrap_or(0));
+                let fields: Vec<Type> = self
+                    .enums
+                    .get(enum_name)
+                    .and_then(|e| {
+                        e.members()
+                            .iter()
+                            .find(|v| v.name() == variant.trim_start_matches('.'))
+                    })
+                    .map(|v| {
+                        v.children
+                            .iter()
+                            .map(|t| Type::from_type_dec(Some(t)))
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                for (i, field) in pattern.children.iter().enumerate() {
+                    self.bind_pattern(field, fields.get(i).cloned());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn type_match(&mut self, node: &'a ASTNode) -> Option<Type> {
+        let matched = self.type_of(&node.children[0]);
+        let matched = self.require_present(matched, &node.children[0]);
+        self.check_match(node, matched.clone());
+        let mut result = None;
+        for arm in &node.children[1..] {
+            self.locals.push(HashMap::new());
+            self.bind_pattern(&arm.children[0], matched.clone());
+            let body = &arm.children[1];
+            if body.is(Production::BlockBody) {
+                self.check_block(body);
+            } else if let Some(found) = self.type_of(body) {
+                result = result.or(Some(found));
+            }
+            self.locals.pop();
+        }
+        result
+    }
+
+    /// Type of `result?`: the value of an `Ok`. The error of an `Err` is
+    /// returned from the enclosing function, so that has to return a
+    /// `Result` able to hold it.
+    fn type_try(&mut self, node: &'a ASTNode) -> Option<Type> {
+        let operand = &node.children[0];
+        let (value, error) = match self.type_of(operand)? {
+            Type::Result(value, error) => (*value, *error),
+            found => {
+                self.diagnostics.push(Diagnostic::at(
+                    format!("`?` needs a `Result`, found `{}`", found),
+                    operand,
+                ));
+                return None;
+            }
+        };
+        match &self.returns {
+            Type::Result(_, returned) if !self.is_assignable(&error, returned) => {
+                let message = format!(
+                    "`?` cannot return an error of type `{}` from a function returning `{}`",
+                    error, self.returns
+                );
+                self.diagnostics.push(Diagnostic::at(message, node));
+            }
+            Type::Result(..) => {}
+            _ => self.diagnostics.push(Diagnostic::at(
+                String::from("`?` can only be used in functions returning a `Result`"),
+                node,
+            )),
+        }
+        Some(value)
+    }
+
+    /// Type of an expression used where a value of type `expected` is
+    /// wanted. `Ok` and `Err` take their types from there, as their operand
+    /// only tells one of the two, and the elements and entries of list and
+    /// map literals are checked against it.
+    fn type_expected(&mut self, node: &'a ASTNode, expected: &Type) -> Option<Type> {
+        let inner = match (&node.production, expected) {
+            (Some(Production::Ok), Type::Result(value, _)) => value,
+            (Some(Production::Err), Type::Result(_, error)) => error,
+            (Some(Production::ListLiteral | Production::MapLiteral), Type::Option(inner)) => {
+                return self.type_expected(node, inner);
+            }
+            (Some(Production::ListLiteral), Type::List(element)) => {
+                for child in &node.children {
+                    self.check_against(child, Some(element));
+                }
+                return Some(expected.clone());
+            }
+            (Some(Production::MapLiteral), Type::Map(key, value)) => {
+                for entry in &node.children {
+                    self.check_against(&entry.children[0], Some(key));
+                    self.check_against(&entry.children[1], Some(value));
+                }
+                return Some(expected.clone());
+            }
+            _ => return self.type_of(node),
+        };
+        let found = self.type_expected(&node.children[0], inner);
+        self.check_fits(found, inner, &node.children[0]);
+        Some(expected.clone())
+    }
+
+    /// Checks an expression against the type it has to fit when that is
+    /// known, otherwise just checks the expressions below it.
+    fn check_against(&mut self, node: &'a ASTNode, expected: Option<&Type>) {
+        match expected {
+            Some(expected) => {
+                let found = self.type_expected(node, expected);
+                self.check_fits(found, expected, node);
+            }
+            None => {
+                self.type_of(node);
+            }
+        }
+    }
+
+    /// Type of an expression, `None` when it cannot be told. Checks the
+    /// expressions below it along the way.
+    fn type_of(&mut self, node: &'a ASTNode) -> Option<Type> {
+        match &node.production {
+            Some(Production::NumberLiteral) => Some(Type::Number),
+            Some(Production::WordLiteral) => Some(Type::Word),
+            Some(Production::BooleanLiteral) => Some(Type::Boolean),
+            Some(Production::NullLiteral) => Some(Type::Null),
+            Some(Production::Ident) => self.lookup_local(&node.name()).or_else(|| {
+                if self.enums.contains_key(&node.name()) {
+                    Some(Type::Named(node.name(), vec![]))
+                } else {
+                    None
+                }
+            }),
+            Some(Production::This) => self.class.map(|c| {
+                let params = type_params(c)
+                    .into_iter()
+                    .map(|(p, _)| Type::Named(p, vec![]))
+                    .collect();
+                Type::Named(c.name(), params)
+            }),
+            Some(Production::Super) => self
+                .class
+                .and_then(|c| self.parent(c))
+                .map(|p| Type::Named(p.name(), vec![])),
+            Some(Production::New) => self.type_new(node),
+            Some(Production::Member) => self.type_member(node).map(|member| match member.params {
+                Some(params) if member.type_params.is_empty() => {
+                    Type::FunctionOf(params, Box::new(member.returns))
+                }
+                Some(_) => Type::Function,
+                None => member.returns,
+            }),
+            Some(Production::Call) => self.type_call(node),
+            Some(Production::Index) => {
+                let object = self.type_of(&node.children[0]);
+                let object = self.require_present(object, &node.children[0]);
+                self.type_of(&node.children[1]);
+                match object? {
+                    Type::List(element) => Some(*element),
+                    Type::Map(_, value) => Some(*value),
+                    _ => None,
+                }
+            }
+            Some(Production::Expression) => {
+                let operator: String = node.operator.iter().flatten().collect();
+                self.type_binary(node, &operator)
+            }
+            Some(Production::Unary) => {
+                let operator: String = node.operator.iter().flatten().collect();
+                let operand_type = match operator.as_str() {
+                    "!" => Type::Boolean,
+                    _ => Type::Number,
+                };
+                let operand = self.type_of(&node.children[0]);
+                let operand = self.require_present(operand, &node.children[0]);
+                self.check_operand(operand, &operand_type, &operator, &node.children[0]);
+                Some(operand_type)
+            }
+            Some(Production::ListLiteral) => {
+                let (first, rest) = node.children.split_first()?;
+                let element = self.type_of(first);
+                for child in rest {
+                    self.check_against(child, element.as_ref());
+                }
+                element.map(|e| Type::List(Box::new(e)))
+            }
+            Some(Production::MapLiteral) => {
+                let (first, rest) = node.children.split_first()?;
+                let key = self.type_of(&first.children[0]);
+                let value = self.type_of(&first.children[1]);
+                if let Some(key) = key.as_ref().filter(|k| !k.is_map_key()) {
+                    self.diagnostics.push(Diagnostic::at(
+                        format!(
+                            "Map keys have to be Word, Number or Boolean, found `{}`",
+                            key
+                        ),
+                        &first.children[0],
+                    ));
+                }
+                for entry in rest {
+                    self.check_against(&entry.children[0], key.as_ref());
+                    self.check_against(&entry.children[1], value.as_ref());
+                }
+                let key = key.filter(|k| k.is_map_key())?;
+                Some(Type::Map(Box::new(key), Box::new(value?)))
+            }
+            Some(Production::FunctionDec) => self.check_function(node),
+            Some(Production::Match) => self.type_match(node),
+            Some(Production::Try) => self.type_try(node),
+            _ => {
+                for child in &node.children {
+                    self.type_of(child);
+                }
+                None
+            }
+        }
+    }
+
+    /// Checks the statements of a block in a scope of its own. Returns
+    /// whether the block always leaves the function.
+    fn check_block(&mut self, block: &'a ASTNode) -> bool {
+        self.locals.push(HashMap::new());
+        let mut exits = false;
+        for statement in &block.children {
+            exits = self.check_statement(statement);
+        }
+        self.locals.pop();
+        exits
+    }
+
+    /// Checks a block in a scope where `facts` hold.
+    fn check_narrowed_block(&mut self, block: &'a ASTNode, facts: Facts) -> bool {
+        let scope = self.narrowed_scope(facts);
+        self.locals.push(scope);
+        let exits = self.check_block(block);
+        self.locals.pop();
+        exits
+    }
+
+    /// Checks an `if` statement. A condition narrows the variables it checks
+    /// against null in the branch where they are known to be present, and
+    /// in the rest of the enclosing block when the other branch always
+    /// leaves the function.
+    fn check_if(&mut self, node: &'a ASTNode) -> bool {
+        let condition = &node.children[0];
+        let found = self.type_of(condition);
+        if let Some(found) = found.filter(|f| *f != Type::Boolean) {
+            self.diagnostics.push(Diagnostic::at(
+                format!("Expected a `Boolean` condition, found `{}`", found),
+                condition,
+            ));
+        }
+        let (holds, fails) = self.facts(condition);
+        let then_exits = self.check_narrowed_block(&node.children[1], holds.clone());
+        let else_exits = match node.children.get(2) {
+            Some(otherwise) if otherwise.is(Production::If) => {
+                let scope = self.narrowed_scope(fails.clone());
+                self.locals.push(scope);
+                let exits = self.check_if(otherwise);
+                self.locals.pop();
+                exits
+            }
+            Some(otherwise) => self.check_narrowed_block(otherwise, fails.clone()),
+            None => false,
+        };
+        let proven = match (then_exits, else_exits) {
+            (true, true) => [fails, holds].concat(),
+            (true, false) => fails,
+            (false, true) => holds,
+            (false, false) => vec![],
+        };
+        for (name, local) in self.narrowed_scope(proven) {
+            self.declare(name, local);
+        }
+        then_exits && else_exits
+    }
+
+    fn report_constant_assigned(&mut self, message: String, node: &ASTNode, constant: &ASTNode) {
+        self.diagnostics.push(
+            Diagnostic::at(message, node)
+                .with_note(String::from("declared as a constant here"), constant),
+        );
+    }
+
+    /// Checks an assignment: constants cannot be assigned to, except for a
+    /// `const` field without a value being set by the constructor, and the
+    /// value has to fit the type the target was declared with. Assigning a
+    /// variable undoes what null checks proved about it.
+    fn check_assign(&mut self, node: &'a ASTNode) {
+        let (target, value) = (&node.children[0], &node.children[1]);
+        // the type the target was declared with and the one it has now
+        let (declared, current) = match &target.production {
+            Some(Production::Ident) => {
+                let local = self.local(&target.name()).cloned();
+                if let Some(constant) = local.as_ref().and_then(|l| l.constant) {
+                    self.report_constant_assigned(
+                        format!("Cannot assign to `{}`, it is a constant", target.name()),
+                        node,
+                        constant,
+                    );
+                }
+                (
+                    local.and_then(|l| l.declared),
+                    self.lookup_local(&target.name()),
+                )
+            }
+            Some(Production::Member) => {
+                let member = self.type_member(target);
+                let constant = member.as_ref().and_then(|m| m.constant);
+                let set_by_constructor = self.constructor
+                    && target.children[0].is(Production::This)
+                    && constant.is_some_and(|c| c.initializer().is_none());
+                if let Some(constant) = constant.filter(|_| !set_by_constructor) {
+                    self.report_constant_assigned(
+                        format!(
+                            "Cannot assign to field `{}`, it is a constant",
+                            target.name()
+                        ),
+                        node,
+                        constant,
+                    );
+                }
+                let declared = member.map(|m| m.returns);
+                (declared.clone(), declared)
+            }
+            _ => {
+                let declared = self.type_of(target);
+                (declared.clone(), declared)
+            }
+        };
+        if let Some(operator) = &node.operator {
+            // `x += v` uses the value of `x` like `x = x + v` does
+            let current = self.require_present(current, target);
+            let found = self.type_of(value);
+            let found = self.require_present(found, value);
+            if let (Some(current), Some(found)) = (&current, &found) {
+                let operator: String = operator.iter().collect();
+                let result = self.type_operator_call((target, current, value, found), &operator);
+                if let (Some(declared), Some(result)) = (&declared, result) {
+                    if !self.is_assignable(&result, declared) {
+                        self.diagnostics.push(Diagnostic::at(
+                            format!(
+                                "`{}=` gives `{}`, which cannot be assigned to `{}`",
+                                operator, result, declared
+                            ),
+                            node,
+                        ));
+                    }
+                }
+            }
+        } else if let Some(declared) = declared {
+            let found = self.type_expected(value, &declared);
+            self.check_fits(found, &declared, value);
+        } else {
+            self.type_of(value);
+        }
+        if target.is(Production::Ident) {
+            self.forget_narrowing(&target.name());
+        }
+    }
+
+    /// Checks the initialiser of a local or field against its type, or
+    /// infers the type from it when there is none. Returns the type, `None`
+    /// when it cannot be told.
+    fn check_vardec(&mut self, node: &'a ASTNode) -> Option<Type> {
+        let initializer = node.initializer();
+        let declared = match node.find(Production::TypeDec) {
+            Some(type_dec) => Type::from_type_dec(Some(type_dec)),
+            None => {
+                let found = initializer.and_then(|i| self.type_of(i));
+                let empty = initializer.is_some_and(|i| {
+                    (i.is(Production::ListLiteral) || i.is(Production::MapLiteral))
+                        && i.children.is_empty()
+                });
+                if found == Some(Type::Null) || empty {
+                    self.diagnostics.push(Diagnostic::at(
+                        format!(
+                            "Cannot infer the type of `{}` from its value; give it a type",
+                            node.name()
+                        ),
+                        node,
+                    ));
+                    return None;
+                }
+                if let Some(found) = &found {
+                    self.inferred
+                        .entry(node.location())
+                        .or_insert_with(|| found.clone());
+                }
+                return found;
+            }
+        };
+        if let Some(initializer) = initializer {
+            let found = self.type_expected(initializer, &declared);
+            self.check_fits(found, &declared, initializer);
+        }
+        Some(declared)
+    }
+
+    /// Checks a statement, returning whether it always leaves the function.
+    fn check_statement(&mut self, node: &'a ASTNode) -> bool {
+        match &node.production {
+            Some(Production::Vardec) => {
+                let declared = self.check_vardec(node);
+                let local = Local {
+                    declared,
+                    narrowed: None,
+                    constant: Some(node).filter(|n| n.is_const()),
+                };
+                self.declare(node.name(), local);
+                false
+            }
+            Some(Production::If) => self.check_if(node),
+            Some(Production::Return) => {
+                let found = match node.children.first() {
+                    Some(value) => {
+                        let returns = self.returns.clone();
+                        let found = self.type_expected(value, &returns);
+                        self.check_fits(found.clone(), &returns, value);
+                        found
+                    }
+                    None => {
+                        if self.returned.is_none() {
+                            self.diagnostics.push(Diagnostic::at(
+                                format!("Expected a `{}` to be returned", self.returns),
+                                node,
+                            ));
+                        }
+                        Some(Type::Void)
+                    }
+                };
+                if let Some(returned) = &mut self.returned {
+                    returned.push(found);
+                }
+                true
+            }
+            Some(Production::For) => {
+                let iterated = self.type_of(&node.children[0]);
+                let iterated = self.require_present(iterated, &node.children[0]);
+                self.locals.push(HashMap::new());
+                match iterated {
+                    Some(Type::List(element)) => self.declare_local(node.name(), *element),
+                    Some(Type::Map(key, _)) => self.declare_local(node.name(), *key),
+                    _ => {}
+                }
+                self.check_block(&node.children[1]);
+                self.locals.pop();
+                false
+            }
+            Some(Production::Assign) => {
+                self.check_assign(node);
+                false
+            }
+            _ => {
+                if let Some(Type::Result(value, error)) = self.type_of(node) {
+                    self.diagnostics.push(Diagnostic::at(
+                        format!(
+                            "This `Result<{}, {}>` is ignored; handle it with `match` or propagate it with `?`",
+                            value, error
+                        ),
+                        node,
+                    ));
+                }
+                false
+            }
+        }
+    }
+
+    /// Checks the body of a function literal with its parameters in scope.
+    /// Captured variables are shared with the enclosing function, which may
+    /// assign them before the literal is called, so null checks made outside
+    /// it do not hold inside.
+    ///
+    /// Returns the type of the function. Without a `~Type` its return type
+    /// is inferred from its `return` statements: the type they all return,
+    /// made optional if some return `null`, or nothing if none returns a
+    /// value.
+    fn check_function(&mut self, function: &'a ASTNode) -> Option<Type> {
+        let mut captured = HashMap::new();
+        for scope in &self.locals {
+            for (name, local) in scope {
+                let local = Local {
+                    narrowed: None,
+                    ..local.clone()
+                };
+                captured.insert(name.clone(), local);
+            }
+        }
+        self.locals.push(captured);
+        self.locals.push(HashMap::new());
+        if let Some(params) = function.find(Production::Params) {
+            for param in &params.children {
+                let declared = Type::from_type_dec(param.find(Production::TypeDec));
+                self.declare_local(param.name(), declared);
+            }
+        }
+        let annotated = function.find(Production::TypeDec);
+        let returns = Type::from_type_dec(annotated);
+        let enclosing = std::mem::replace(&mut self.returns, returns);
+        let inferring = if annotated.is_none() {
+            Some(vec![])
+        } else {
+            None
+        };
+        let enclosing_returned = std::mem::replace(&mut self.returned, inferring);
+        let enclosing_bounds = self.bounds.clone();
+        self.enter_type_params(function);
+        if let Some(body) = function.find(Production::BlockBody) {
+            self.check_block(body);
+        }
+        self.locals.truncate(self.locals.len() - 2);
+        let returns = std::mem::replace(&mut self.returns, enclosing);
+        let returned = std::mem::replace(&mut self.returned, enclosing_returned);
+        self.bounds = enclosing_bounds;
+        let returns = match returned {
+            Some(returned) => self.unify_returned(function, returned)?,
+            None => returns,
+        };
+        let function_type = Type::FunctionOf(param_types(function), Box::new(returns));
+        if annotated.is_none() {
+            self.inferred
+                .entry(function.location())
+                .or_insert_with(|| function_type.clone());
+        }
+        Some(function_type)
+    }
+
+    /// Return type of a function literal from the types its `return`
+    /// statements give, `None` if one of them cannot be told or they
+    /// disagree.
+    fn unify_returned(&mut self, function: &ASTNode, returned: Vec<Option<Type>>) -> Option<Type> {
+        let returned: Vec<Type> = returned.into_iter().collect::<Option<_>>()?;
+        let returns_null = returned.contains(&Type::Null);
+        let mut values = returned
+            .iter()
+            .filter(|t| **t != Type::Null && **t != Type::Void);
+        let unified = match values.next() {
+            Some(first) => values.try_fold(first.clone(), |unified, next| {
+                if self.is_assignable(next, &unified) {
+                    Some(unified)
+                } else if self.is_assignable(&unified, next) {
+                    Some(next.clone())
+                } else {
+                    None
+                }
+            }),
+            None if returns_null => Some(Type::Null),
+            None => return Some(Type::Void),
+        };
+        match unified {
+            Some(unified) if returns_null && !unified.is_nullable() => {
+                Some(Type::Option(Box::new(unified)))
+            }
+            Some(unified) => Some(unified),
+            None => {
+                let found: Vec<String> = returned.iter().map(|t| format!("`{}`", t)).collect();
+                self.diagnostics.push(Diagnostic::at(
+                    format!(
+                        "Cannot infer the return type from {}; give it one with `~Type`",
+                        found.join(", ")
+                    ),
+                    function,
+                ));
+                None
+            }
+        }
+    }
+
+    /// Records the bounds of the type parameters of a generic class or
+    /// function. Unbounded parameters shadow the bounds of enclosing ones
+    /// with the same name.
+    fn enter_type_params(&mut self, generic: &ASTNode) {
+        for (param, bound) in type_params(generic) {
+            match bound {
+                Some(bound) => self.bounds.insert(param, bound),
+                None => self.bounds.remove(&param),
+            };
+        }
+    }
+
+    /// Checks the initialisers of the members of a class.
+    fn check_members(&mut self, class: &'a ASTNode) {
+        self.class = Some(class);
+        self.bounds = HashMap::new();
+        self.enter_type_params(class);
+        for member in class.members() {
+            if member.initializer().is_none() {
+                continue;
+            }
+            self.locals = vec![HashMap::new()];
+            self.constructor = member.name() == "init";
+            self.check_vardec(member);
+        }
+        self.class = None;
+    }
+
+    /// Type of a field or local, written in its declaration or inferred
+    /// from its value.
+    fn declared_type(&self, vardec: &ASTNode) -> Option<Type> {
+        match vardec.find(Production::TypeDec) {
+            Some(type_dec) => Some(Type::from_type_dec(Some(type_dec))),
+            None => self.inferred.get(&vardec.location()).cloned(),
+        }
+    }
+
+    /// Parameter types and return type of a method or method signature,
+    /// `None` while the return type has not been inferred.
+    fn signature(&self, member: &ASTNode) -> Option<(Vec<Type>, Type)> {
+        let function = function_of(member)?;
+        let returns = match function.find(Production::TypeDec) {
+            Some(returns) => Type::from_type_dec(Some(returns)),
+            None if function.is(Production::MethodSig) => Type::Void,
+            None => match self.inferred.get(&function.location())? {
+                Type::FunctionOf(_, returns) => *returns.clone(),
+                _ => return None,
+            },
+        };
+        Some((param_types(function), returns))
+    }
+
+    /// Infers the types of members declared without one before anything is
+    /// checked, so that using a member does not depend on where it is
+    /// declared. Members may use each other, so this goes over them until
+    /// nothing new is learnt; problems are reported when checking them.
+    fn infer_members(&mut self, program: &'a ASTNode) {
+        let reported = self.diagnostics.len();
+        loop {
+            let known = self.inferred.len();
+            for declaration in &program.children {
+                if declaration.is(Production::ClassDec) {
+                    self.check_members(declaration);
+                }
+            }
+            if self.inferred.len() == known {
+                break;
+            }
+        }
+        self.diagnostics.truncate(reported);
+    }
+
+    /// Checks the type annotations below `node`.
+    fn check_type_decs(&mut self, node: &'a ASTNode) {
+        if node.is(Production::TypeDec) && node.name() == "Map" {
+            if let Some(key) = node.children.first() {
+                let key_type = Type::from_type_dec(Some(key));
+                if !key_type.is_map_key() {
+                    self.diagnostics.push(Diagnostic::at(
+                        format!(
+                            "Map keys have to be Word, Number or Boolean, found `{}`",
+                            key_type
+                        ),
+                        key,
+                    ));
+                }
+            }
+        }
+        for child in &node.children {
+            self.check_type_decs(child);
+        }
+    }
+
+    pub fn check(&mut self, program: &'a ASTNode) {
+        self.infer_members(program);
+        for declaration in &program.children {
+            if declaration.is(Production::ClassDec) {
+                self.check_conformance(declaration);
+                self.check_overrides(declaration);
+                self.check_members(declaration);
+            }
+            self.check_type_decs(declaration);
+        }
+    }
+}
+
+/// Type checks a lowered program, returning every problem found.
+pub fn check(program: &ASTNode) -> Vec<Diagnostic> {
+    let mut checker = Checker::new(program);
+    checker.check(program);
+    checker.diagnostics
+}
+
+/// Operators of a lowered program that call methods of their operands.
+pub fn operator_calls(program: &ASTNode) -> Vec<OperatorCall> {
+    let mut checker = Checker::new(program);
+    checker.check(program);
+    checker.operator_calls
+}
+
+/// Types inferred for the bindings and function literals of a lowered
+/// program, in the order they are declared.
+pub fn inferred_types(program: &ASTNode) -> Vec<InferredType> {
+    let mut checker = Checker::new(program);
+    checker.check(program);
+    let mut inferred: Vec<InferredType> = checker
+        .inferred
+        .into_iter()
+        .map(
+            |((source, line_number, start_col), inferred)| InferredType {
+                source,
+                line_number,
+                start_col,
+                inferred,
+            },
+        )
+        .collect();
+    inferred.sort_by_key(|i| (i.source, i.line_number, i.start_col));
+    inferred
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codegen;
+    use crate::parser::parse_text;
+
+    fn diagnostics(text: &str) -> Vec<String> {
+        let program = parse_text(text);
+        check(&program).into_iter().map(|d| d.message).collect()
+    }
+
+    #[test]
+    fn conforming_class() {
+        let found = diagnostics(
+            "interface Named { name() ~Word; friend() ~Named; }
+             class Base { let name: Function = () ~Word { return \"base\"; } }
+             class Person extends Base implements Named {
+                let friend: Function = () ~Person { return this; }
+             }",
+        );
+        assert!(found.is_empty());
+    }
+
+    #[test]
+    fn map_key_types() {
+        let found = diagnostics(
+            "class Index {
+                let byName: Map<Word, Index>;
+                let byIndex: Map<Index, Word>;
+                let nested: Map<Boolean, Map<Number[], Word>>;
+             }",
+        );
+        assert_eq!(
+            found,
+            vec![
+                "Map keys have to be Word, Number or Boolean, found `Index`",
+                "Map keys have to be Word, Number or Boolean, found `List<Number>`",
+            ]
+        );
+    }
+
+    #[test]
+    fn missing_and_mismatched_methods() {
+        let found = diagnostics(
+            "interface Greeter { greet(other: Word) ~Word; wave(); count() ~Number; }
+             class Person implements Greeter {
+                let greet: Function = (other: Number) ~Word { return \"hi\"; }
+                let count: Number = 1;
+             }",
+        );
+        assert_eq!(
+            found,
+            vec![
+                "`Person.greet` does not match `Greeter.greet`: expected (Word) ~Word, found (Number) ~Word",
+                "`Person` does not implement `wave` required by interface `Greeter`",
+                "`Person.count` has to be a method to implement `Greeter.count`",
+            ]
+        );
+    }
+
+    #[test]
+    fn match_exhaustiveness() {
+        let found = diagnostics(
+            "enum Shape { Circle(Number), Rect(Number, Number), Empty }
+             enum Maybe { Some(Boolean), None }
+             class Geometry {
+                let f: Function = (s: Shape, o: Maybe, n: Number) ~Number {
+                    let a: Number = match (s) { Shape.Circle(r) => r, Shape.Rect(w, h) => w };
+                    let b: Number = match (o) { Maybe.Some(true) => 1, Maybe.None => 0 };
+                    let c: Number = match (o) { Maybe.Some(true) => 1, Maybe.Some(false) => 2, Maybe.None => 3 };
+                    let d: Number = match (n) { 1 => 1, 2 => 2 };
+                    let e: Number = match (n) { 1 => 1, x => x, 2 => 2 };
+                    let g: Number = match (s) { Shape.Rect(1, _) => 1, _ => 0, Shape.Empty => 0 };
+                    return match (o) { Maybe.Some(1) => 1, \"one\" => 2, _ => 0 };
+                }
+             }",
+        );
+        assert_eq!(
+            found,
+            vec![
+                "Non-exhaustive match: `Shape.Empty` is not covered",
+                "Non-exhaustive match: `Maybe.Some(false)` is not covered",
+                "Non-exhaustive match: `_` is not covered",
+                "Unreachable match arm: earlier arms match every value it matches",
+                "Unreachable match arm: earlier arms match every value it matches",
+                "Expected a pattern of type `Boolean`, found one of type `Number`",
+                "Expected a pattern of type `Maybe`, found one of type `Word`",
+            ]
+        );
+    }
+
+    #[test]
+    fn null_safety() {
+        let found = diagnostics(
+            "class Person {
+                let name: Word;
+                let friend: Person?;
+                let age: Number? = null;
+                let greet: Function = (other: Person) ~Word { return other.name; }
+                let describe: Function = (other: Person?) ~Word {
+                    let name: Word? = other?.name;
+                    let fallback: Word = other?.name ?? \"nobody\";
+                    if (other != null && other.name == \"Bob\") { return other.name; }
+                    if (other == null) { return this.friend.name; }
+                    return other.name + name + this.greet(this.friend);
+                }
+                let older: Function = () ~Number {
+                    if (this.age != null) { return this.age + 1; }
+                    return this.age;
+                }
+             }",
+        );
+        assert_eq!(
+            found,
+            vec![
+                "Value of type `Person?` may be null; check it against null before using it",
+                "Value of type `Word?` may be null; check it against null before using it",
+                "Value of type `Person?` may be null and cannot be used as `Person`",
+                "Value of type `Number?` may be null; check it against null before using it",
+                "Value of type `Number?` may be null and cannot be used as `Number`",
+            ]
+        );
+    }
+
+    #[test]
+    fn generics() {
+        let found = diagnostics(
+            "interface Named { name() ~Word; }
+             class Person implements Named { let name: Function = () ~Word { return \"p\"; } }
+             class Box<T> {
+                let value: T;
+                let init: Function = (value: T) { this.value = value; }
+                let get: Function = () ~T { return this.value; }
+                let describe: Function = <N: Named>(named: N) ~Word { return named.name(); }
+             }
+             class User {
+                let box: Box<Person?> = new Box(null);
+                let run: Function = (p: Person, maybe: Person?) ~Word {
+                    let a: Person = new Box(p).get();
+                    let b: Person = new Box(maybe).get();
+                    let c: Word = new Box(1).describe(p) + this.box.get().name();
+                    return new Box(2).describe(3);
+                }
+             }",
+        );
+        assert_eq!(
+            found,
+            vec![
+                "Value of type `Person?` may be null and cannot be used as `Person`",
+                "Value of type `Person?` may be null; check it against null before using it",
+                "`Number` does not implement `Named`, required by type parameter `N`",
+            ]
+        );
+    }
+
+    #[test]
+    fn results() {
+        let found = diagnostics(
+            "class Parser {
+                let digit: Function = (c: Word) ~Result<Number, Word> {
+                    if (c == \"1\") { return Ok(1); }
+                    return Err(\"not a digit: \" + c);
+                }
+                let sum: Function = (a: Word, b: Word) ~Result<Number, Word> {
+                    let total: Number = this.digit(a)? + this.digit(b)?;
+                    this.digit(a);
+                    return Ok(total);
+                }
+                let show: Function = (c: Word) ~Word {
+                    let n: Number = this.digit(c)?;
+                    return match (this.digit(c)) { Ok(n) => \"ok\", Err(e) => e };
+                }
+                let partial: Function = (c: Word) ~Number {
+                    let wrong: Result<Number, Number> = Ok(1);
+                    let flag: Result<Boolean, Number> = Err(null);
+                    return match (wrong) { Ok(1) => 1, Err(e) => e, 2 => 2 };
+                }
+                let mixed: Function = (c: Word) ~Result<Number, Number> {
+                    return Ok(this.digit(c)? + 1);
+                }
+             }",
+        );
+        assert_eq!(
+            found,
+            vec![
+                "This `Result<Number, Word>` is ignored; handle it with `match` or propagate it with `?`",
+                "`?` can only be used in functions returning a `Result`",
+                "Value of type `null` may be null and cannot be used as `Number`",
+                "Expected a pattern of type `Result<Number, Number>`, found one of type `Number`",
+                "`?` cannot return an error of type `Word` from a function returning `Result<Number, Number>`",
+            ]
+        );
+    }
+
+    #[test]
+    fn assignment_and_constants() {
+        let found = diagnostics(
+            "class Person {
+                const name: Word;
+                const species: Word = \"human\";
+                let friend: Person?;
+                let init: Function = (name: Word) {
+                    this.name = name;
+                    this.species = \"robot\";
+                }
+                let rename: Function = (name: Word) {
+                    const limit: Number = 10;
+                    let count: Number = 0;
+                    count += 1;
+                    limit -= 1;
+                    this.name = name;
+                    this.friend = null;
+                }
+                let check: Function = (other: Person?) ~Word {
+                    if (other != null) {
+                        let first: Word = other.name;
+                        other = this.friend;
+                        return other.name;
+                    }
+                    if (other == null) { return \"nobody\"; }
+                    let later: Function = () ~Word { return other.name; };
+                    return other.name;
+                }
+             }",
+        );
+        assert_eq!(
+            found,
+            vec![
+                "Cannot assign to field `species`, it is a constant",
+                "Cannot assign to `limit`, it is a constant",
+                "Cannot assign to field `name`, it is a constant",
+                "Value of type `Person?` may be null; check it against null before using it",
+                "Value of type `Person?` may be null; check it against null before using it",
+            ]
+        );
+    }
+
+    #[test]
+    fn operator_overloading() {
+        let text = "class Money {
+                let cents: Number;
+                let add: Function = (other: Money) ~Money { return new Money(); }
+                let lessThan: Function = (other: Money) ~Boolean { return this.cents < other.cents; }
+                let equals: Function = (other: Money) ~Number { return 1; }
+             }
+             class Wallet {
+                let total: Money;
+                let spend: Function = (price: Money) {
+                    let sum: Money = this.total + price;
+                    let cheap: Boolean = price <= this.total;
+                    let same = price != this.total;
+                    let missing = price - this.total;
+                    let wrong = this.total + 1;
+                    let identical: Boolean = this == this;
+                    this.total += price;
+                }
+             }";
+        assert_eq!(
+            diagnostics(text),
+            vec![
+                "`Money.equals` has to return `Boolean` to be used as `!=`, found `Number`",
+                "`Money` has no method `subtract`, which `-` needs",
+                "`+` on `Money` takes `Money`, found `Number`",
+            ]
+        );
+        let program = parse_text(text);
+        let calls: Vec<(i32, String, bool, bool)> = operator_calls(&program)
+            .into_iter()
+            .map(|c| (c.lhs.1, c.method, c.swapped, c.negated))
+            .collect();
+        let expected = vec![
+            (10, "add", false, false),
+            (11, "lessThan", true, true),
+            (12, "equals", false, true),
+            (14, "add", false, false),
+            (16, "add", false, false),
+        ];
+        let expected: Vec<(i32, String, bool, bool)> = expected
+            .into_iter()
+            .map(|(line, method, swapped, negated)| (line, String::from(method), swapped, negated))
+            .collect();
+        assert_eq!(calls, expected);
+    }
+
+    #[test]
+    fn unknown_members() {
+        let program = parse_text(
+            "interface Shape { area() ~Number; }
+             class P { let n: Number = 1; }
+             class Main {
+                let main: Function = (s: Shape?) {
+                    let p: P = new P();
+                    let w: Word = p.m;
+                    p.foo(1, 2);
+                    let x: Number? = null;
+                    print(x?.y);
+                    print(s?.perimeter);
+                    print(p.n + [1].length);
+                }
+             }",
+        );
+        let found: Vec<(String, Vec<i32>)> = check(&program)
+            .into_iter()
+            .map(|d| (d.message, d.notes.iter().map(|n| n.line_number).collect()))
+            .collect();
+        let expected = vec![
+            ("`P` has no member `m`", vec![2]),
+            ("`P` has no member `foo`", vec![2]),
+            ("`Number` has no member `y`", vec![]),
+            ("`Shape` has no member `perimeter`", vec![1]),
+        ];
+        let expected: Vec<(String, Vec<i32>)> = expected
+            .into_iter()
+            .map(|(message, notes)| (String::from(message), notes))
+            .collect();
+        assert_eq!(found, expected);
+    }
+
+    #[test]
+    fn snippet_members_are_checked_in_each_class() {
+        let program = parse_text(
+            "snippet Greeter {
+                let hi: Function = () ~Word { return this.name; }
+             }
+             class Named { include Greeter; let name: Word = \"x\"; }
+             class Anonymous { include Greeter; }",
+        );
+        let program = codegen::include_snippets(&program);
+        let found: Vec<(String, i32)> = check(&program)
+            .into_iter()
+            .map(|d| (d.message, d.line_number))
+            .collect();
+        assert_eq!(
+            found,
+            vec![(String::from("`Anonymous` has no member `name`"), 2)]
+        );
+    }
+
+    #[test]
+    fn override_signatures() {
+        let found = diagnostics(
+            "class A {
+                let f: Function = (n: Number) ~Number { return n; }
+                let speak: Function = () ~Word { return \"a\"; }
+                let copy: Function = (other: B) ~A { return this; }
+             }
+             class B extends A {
+                let f: Function = (w: Word) ~Number { return 1; }
+                let speak: Function = () ~Number { return 1; }
+                let copy: Function = (other: A) ~B { return this; }
+             }",
+        );
+        assert_eq!(
+            found,
+            vec![
+                "`B.f` does not match `A.f` it overrides: expected (Number) ~Number, found (Word) ~Number",
+                "`B.speak` does not match `A.speak` it overrides: expected () ~Word, found () ~Number",
+            ]
+        );
+    }
+
+    #[test]
+    fn type_mismatches() {
+        let text = "class Person {
+                let age: Number = \"fifteen\";
+                let name: Word = \"Ada\";
+                let greet: Function = (other: Person, times: Number) ~Word {
+                    let total: Number = times * \"two\";
+                    let label: Word = this.name + times;
+                    let wrong: Word = this.name + other;
+                    if (times) { return this.name; }
+                    if (times > 1 && this.name) { return 1; }
+                    if (times == this.name) { return; }
+                    let same: Boolean = other == this;
+                    let negative: Number = -this.name;
+                    let shouted: Word = this.greet(other);
+                    this.greet(this.name, 1);
+                    times += \"x\";
+                    return this.name;
+                }
+             }";
+        assert_eq!(
+            diagnostics(text),
+            vec![
+                "Expected `Number`, found `Word`",
+                "Expected `Number` operands for `*`, found `Word`",
+                "Expected `Word` operands for `+`, found `Person`",
+                "Expected a `Boolean` condition, found `Number`",
+                "Expected `Boolean` operands for `&&`, found `Word`",
+                "Expected `Word`, found `Number`",
+                "`Number` and `Word` cannot be compared with `==`",
+                "Expected a `Word` to be returned",
+                "Expected `Number` operands for `-`, found `Word`",
+                "`greet` takes 2 argument(s) but 1 were given",
+                "Expected `Person`, found `Word`",
+                "`+=` gives `Word`, which cannot be assigned to `Number`",
+            ]
+        );
+    }
+
+    #[test]
+    fn list_literal_elements() {
+        let found = diagnostics(
+            "class Main {
+                let main: Function = () {
+                    let xs: List<Number> = [1, \"a\"];
+                    let ys = [1, \"a\", 2];
+                    let maybe: List<Number?>? = [null, 1];
+                    let nested: List<List<Number>> = [[1], [\"x\"]];
+                }
+             }",
+        );
+        assert_eq!(
+            found,
+            vec![
+                "Expected `Number`, found `Word`",
+                "Expected `Number`, found `Word`",
+                "Expected `Number`, found `Word`",
+            ]
+        );
+    }
+
+    #[test]
+    fn map_literal_entries() {
+        let found = diagnostics(
+            "class Main {
+                let main: Function = () {
+                    let m: Map<Word, Number> = { \"a\": 1, 2: 1 };
+                    let n = { \"a\": 1, [1]: 2 };
+                    let k = { [1]: 2 };
+                    let o = { \"a\": 1, \"b\": \"x\" };
+                    let p: Map<Word, Number> = { \"a\": 1, \"b\": \"x\" };
+                }
+             }",
+        );
+        assert_eq!(
+            found,
+            vec![
+                "Expected `Word`, found `Number`",
+                "Expected `Word`, found `List<Number>`",
+                "Map keys have to be Word, Number or Boolean, found `List<Number>`",
+                "Expected `Number`, found `Word`",
+                "Expected `Number`, found `Word`",
+            ]
+        );
+    }
+
+    #[test]
+    fn inference() {
+        let text = "class Person {
+                let age = 25 + 12;
+                let name = \"Bob\";
+                let older = (years: Number) { return this.age + years; }
+                let title: Function = (formal: Boolean) {
+                    if (formal) { return \"Mr \" + this.name; }
+                    return null;
+                }
+                let describe: Function = () ~Word {
+                    let add = (a: Number, b: Number) ~Number { return a + b; };
+                    let total = add(this.older(1), 2);
+                    let empty = [];
+                    let nothing = null;
+                    let title: Word = this.title(true);
+                    return this.name;
+                }
+                let mixed: Function = (flag: Boolean) {
+                    if (flag) { return 1; }
+                    return \"one\";
+                }
+             }";
+        assert_eq!(
+            diagnostics(text),
+            vec![
+                "Cannot infer the type of `empty` from its value; give it a type",
+                "Cannot infer the type of `nothing` from its value; give it a type",
+                "Value of type `Word?` may be null and cannot be used as `Word`",
+                "Cannot infer the return type from `Number`, `Word`; give it one with `~Type`",
+            ]
+        );
+        let program = parse_text(text);
+        let inferred: Vec<(i32, String)> = inferred_types(&program)
+            .into_iter()
+            .map(|i| (i.line_number, i.inferred.to_string()))
+            .collect();
+        let expected = vec![
+            (2, "Number"),
+            (3, "Word"),
+            // the binding and the function literal it is initialised with
+            (4, "(Number) ~Number"),
+            (4, "(Number) ~Number"),
+            (5, "(Boolean) ~Word?"),
+            (10, "(Number, Number) ~Number"),
+            (11, "Number"),
+        ];
+        let expected: Vec<(i32, String)> = expected
+            .into_iter()
+            .map(|(line, t)| (line, String::from(t)))
+            .collect();
+        assert_eq!(inferred, expected);
+    }
+}
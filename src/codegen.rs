@@ -0,0 +1,617 @@
+use super::checker;
+use super::checker::OperatorCall;
+use super::parser::ASTNode;
+use super::parser::Production;
+use super::resolver;
+use super::resolver::Resolver;
+use super::resolver::SymbolTable;
+
+/// Rewrites the names of classes, snippets, interfaces and enums used below
+/// `node` to their qualified form.
+fn qualify_names(node: &mut ASTNode, section: Option<&str>, symbols: &SymbolTable) {
+    let names_declaration = [
+        Production::Include,
+        Production::Extends,
+        Production::Implements,
+        Production::New,
+        Production::TypeDec,
+    ];
+    if names_declaration.iter().any(|p| node.is(p.clone())) {
+        if let Some((name, _)) = symbols.lookup(node.source, section, &node.name()) {
+            node.value = Some(name.chars().collect());
+        }
+    }
+    if node.is(Production::VariantPattern) {
+        let name = node.name();
+        let (enum_name, variant) = name.split_at(name.rfind('.').unwrap_or(0));
+        if let Some((qualified, _)) = symbols.lookup(node.source, section, enum_name) {
+            node.value = Some(format!("{}{}", qualified, variant).chars().collect());
+        }
+    }
+    // `Shapes.Shape` in an expression is a chain of member accesses that
+    // names an enum; it becomes a single identifier with the qualified name
+    if node.is(Production::Ident) || node.is(Production::Member) {
+        let named = node
+            .path()
+            .and_then(|path| symbols.lookup(node.source, section, &path));
+        if let Some((name, declaration)) = named {
+            if declaration.is(Production::EnumDec) {
+                *node = ASTNode {
+                    production: Some(Production::Ident),
+                    children: vec![],
+                    value: Some(name.chars().collect()),
+                    ..node.clone()
+                };
+                return;
+            }
+        }
+    }
+    for child in node.children.iter_mut() {
+        qualify_names(child, section, symbols);
+    }
+}
+
+/// Hoists the classes and snippets of every section to the top level under
+/// their qualified names, renames the private declarations of imported
+/// modules to theirs and qualifies the names using them to match, so later
+/// stages never see sections or modules. Imports are dropped too, the
+/// loader and resolver being done with them. Expects a program the
+/// resolver accepted.
+pub fn flatten_sections(program: &ASTNode) -> ASTNode {
+    let mut resolver = Resolver::new();
+    resolver.resolve(program);
+    let mut lowered = ASTNode {
+        children: vec![],
+        ..program.clone()
+    };
+    for declaration in &program.children {
+        if declaration.is(Production::Import) {
+            continue;
+        }
+        let (section, members) = if declaration.is(Production::SectionDec) {
+            (Some(declaration.name()), declaration.members().to_vec())
+        } else {
+            (None, vec![declaration.clone()])
+        };
+        for mut member in members {
+            let qualified = match &section {
+                Some(section) => format!("{}.{}", section, member.name()),
+                None => SymbolTable::qualified(&member),
+            };
+            member.value = Some(qualified.chars().collect());
+            qualify_names(&mut member, section.as_deref(), &resolver.symbols);
+            lowered.children.push(member);
+        }
+    }
+    lowered
+}
+
+/// Copies the members of every included snippet into the including class and
+/// drops the snippet declarations, so later stages only ever see classes.
+/// Expects a program the resolver accepted.
+pub fn include_snippets(program: &ASTNode) -> ASTNode {
+    let snippets: Vec<&ASTNode> = program
+        .children
+        .iter()
+        .filter(|d| d.is(Production::SnippetDec))
+        .collect();
+    let mut lowered = program.clone();
+    lowered.children.retain(|d| !d.is(Production::SnippetDec));
+    for class in lowered.children.iter_mut() {
+        if let Some(body) = class
+            .children
+            .iter_mut()
+            .find(|c| c.is(Production::BlockBody))
+        {
+            let mut members = vec![];
+            for member in &body.children {
+                if !member.is(Production::Include) {
+                    members.push(member.clone());
+                    continue;
+                }
+                let snippet = snippets.iter().find(|s| s.name() == member.name());
+                for snippet_member in snippet.map(|s| s.members()).unwrap_or(&[]) {
+                    let overridden = body
+                        .children
+                        .iter()
+                        .any(|m| !m.is(Production::Include) && m.name() == snippet_member.name());
+                    if !overridden {
+                        members.push(snippet_member.clone());
+                    }
+                }
+            }
+            body.children = members;
+        }
+    }
+    lowered
+}
+
+/// Method slot of a vtable: the method name and the class whose
+/// implementation instances of the laid out class run.
+#[derive(Debug, PartialEq, Clone)]
+pub struct VtableEntry {
+    pub method: String,
+    pub class: String,
+}
+
+/// Dispatch table of one interface a class implements: for every method of
+/// the interface, in declaration order, its index in the class's vtable.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Itable {
+    pub interface: String,
+    pub slots: Vec<usize>,
+}
+
+/// Memory layout of a class for the backends: the slots every instance
+/// carries, and the vtable its methods are dispatched through.
+///
+/// Generic classes and functions are compiled once rather than per type
+/// argument: type arguments are erased and every slot holds a boxed value,
+/// so `Box<Number>` and `Box<Word>` share this layout.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ClassLayout {
+    pub name: String,
+    pub parent: Option<String>,
+    /// Instance fields in slot order, inherited fields first so that an
+    /// instance can be used wherever its parent is expected.
+    pub fields: Vec<String>,
+    /// Inherited methods keep the index they have in the parent's vtable,
+    /// overriding only replaces the implementing class; new methods are
+    /// appended.
+    pub vtable: Vec<VtableEntry>,
+    /// Tables for calling through interface typed values, inherited ones
+    /// included.
+    pub itables: Vec<Itable>,
+}
+
+fn layout_of(
+    name: &str,
+    classes: &[&ASTNode],
+    interfaces: &[&ASTNode],
+    done: &mut Vec<ClassLayout>,
+) -> ClassLayout {
+    if let Some(layout) = done.iter().find(|l| l.name == name) {
+        return layout.clone();
+    }
+    let class = classes
+        .iter()
+        .find(|c| c.name() == name)
+        .expect("layout of an unknown class");
+    let parent = class.find(Production::Extends).map(|e| e.name());
+    let mut layout = match &parent {
+        Some(parent) => ClassLayout {
+            name: class.name(),
+            parent: Some(parent.clone()),
+            ..layout_of(parent, classes, interfaces, done)
+        },
+        None => ClassLayout {
+            name: class.name(),
+            parent: None,
+            fields: vec![],
+            vtable: vec![],
+            itables: vec![],
+        },
+    };
+    for member in class.members() {
+        if !member.is_method() {
+            layout.fields.push(member.name());
+            continue;
+        }
+        let entry = VtableEntry {
+            method: member.name(),
+            class: class.name(),
+        };
+        match layout.vtable.iter_mut().find(|e| e.method == entry.method) {
+            Some(inherited) => *inherited = entry,
+            None => layout.vtable.push(entry),
+        }
+    }
+    for implements in class
+        .children
+        .iter()
+        .filter(|c| c.is(Production::Implements))
+    {
+        let interface = interfaces.iter().find(|i| i.name() == implements.name());
+        let slots = interface
+            .map(|i| i.members())
+            .unwrap_or(&[])
+            .iter()
+            .map(|required| {
+                layout
+                    .vtable
+                    .iter()
+                    .position(|e| e.method == required.name())
+                    .expect("conformance is checked before layout")
+            })
+            .collect();
+        layout.itables.retain(|t| t.interface != implements.name());
+        layout.itables.push(Itable {
+            interface: implements.name(),
+            slots,
+        });
+    }
+    done.push(layout.clone());
+    layout
+}
+
+/// Computes the layout of every class of a program whose sections have been
+/// flattened and whose snippets have been included.
+pub fn layouts(program: &ASTNode) -> Vec<ClassLayout> {
+    let of = |production: Production| -> Vec<&ASTNode> {
+        program
+            .children
+            .iter()
+            .filter(|d| d.is(production.clone()))
+            .collect()
+    };
+    let classes = of(Production::ClassDec);
+    let interfaces = of(Production::InterfaceDec);
+    let mut done = vec![];
+    for class in &classes {
+        layout_of(&class.name(), &classes, &interfaces, &mut done);
+    }
+    done
+}
+
+/// Variant of an enum and the tag identifying it at runtime.
+#[derive(Debug, PartialEq, Clone)]
+pub struct VariantLayout {
+    pub name: String,
+    pub tag: usize,
+    /// Number of values the variant carries.
+    pub fields: usize,
+}
+
+/// Runtime representation of an enum: a value is the tag of its variant
+/// followed by the values that variant carries.
+#[derive(Debug, PartialEq, Clone)]
+pub struct EnumLayout {
+    pub name: String,
+    /// Variants in declaration order, tagged from 0.
+    pub variants: Vec<VariantLayout>,
+    /// Whether no variant carries values. Such a value is nothing but its
+    /// tag, so a match over it is lowered to a jump table indexed by the tag
+    /// rather than a chain of tests.
+    pub unit_only: bool,
+}
+
+/// Layout of the built in `Result`, an enum of `Ok(value)` and
+/// `Err(error)`. `result?` is lowered to a test of the tag that returns the
+/// result unchanged from the enclosing function when it is an `Err`, and
+/// otherwise leaves the value of the `Ok`.
+fn result_layout() -> EnumLayout {
+    let variant = |name: &str, tag| VariantLayout {
+        name: String::from(name),
+        tag,
+        fields: 1,
+    };
+    EnumLayout {
+        name: String::from("Result"),
+        variants: vec![variant("Ok", 0), variant("Err", 1)],
+        unit_only: false,
+    }
+}
+
+/// Computes the layout of every enum of a program whose sections have been
+/// flattened, followed by that of `Result`.
+pub fn enum_layouts(program: &ASTNode) -> Vec<EnumLayout> {
+    let mut layouts: Vec<EnumLayout> = program
+        .children
+        .iter()
+        .filter(|d| d.is(Production::EnumDec))
+        .map(|declaration| {
+            let variants: Vec<VariantLayout> = declaration
+                .members()
+                .iter()
+                .enumerate()
+                .map(|(tag, variant)| VariantLayout {
+                    name: variant.name(),
+                    tag,
+                    fields: variant.children.len(),
+                })
+                .collect();
+            EnumLayout {
+                name: declaration.name(),
+                unit_only: variants.iter().all(|v| v.fields == 0),
+                variants,
+            }
+        })
+        .collect();
+    layouts.push(result_layout());
+    layouts
+}
+
+/// Runtime representation of a function literal that captures locals: a
+/// closure pairs its code with an environment record.
+///
+/// Captured variables are shared by reference (see `resolver::Capture`), so
+/// they cannot stay in the frame of the call declaring them. Each one is
+/// allocated in a heap cell instead, which that call and its closures all
+/// reach the variable through; the environment record of a closure holds
+/// the cells it captured. A closure that only hands a variable on to one
+/// nested in it still carries the cell.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ClosureLayout {
+    pub source: usize,
+    pub line_number: i32,
+    pub start_col: usize,
+    /// Cells of the environment record in slot order.
+    pub environment: Vec<String>,
+}
+
+/// Computes the layout of every capturing function literal of a program
+/// whose sections have been flattened. A literal included into several
+/// classes through a snippet is compiled once.
+pub fn closure_layouts(program: &ASTNode) -> Vec<ClosureLayout> {
+    let mut layouts: Vec<ClosureLayout> = vec![];
+    for capture in resolver::captures(program) {
+        let layout = ClosureLayout {
+            source: capture.source,
+            line_number: capture.line_number,
+            start_col: capture.start_col,
+            environment: capture.names,
+        };
+        if !layouts.contains(&layout) {
+            layouts.push(layout);
+        }
+    }
+    layouts
+}
+
+/// `receiver.method(argument)`, located at `at`.
+fn method_call(at: &ASTNode, receiver: ASTNode, method: &str, argument: ASTNode) -> ASTNode {
+    let member = ASTNode {
+        production: Some(Production::Member),
+        value: Some(method.chars().collect()),
+        operator: None,
+        ..at.clone()
+    }
+    .with_children(vec![receiver]);
+    ASTNode {
+        production: Some(Production::Call),
+        value: None,
+        operator: None,
+        ..at.clone()
+    }
+    .with_children(vec![member, argument])
+}
+
+fn lower_operator(node: &mut ASTNode, calls: &[OperatorCall]) {
+    for child in node.children.iter_mut() {
+        lower_operator(child, calls);
+    }
+    if node.children.len() != 2 || node.operator.is_none() {
+        return;
+    }
+    let (lhs, rhs) = (node.children[0].location(), node.children[1].location());
+    let call = match calls.iter().find(|c| c.lhs == lhs && c.rhs == rhs) {
+        Some(call) => call,
+        None => return,
+    };
+    let rhs = node.children.pop().unwrap();
+    let lhs = node.children.pop().unwrap();
+    if node.is(Production::Assign) {
+        let value = method_call(node, lhs.clone(), &call.method, rhs);
+        node.operator = None;
+        node.children = vec![lhs, value];
+        return;
+    }
+    let (receiver, argument) = if call.swapped { (rhs, lhs) } else { (lhs, rhs) };
+    let lowered = method_call(node, receiver, &call.method, argument);
+    *node = if call.negated {
+        // the language has no `!`, so `!a.equals(b)` is spelt with `==`
+        let false_literal = ASTNode {
+            production: Some(Production::BooleanLiteral),
+            value: Some("false".chars().collect()),
+            operator: None,
+            ..node.clone()
+        }
+        .with_children(vec![]);
+        ASTNode {
+            operator: Some(vec!['=', '=']),
+            ..node.clone()
+        }
+        .with_children(vec![lowered, false_literal])
+    } else {
+        lowered
+    };
+}
+
+/// Rewrites the operators the checker resolved to methods of their
+/// operands into calls of those methods, `a + b` into `a.add(b)` and
+/// `a >= b` into `a.lessThan(b) == false`, so backends only ever apply
+/// operators to built in values. A compound assignment `x += y` becomes
+/// `x = x.add(y)`, evaluating the object of a field target twice. Expects
+/// a program the checker accepted.
+pub fn lower_operators(program: &ASTNode) -> ASTNode {
+    let calls = checker::operator_calls(program);
+    let mut lowered = program.clone();
+    lower_operator(&mut lowered, &calls);
+    lowered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_section_text;
+    use crate::parser::parse_text;
+    use crate::section;
+
+    #[test]
+    fn operators_lowered_to_method_calls() {
+        let text = "class Money {
+                let add: Function = (other: Money) ~Money { return other; }
+                let lessThan: Function = (other: Money) ~Boolean { return true; }
+                let sum: Function = (a: Money, b: Money) ~Boolean {
+                    let total: Money = a + b;
+                    total += a;
+                    return a > b;
+                }
+             }";
+        let program = parse_text(text);
+        let lowered = lower_operators(&program);
+        let body = &lowered.children[0].members()[2]
+            .initializer()
+            .unwrap()
+            .find(Production::BlockBody)
+            .unwrap()
+            .children;
+        let total = body[0].initializer().unwrap();
+        assert!(total.is(Production::Call));
+        assert_eq!(total.children[0].name(), "add");
+        assert_eq!(total.children[0].children[0].name(), "a");
+        assert_eq!(body[1].operator, None);
+        assert_eq!(body[1].children[1].children[0].name(), "add");
+        let compared = &body[2].children[0];
+        assert_eq!(compared.children[0].name(), "lessThan");
+        assert_eq!(compared.children[0].children[0].name(), "b");
+        assert_eq!(compared.children[1].name(), "a");
+    }
+
+    #[test]
+    fn sections_are_flattened_with_qualified_names() {
+        let text =
+            "section Shapes { snippet Round { let r: Number; } class Circle { include Round; } }";
+        let program = parse_section_text(text);
+        let lowered = include_snippets(&flatten_sections(&program));
+        assert_eq!(lowered.children.len(), 1);
+        assert_eq!(lowered.children[0].name(), "Shapes.Circle");
+        assert_eq!(lowered.children[0].members()[0].name(), "r");
+    }
+
+    #[test]
+    fn class_layout() {
+        let text = "snippet S { let tag: Word; let describe: Function = () { } }
+                    class C { let a: Number; include S; let init: Function = () { this.a = 1; } let b: Boolean; }";
+        let program = parse_text(text);
+        let layouts = layouts(&include_snippets(&program));
+        assert_eq!(layouts[0].fields, vec!["a", "tag", "b"]);
+        let methods: Vec<&str> = layouts[0]
+            .vtable
+            .iter()
+            .map(|e| e.method.as_str())
+            .collect();
+        assert_eq!(methods, vec!["describe", "init"]);
+    }
+
+    #[test]
+    fn inherited_layout_and_vtable() {
+        let text = "class B extends A { let c: Number; let speak: Function = () { } let run: Function = () { } }
+                    class A { let a: Number; let speak: Function = () { } let walk: Function = () { } }";
+        let program = parse_text(text);
+        let layouts = layouts(&program);
+        assert_eq!(layouts[0].name, "A");
+        let b = &layouts[1];
+        assert_eq!(b.parent, Some(String::from("A")));
+        assert_eq!(b.fields, vec!["a", "c"]);
+        let vtable: Vec<(&str, &str)> = b
+            .vtable
+            .iter()
+            .map(|e| (e.method.as_str(), e.class.as_str()))
+            .collect();
+        assert_eq!(vtable, vec![("speak", "B"), ("walk", "A"), ("run", "B")]);
+    }
+
+    #[test]
+    fn interface_tables() {
+        let text = "interface Runner { run(); walk(); }
+                    class A implements Runner { let walk: Function = () { } let run: Function = () { } }
+                    class B extends A { let jump: Function = () { } let run: Function = () { } }";
+        let program = parse_text(text);
+        let layouts = layouts(&program);
+        let expected = vec![Itable {
+            interface: String::from("Runner"),
+            slots: vec![1, 0],
+        }];
+        assert_eq!(layouts[0].itables, expected);
+        assert_eq!(layouts[1].itables, expected);
+    }
+
+    #[test]
+    fn snippet_members_are_copied_into_class() {
+        let text = "snippet S { let a: Number = 1; let b: Number = 2; }
+                    class C { include S; let b: Number = 3; }";
+        let program = parse_text(text);
+        let lowered = include_snippets(&program);
+        assert_eq!(lowered.children.len(), 1);
+        let names: Vec<String> = lowered.children[0]
+            .members()
+            .iter()
+            .map(|m| m.name())
+            .collect();
+        assert_eq!(names, vec!["a", "b"]);
+        let b = &lowered.children[0].members()[1];
+        assert_eq!(b.children[1].name(), "3");
+    }
+
+    #[test]
+    fn private_names_of_modules_are_qualified_by_source() {
+        let mut lib = parse_text("class Helper { } export class Util { let helper: Helper; }");
+        lib.set_source(1);
+        let program = section::merge(vec![
+            lib,
+            parse_text("class Helper { let util: Util; let made: Helper = new Helper(); }"),
+        ]);
+        let lowered = flatten_sections(&program);
+        let names: Vec<String> = lowered.children.iter().map(|d| d.name()).collect();
+        assert_eq!(names, vec!["Helper@1", "Util", "Helper"]);
+        assert_eq!(
+            lowered.children[1].members()[0].children[0].name(),
+            "Helper@1"
+        );
+        let made = &lowered.children[2].members()[1];
+        assert_eq!(made.children[0].name(), "Helper");
+        assert_eq!(made.children[1].name(), "Helper");
+    }
+
+    #[test]
+    fn enums_in_sections_are_qualified_and_laid_out() {
+        let text = "section Geo {
+                        enum Shape { Circle(Number), Empty }
+                        enum Day { Mon, Tue }
+                        class Area {
+                            let of: Function = (s: Shape) ~Number {
+                                return match (s) { Shape.Circle(r) => r, Geo.Shape.Empty => 0 };
+                            }
+                            let zero: Shape = Shape.Circle(0);
+                        }
+                    }";
+        let program = parse_section_text(text);
+        let lowered = flatten_sections(&program);
+        let area = lowered.children[2].members();
+        let body = area[0]
+            .initializer()
+            .unwrap()
+            .find(Production::BlockBody)
+            .unwrap();
+        let arms = &body.children[0].children[0].children[1..];
+        assert_eq!(arms[0].children[0].name(), "Geo.Shape.Circle");
+        assert_eq!(arms[1].children[0].name(), "Geo.Shape.Empty");
+        let construction = &area[1].children[1];
+        assert_eq!(construction.children[0].children[0].name(), "Geo.Shape");
+        assert!(construction.children[0].children[0].is(Production::Ident));
+        let layouts = enum_layouts(&lowered);
+        assert_eq!(layouts[0].name, "Geo.Shape");
+        assert_eq!(layouts[0].variants[0].fields, 1);
+        assert!(!layouts[0].unit_only);
+        assert_eq!(layouts[1].variants[1].tag, 1);
+        assert!(layouts[1].unit_only);
+        assert_eq!(layouts.last().unwrap().name, "Result");
+    }
+
+    #[test]
+    fn closures_included_twice_are_laid_out_once() {
+        let text = "snippet Adder {
+                        let adder: Function = (n: Number) ~Function {
+                            return (m: Number) ~Number { return n + m; };
+                        }
+                    }
+                    class A { include Adder; }
+                    class B { include Adder; }";
+        let program = parse_text(text);
+        let layouts = closure_layouts(&include_snippets(&program));
+        assert_eq!(layouts.len(), 1);
+        assert_eq!(layouts[0].line_number, 3);
+        assert_eq!(layouts[0].environment, vec!["n"]);
+    }
+}
@@ -0,0 +1,386 @@
+use super::cfg::Cfg;
+use super::cfg::Terminator;
+use super::diagnostic::Diagnostic;
+use super::parser::ASTNode;
+use super::parser::Location;
+use super::parser::Production;
+use super::resolver;
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::collections::VecDeque;
+
+/// Locals of one function declared without a value that may not have been
+/// assigned yet, by the location of their declaration.
+type Unassigned = BTreeSet<Location>;
+
+/// Definite assignment analysis of one function: a local declared without
+/// a value has to be assigned on every path to a use of it, much as in
+/// Java. Assignments made in function literals do not count, as the
+/// literal may never be called, but uses in them do, since it may be
+/// called right away.
+struct Analysis<'a, 'r> {
+    cfg: Cfg<'a>,
+    /// Declarations of the locals, parameters and loop variables used in
+    /// the program, by where they are used.
+    references: &'r HashMap<Location, Location>,
+    /// Uninitialised declarations of the function by location.
+    declarations: HashMap<Location, &'a ASTNode>,
+    /// What may be unassigned when each block is left.
+    leaving: Vec<Unassigned>,
+    reported: Vec<Location>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+/// Identifiers read by `node`, in function literals nested in it too. The
+/// target of a plain assignment is written rather than read.
+fn reads<'a>(node: &'a ASTNode, found: &mut Vec<&'a ASTNode>) {
+    match &node.production {
+        Some(Production::Ident) => found.push(node),
+        Some(Production::Assign)
+            if node.operator.is_none() && node.children[0].is(Production::Ident) =>
+        {
+            reads(&node.children[1], found)
+        }
+        _ => {
+            for child in &node.children {
+                reads(child, found);
+            }
+        }
+    }
+}
+
+/// Expressions a terminator evaluates before control leaves its block.
+fn evaluated<'a>(terminator: &Terminator<'a>) -> Option<&'a ASTNode> {
+    match terminator {
+        Terminator::Branch(node, ..)
+        | Terminator::Iterate(node, ..)
+        | Terminator::Match(node, _) => node.children.first(),
+        Terminator::Return(node) => node.children.first(),
+        Terminator::Goto(_) | Terminator::End => None,
+    }
+}
+
+impl<'a, 'r> Analysis<'a, 'r> {
+    /// Runs `block` from the state it is entered in, calling `read` for
+    /// every identifier read with what may be unassigned at that point.
+    /// Returns what may be unassigned when the block is left.
+    fn run_block(
+        &self,
+        block: usize,
+        mut unassigned: Unassigned,
+        read: &mut dyn FnMut(&'a ASTNode, &Unassigned),
+    ) -> Unassigned {
+        let block = &self.cfg.blocks[block];
+        for statement in &block.statements {
+            let mut found = vec![];
+            reads(statement, &mut found);
+            for ident in found {
+                read(ident, &unassigned);
+            }
+            let assigned = match &statement.production {
+                Some(Production::Vardec)
+                    if self.declarations.contains_key(&statement.location()) =>
+                {
+                    unassigned.insert(statement.location());
+                    continue;
+                }
+                Some(Production::Assign) if statement.operator.is_none() => {
+                    self.references.get(&statement.children[0].location())
+                }
+                _ => None,
+            };
+            if let Some(assigned) = assigned {
+                unassigned.remove(assigned);
+            }
+        }
+        if let Some(evaluated) = evaluated(&block.terminator) {
+            let mut found = vec![];
+            reads(evaluated, &mut found);
+            for ident in found {
+                read(ident, &unassigned);
+            }
+        }
+        unassigned
+    }
+
+    /// What may be unassigned when entering `block`.
+    fn entering(
+        &self,
+        block: usize,
+        predecessors: &[Vec<usize>],
+        reachable: &[bool],
+    ) -> Unassigned {
+        predecessors[block]
+            .iter()
+            .filter(|&&p| reachable[p])
+            .flat_map(|&p| self.leaving[p].iter().cloned())
+            .collect()
+    }
+
+    /// Works out what may be unassigned when each block is left.
+    fn solve(&mut self, predecessors: &[Vec<usize>], reachable: &[bool]) {
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for block in 0..self.cfg.blocks.len() {
+                if !reachable[block] {
+                    continue;
+                }
+                let entering = self.entering(block, predecessors, reachable);
+                let leaving = self.run_block(block, entering, &mut |_, _| {});
+                if leaving != self.leaving[block] {
+                    self.leaving[block] = leaving;
+                    changed = true;
+                }
+            }
+        }
+    }
+
+    /// Notes describing a path from the declaration `declared` to `block`
+    /// along which it is never assigned, one for each decision taken on
+    /// the way.
+    fn path(
+        &self,
+        declared: Location,
+        block: usize,
+        predecessors: &[Vec<usize>],
+    ) -> Vec<(String, &'a ASTNode)> {
+        let declares = |b: usize| {
+            self.cfg.blocks[b]
+                .statements
+                .iter()
+                .any(|s| s.location() == declared)
+        };
+        // search backwards for the nearest block declaring the variable
+        let mut next: HashMap<usize, usize> = HashMap::new();
+        let mut pending = VecDeque::from(vec![block]);
+        let mut start = None;
+        while let Some(b) = pending.pop_front() {
+            if declares(b) {
+                start = Some(b);
+                break;
+            }
+            for &p in &predecessors[b] {
+                if self.leaving[p].contains(&declared) && p != block && !next.contains_key(&p) {
+                    next.insert(p, b);
+                    pending.push_back(p);
+                }
+            }
+        }
+        let mut notes = vec![];
+        let mut current = match start {
+            Some(start) => start,
+            None => return notes,
+        };
+        while let Some(&to) = next.get(&current) {
+            let note = match &self.cfg.blocks[current].terminator {
+                Terminator::Branch(node, then, _) => Some((
+                    String::from(if to == *then {
+                        "when this condition holds"
+                    } else {
+                        "when this condition does not hold"
+                    }),
+                    &node.children[0],
+                )),
+                Terminator::Iterate(node, body, _) => Some((
+                    String::from(if to == *body {
+                        "going into this loop"
+                    } else {
+                        "when this loop is left"
+                    }),
+                    *node,
+                )),
+                Terminator::Match(node, arms) => arms.iter().position(|&arm| arm == to).map(|i| {
+                    (
+                        String::from("when this arm is taken"),
+                        &node.children[i + 1],
+                    )
+                }),
+                _ => None,
+            };
+            notes.extend(note);
+            current = to;
+        }
+        notes
+    }
+
+    fn check(&mut self) {
+        let predecessors = self.cfg.predecessors();
+        let reachable = self.cfg.reachable();
+        self.solve(&predecessors, &reachable);
+        for block in 0..self.cfg.blocks.len() {
+            if !reachable[block] {
+                continue;
+            }
+            let entering = self.entering(block, &predecessors, &reachable);
+            let mut unassigned_reads = vec![];
+            self.run_block(block, entering, &mut |ident, unassigned| {
+                if let Some(declared) = self.references.get(&ident.location()) {
+                    if unassigned.contains(declared) {
+                        unassigned_reads.push((ident, *declared));
+                    }
+                }
+            });
+            for (ident, declared) in unassigned_reads {
+                if self.reported.contains(&declared) {
+                    continue;
+                }
+                self.reported.push(declared);
+                let declaration = self.declarations[&declared];
+                let mut diagnostic = Diagnostic::at(
+                    format!(
+                        "`{}` may be used before it is assigned a value",
+                        ident.name()
+                    ),
+                    ident,
+                )
+                .with_note(String::from("declared here without a value"), declaration);
+                for (message, node) in self.path(declared, block, &predecessors) {
+                    diagnostic = diagnostic.with_note(message, node);
+                }
+                self.diagnostics.push(diagnostic);
+            }
+        }
+    }
+}
+
+fn check_functions(
+    node: &ASTNode,
+    references: &HashMap<Location, Location>,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    if node.is(Production::FunctionDec) {
+        let (cfg, _) = Cfg::build(node);
+        let declarations = cfg
+            .blocks
+            .iter()
+            .flat_map(|b| b.statements.iter())
+            .filter(|s| s.is(Production::Vardec) && s.initializer().is_none())
+            .map(|s| (s.location(), *s))
+            .collect();
+        let mut analysis = Analysis {
+            leaving: vec![Unassigned::new(); cfg.blocks.len()],
+            cfg,
+            references,
+            declarations,
+            reported: vec![],
+            diagnostics: vec![],
+        };
+        analysis.check();
+        diagnostics.extend(analysis.diagnostics);
+    }
+    for child in &node.children {
+        check_functions(child, references, diagnostics);
+    }
+}
+
+/// Reports locals declared without a value that may be used before they
+/// are assigned one, see `Analysis`.
+pub fn check(program: &ASTNode) -> Vec<Diagnostic> {
+    let references = resolver::references(program)
+        .into_iter()
+        .map(|r| ((r.source, r.line_number, r.start_col), r.declaration))
+        .collect();
+    let mut diagnostics = vec![];
+    check_functions(program, &references, &mut diagnostics);
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_text;
+
+    fn diagnostics(text: &str) -> Vec<Diagnostic> {
+        check(&parse_text(text))
+    }
+
+    #[test]
+    fn assigned_on_every_path() {
+        let found = diagnostics(
+            "class A { let f: Function = (flag: Boolean, xs: List<Number>) ~Number {
+                let a: Number;
+                if (flag) { a = 1; } else { a = 2; }
+                let b: Number;
+                match (flag) { true => { b = 1; } false => { return a; } }
+                let c: Number;
+                c = a + b;
+                let d: Number;
+                for (x in xs) { d = x; break; }
+                let e: Number;
+                let later: Function = () { e = 1; };
+                return a + b + c;
+             } }",
+        );
+        assert_eq!(found, vec![]);
+    }
+
+    #[test]
+    fn names_the_path_leaving_a_variable_unassigned() {
+        let found = diagnostics(
+            "class A { let f: Function = (flag: Boolean, xs: List<Number>) ~Number {
+                let a: Number;
+                if (flag) { a = 1; }
+                let b: Number;
+                for (x in xs) { b = x; }
+                let c: Number;
+                let read: Function = () ~Number { return c; };
+                let d: Number;
+                if (flag) { d = 1; } else { if (a > 1) { return 1; } }
+                print(a + d);
+                return b;
+             } }",
+        );
+        type Found = (String, i32, Vec<(String, i32)>);
+        let found: Vec<Found> = found
+            .into_iter()
+            .map(|d| {
+                let notes = d
+                    .notes
+                    .into_iter()
+                    .map(|n| (n.message, n.line_number))
+                    .collect();
+                (d.message, d.line_number, notes)
+            })
+            .collect();
+        let note = |message: &str, line: i32| (String::from(message), line);
+        assert_eq!(
+            found,
+            vec![
+                (
+                    String::from("`c` may be used before it is assigned a value"),
+                    7,
+                    vec![note("declared here without a value", 6)],
+                ),
+                (
+                    String::from("`a` may be used before it is assigned a value"),
+                    9,
+                    vec![
+                        note("declared here without a value", 2),
+                        note("when this condition does not hold", 3),
+                        note("when this loop is left", 5),
+                        note("when this condition does not hold", 9),
+                    ],
+                ),
+                (
+                    String::from("`d` may be used before it is assigned a value"),
+                    10,
+                    vec![
+                        note("declared here without a value", 8),
+                        note("when this condition does not hold", 9),
+                        note("when this condition does not hold", 9),
+                    ],
+                ),
+                (
+                    String::from("`b` may be used before it is assigned a value"),
+                    11,
+                    vec![
+                        note("declared here without a value", 4),
+                        note("when this loop is left", 5),
+                        note("when this condition holds", 9),
+                    ],
+                ),
+            ]
+        );
+    }
+}
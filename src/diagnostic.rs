@@ -0,0 +1,123 @@
+use super::parser::ASTNode;
+
+/// Secondary location attached to a diagnostic, e.g. the earlier declaration
+/// a duplicate clashes with.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Note {
+    pub message: String,
+    pub source: usize,
+    pub line_number: i32,
+    pub start_col: usize,
+}
+
+/// How bad a diagnostic is. Only errors stop the compiler.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// A problem found while compiling. `source` indexes the list of files
+/// handed to the compiler.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub source: usize,
+    pub line_number: i32,
+    pub start_col: usize,
+    pub notes: Vec<Note>,
+}
+
+impl Diagnostic {
+    pub fn error(message: String, line_number: i32, start_col: usize) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Error,
+            message,
+            source: 0,
+            line_number,
+            start_col,
+            notes: vec![],
+        }
+    }
+
+    /// Error located at `node`.
+    pub fn at(message: String, node: &ASTNode) -> Diagnostic {
+        Diagnostic {
+            source: node.source,
+            ..Diagnostic::error(message, node.line_number, node.start_col)
+        }
+    }
+
+    /// Warning located at `node`.
+    pub fn warning_at(message: String, node: &ASTNode) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Warning,
+            ..Diagnostic::at(message, node)
+        }
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+
+    /// Adds a note located at `node`.
+    pub fn with_note(mut self, message: String, node: &ASTNode) -> Diagnostic {
+        self.notes.push(Note {
+            message,
+            source: node.source,
+            line_number: node.line_number,
+            start_col: node.start_col,
+        });
+        self
+    }
+
+    /// Adds the notes of a stack trace, innermost call first. A call made
+    /// over and over from the same place, as deep recursion gives, is noted
+    /// once along with how many more times it was made.
+    pub fn with_trace(mut self, trace: Vec<Note>) -> Diagnostic {
+        let mut trace = trace.into_iter().peekable();
+        while let Some(note) = trace.next() {
+            let mut repeated = 0;
+            while trace.peek() == Some(&note) {
+                trace.next();
+                repeated += 1;
+            }
+            let summary = Note {
+                message: format!("... repeated {} more time(s)", repeated),
+                ..note.clone()
+            };
+            self.notes.push(note);
+            if repeated > 0 {
+                self.notes.push(summary);
+            }
+        }
+        self
+    }
+
+    /// Formats the diagnostic with the names of the files it points into.
+    pub fn render(&self, files: &[String]) -> String {
+        let file = |source: usize| files.get(source).map(|f| f.as_str()).unwrap_or("<input>");
+        let mut text = format!(
+            "{}:{}:{}: {}: {}",
+            file(self.source),
+            self.line_number,
+            self.start_col,
+            match self.severity {
+                Severity::Error => "error",
+                Severity::Warning => "warning",
+            },
+            self.message
+        );
+        for note in &self.notes {
+            text.push_str(&format!(
+                "\n  {}:{}:{}: note: {}",
+                file(note.source),
+                note.line_number,
+                note.start_col,
+                note.message
+            ));
+        }
+        text
+    }
+}
@@ -0,0 +1,345 @@
+use super::diagnostic::Diagnostic;
+use super::parser::ASTNode;
+use super::parser::Location;
+use super::parser::Production;
+use super::resolver;
+use std::collections::HashMap;
+
+/// Value of an expression known at compile time.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Constant {
+    Number(i64),
+    Word(String),
+    Boolean(bool),
+}
+
+impl Constant {
+    /// Value of a literal.
+    fn of(node: &ASTNode) -> Option<Constant> {
+        match &node.production {
+            Some(Production::NumberLiteral) => node.name().parse().ok().map(Constant::Number),
+            Some(Production::WordLiteral) => Some(Constant::Word(node.name())),
+            Some(Production::BooleanLiteral) => Some(Constant::Boolean(node.name() == "true")),
+            _ => None,
+        }
+    }
+
+    /// The value as text, as `+` joins it to a `Word`.
+    fn text(&self) -> String {
+        match self {
+            Constant::Number(number) => number.to_string(),
+            Constant::Word(word) => word.clone(),
+            Constant::Boolean(boolean) => boolean.to_string(),
+        }
+    }
+
+    /// Literal standing for the value, located at `at`.
+    fn literal(&self, at: &ASTNode) -> ASTNode {
+        let production = match self {
+            Constant::Number(_) => Production::NumberLiteral,
+            Constant::Word(_) => Production::WordLiteral,
+            Constant::Boolean(_) => Production::BooleanLiteral,
+        };
+        ASTNode {
+            production: Some(production),
+            children: vec![],
+            value: Some(self.text().chars().collect()),
+            operator: None,
+            ..at.clone()
+        }
+    }
+}
+
+/// Applies a built in binary operator to two constants. `Err` holds the
+/// message of an error evaluation runs into, `Ok(None)` means the operator
+/// does not apply to them.
+fn evaluate(operator: &str, lhs: &Constant, rhs: &Constant) -> Result<Option<Constant>, String> {
+    use Constant::*;
+    let overflow = || {
+        format!(
+            "`{} {} {}` overflows a `Number`",
+            lhs.text(),
+            operator,
+            rhs.text()
+        )
+    };
+    let value = match (operator, lhs, rhs) {
+        ("/", Number(_), Number(0)) => return Err(String::from("Division by zero")),
+        ("+", Number(a), Number(b)) => Number(a.checked_add(*b).ok_or_else(overflow)?),
+        ("-", Number(a), Number(b)) => Number(a.checked_sub(*b).ok_or_else(overflow)?),
+        ("*", Number(a), Number(b)) => Number(a.checked_mul(*b).ok_or_else(overflow)?),
+        ("/", Number(a), Number(b)) => Number(a.checked_div(*b).ok_or_else(overflow)?),
+        ("+", Word(_), _) | ("+", _, Word(_)) => Word(lhs.text() + &rhs.text()),
+        ("<", Number(a), Number(b)) => Boolean(a < b),
+        (">", Number(a), Number(b)) => Boolean(a > b),
+        ("<=", Number(a), Number(b)) => Boolean(a <= b),
+        (">=", Number(a), Number(b)) => Boolean(a >= b),
+        ("&&", Boolean(a), Boolean(b)) => Boolean(*a && *b),
+        ("||", Boolean(a), Boolean(b)) => Boolean(*a || *b),
+        ("==", ..) => Boolean(lhs == rhs),
+        ("!=", ..) => Boolean(lhs != rhs),
+        _ => return Ok(None),
+    };
+    Ok(Some(value))
+}
+
+/// Folds the constant expressions of a program, replacing each with the
+/// literal it evaluates to. Constants are read through `const` locals and
+/// through `this` from `const` fields with a constant value.
+struct Folder {
+    /// Declarations of the locals used in the program, by where they are
+    /// used.
+    references: HashMap<Location, Location>,
+    /// Values of the `const` locals folded so far, by the location of
+    /// their declaration.
+    locals: HashMap<Location, Constant>,
+    /// Values of `const` fields, by class and field name.
+    fields: HashMap<(String, String), Constant>,
+    /// Parent of each class that has one.
+    parents: HashMap<String, String>,
+    /// Class whose members are being folded.
+    class: String,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Folder {
+    /// Value of the `const` field `name` the class being folded declares or
+    /// inherits.
+    fn field(&self, name: &str) -> Option<Constant> {
+        let mut class = Some(&self.class);
+        while let Some(current) = class {
+            if let Some(value) = self.fields.get(&(current.clone(), String::from(name))) {
+                return Some(value.clone());
+            }
+            class = self.parents.get(current);
+        }
+        None
+    }
+
+    /// Value `node` folds to, its children being folded already.
+    fn constant(&mut self, node: &ASTNode) -> Option<Constant> {
+        match &node.production {
+            Some(Production::Ident) => {
+                let declaration = self.references.get(&node.location())?;
+                self.locals.get(declaration).cloned()
+            }
+            Some(Production::Member)
+                if node.children[0].is(Production::This) && node.operator.is_none() =>
+            {
+                self.field(&node.name())
+            }
+            Some(Production::Unary) => match Constant::of(&node.children[0])? {
+                Constant::Boolean(holds) => Some(Constant::Boolean(!holds)),
+                Constant::Number(number) => match number.checked_neg() {
+                    Some(negated) => Some(Constant::Number(negated)),
+                    None => {
+                        self.diagnostics.push(Diagnostic::at(
+                            format!("`-{}` overflows a `Number`", number),
+                            node,
+                        ));
+                        None
+                    }
+                },
+                _ => None,
+            },
+            Some(Production::Expression) => {
+                let operator: String = node.operator.iter().flatten().collect();
+                let rhs = Constant::of(&node.children[1])?;
+                if operator == "/" && rhs == Constant::Number(0) {
+                    // fails at runtime whatever the dividend is
+                    self.diagnostics
+                        .push(Diagnostic::at(String::from("Division by zero"), node));
+                    return None;
+                }
+                let lhs = Constant::of(&node.children[0])?;
+                match evaluate(&operator, &lhs, &rhs) {
+                    Ok(value) => value,
+                    Err(message) => {
+                        self.diagnostics.push(Diagnostic::at(message, node));
+                        None
+                    }
+                }
+            }
+            _ => None,
+        }
+    }
+
+    fn fold(&mut self, node: &mut ASTNode) {
+        // the target of an assignment is written, not read
+        let skipped = usize::from(node.is(Production::Assign));
+        for child in node.children.iter_mut().skip(skipped) {
+            self.fold(child);
+        }
+        if node.is_const() {
+            if let Some(value) = node.initializer().and_then(Constant::of) {
+                self.locals.insert(node.location(), value);
+            }
+        }
+        if let Some(value) = self.constant(node) {
+            *node = value.literal(node);
+        }
+    }
+
+    /// Folds the `const` fields of `class`, so that the methods of the
+    /// class and of its subclasses can read them wherever they are
+    /// declared.
+    fn fold_fields(&mut self, class: &mut ASTNode) {
+        self.class = class.name();
+        let members = match class
+            .children
+            .iter_mut()
+            .find(|c| c.is(Production::BlockBody))
+        {
+            Some(body) => &mut body.children,
+            None => return,
+        };
+        for member in members.iter_mut().filter(|m| m.is_const()) {
+            for child in member.children.iter_mut() {
+                self.fold(child);
+            }
+            if let Some(value) = member.initializer().and_then(Constant::of) {
+                self.fields
+                    .insert((self.class.clone(), member.name()), value);
+            }
+        }
+    }
+}
+
+/// Folds the constant expressions of a program whose operators have been
+/// lowered, so that only built in values are left to them. Division by
+/// zero and `Number` overflow found on the way are errors.
+pub fn fold(program: &ASTNode) -> (ASTNode, Vec<Diagnostic>) {
+    let references = resolver::references(program)
+        .into_iter()
+        .map(|r| ((r.source, r.line_number, r.start_col), r.declaration))
+        .collect();
+    let parents = program
+        .children
+        .iter()
+        .filter_map(|c| Some((c.name(), c.find(Production::Extends)?.name())))
+        .collect();
+    let mut folder = Folder {
+        references,
+        locals: HashMap::new(),
+        fields: HashMap::new(),
+        parents,
+        class: String::new(),
+        diagnostics: vec![],
+    };
+    let mut folded = program.clone();
+    for class in folded.children.iter_mut() {
+        folder.fold_fields(class);
+    }
+    for class in folded.children.iter_mut() {
+        folder.class = class.name();
+        if let Some(body) = class
+            .children
+            .iter_mut()
+            .find(|c| c.is(Production::BlockBody))
+        {
+            for member in body.children.iter_mut().filter(|m| !m.is_const()) {
+                folder.fold(member);
+            }
+        }
+    }
+    (folded, folder.diagnostics)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_text;
+
+    fn fold_text(text: &str) -> (ASTNode, Vec<String>) {
+        let (folded, diagnostics) = fold(&parse_text(text));
+        (folded, diagnostics.into_iter().map(|d| d.message).collect())
+    }
+
+    #[test]
+    fn folds_literals_and_constants() {
+        let (folded, errors) = fold_text(
+            "class Base { const rate: Number = 2 * 3; }
+             class Person extends Base {
+                let testNum: Number = 12 + 13;
+                let testNumAgain: Number = 12 / 13;
+                const greeting: Word = \"Hello \" + \"World\";
+                let label: Word = \"age \" + -4 + \" \" + (1 < 2 && true);
+                let scaled: Function = (x: Number) ~Number {
+                    const limit: Number = this.rate * 10;
+                    let bigger: Boolean = limit >= 60 == true;
+                    let greeting: Word = this.greeting + \"!\";
+                    return x * limit;
+                }
+             }",
+        );
+        assert_eq!(errors, Vec::<String>::new());
+        let members = folded.children[1].members();
+        let value = |member: &ASTNode| {
+            let value = member.initializer().unwrap();
+            (value.production.clone().unwrap(), value.name())
+        };
+        assert_eq!(
+            value(&members[0]),
+            (Production::NumberLiteral, String::from("25"))
+        );
+        assert_eq!(
+            value(&members[1]),
+            (Production::NumberLiteral, String::from("0"))
+        );
+        assert_eq!(
+            value(&members[2]),
+            (Production::WordLiteral, String::from("Hello World"))
+        );
+        assert_eq!(
+            value(&members[3]),
+            (Production::WordLiteral, String::from("age -4 true"))
+        );
+        let body = &members[4]
+            .initializer()
+            .unwrap()
+            .find(Production::BlockBody)
+            .unwrap()
+            .children;
+        assert_eq!(
+            value(&body[0]),
+            (Production::NumberLiteral, String::from("60"))
+        );
+        assert_eq!(
+            value(&body[1]),
+            (Production::BooleanLiteral, String::from("true"))
+        );
+        assert_eq!(
+            value(&body[2]),
+            (Production::WordLiteral, String::from("Hello World!"))
+        );
+        let returned = &body[3].children[0];
+        assert!(returned.is(Production::Expression));
+        assert_eq!(returned.children[1].name(), "60");
+    }
+
+    #[test]
+    fn division_by_zero_and_overflow() {
+        let (_, errors) = fold_text(
+            "class A {
+                const zero: Number = 0;
+                let f: Function = (x: Number) {
+                    let a: Number = 1 / 0;
+                    let b: Number = x / this.zero;
+                    let c: Number = 9223372036854775807 + 1;
+                    let d: Number = 9223372036854775807 * 2 - 1;
+                    let e: Number = -9223372036854775807 - 2;
+                }
+             }",
+        );
+        assert_eq!(
+            errors,
+            vec![
+                "Division by zero",
+                "Division by zero",
+                "`9223372036854775807 + 1` overflows a `Number`",
+                "`9223372036854775807 * 2` overflows a `Number`",
+                "`-9223372036854775807 - 2` overflows a `Number`",
+            ]
+        );
+    }
+}
@@ -0,0 +1,959 @@
+use super::codegen;
+use super::codegen::EnumLayout;
+use super::diagnostic::Diagnostic;
+use super::diagnostic::Note;
+use super::parser::ASTNode;
+use super::parser::Production;
+use super::value;
+use super::value::Runtime;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::Write;
+use std::marker::PhantomData;
+use std::rc::Rc;
+
+/// How deeply calls may nest before the program is stopped.
+pub const MAX_DEPTH: usize = 1000;
+
+/// Values of a program run by walking its tree, whose instances and
+/// functions point into the tree.
+pub struct Tree<'a>(PhantomData<&'a ASTNode>);
+
+impl<'a> Runtime for Tree<'a> {
+    type Instance = Instance<'a>;
+    type Function = Closure<'a>;
+
+    fn class_name(instance: &Instance<'a>) -> String {
+        instance.class.name()
+    }
+
+    fn function_name(function: &Closure<'a>) -> String {
+        function.name.clone()
+    }
+}
+
+type Value<'a> = value::Value<Tree<'a>>;
+
+pub struct Instance<'a> {
+    class: &'a ASTNode,
+    fields: RefCell<HashMap<String, Value<'a>>>,
+}
+
+/// A function literal or method along with the scope it was created in.
+pub struct Closure<'a> {
+    /// Name the function is shown with in stack traces.
+    name: String,
+    function: &'a ASTNode,
+    env: Env<'a>,
+    /// Class of the method the function was created in, for `super`.
+    class: Option<&'a ASTNode>,
+}
+
+/// Variables of one scope, and the scope it is nested in. Closures keep
+/// the scope they were created in alive, sharing its variables with it.
+pub struct Scope<'a> {
+    values: RefCell<HashMap<String, Value<'a>>>,
+    parent: Option<Env<'a>>,
+}
+
+type Env<'a> = Rc<Scope<'a>>;
+
+/// A new scope nested in `parent`.
+fn inner<'a>(parent: &Env<'a>) -> Env<'a> {
+    Rc::new(Scope {
+        values: RefCell::new(HashMap::new()),
+        parent: Some(parent.clone()),
+    })
+}
+
+fn define<'a>(env: &Env<'a>, name: String, value: Value<'a>) {
+    env.values.borrow_mut().insert(name, value);
+}
+
+fn get<'a>(env: &Env<'a>, name: &str) -> Option<Value<'a>> {
+    let mut scope = Some(env);
+    while let Some(current) = scope {
+        if let Some(value) = current.values.borrow().get(name) {
+            return Some(value.clone());
+        }
+        scope = current.parent.as_ref();
+    }
+    None
+}
+
+/// Sets the variable `name` in the innermost scope declaring it.
+fn set<'a>(env: &Env<'a>, name: &str, value: Value<'a>) -> bool {
+    let mut scope = Some(env);
+    while let Some(current) = scope {
+        if let Some(slot) = current.values.borrow_mut().get_mut(name) {
+            *slot = value;
+            return true;
+        }
+        scope = current.parent.as_ref();
+    }
+    false
+}
+
+/// Why evaluation stopped before reaching the end of a statement.
+enum Unwind<'a> {
+    Error(Diagnostic),
+    Return(Value<'a>),
+    Break,
+    Continue,
+}
+
+type Outcome<'a, T> = Result<T, Unwind<'a>>;
+
+/// A call being run, for stack traces and `super`.
+struct Frame<'a> {
+    name: String,
+    /// Where it was called, `None` for `Main.main`.
+    site: Option<&'a ASTNode>,
+    class: Option<&'a ASTNode>,
+}
+
+/// Runs a program by walking its tree. Expects a program the checker
+/// accepted, whose sections have been flattened, snippets included and
+/// operators lowered, so that operators only ever apply to built in values.
+struct Interpreter<'a, 'o> {
+    classes: HashMap<String, &'a ASTNode>,
+    enums: Vec<EnumLayout>,
+    globals: Env<'a>,
+    calls: Vec<Frame<'a>>,
+    out: &'o mut dyn Write,
+}
+
+impl<'a, 'o> Interpreter<'a, 'o> {
+    fn new(program: &'a ASTNode, out: &'o mut dyn Write) -> Interpreter<'a, 'o> {
+        let declarations = &program.children;
+        Interpreter {
+            classes: declarations
+                .iter()
+                .filter(|d| d.is(Production::ClassDec))
+                .map(|d| (d.name(), d))
+                .collect(),
+            enums: codegen::enum_layouts(program),
+            globals: Rc::new(Scope {
+                values: RefCell::new(HashMap::new()),
+                parent: None,
+            }),
+            calls: vec![],
+            out,
+        }
+    }
+
+    /// A runtime error at `node`, with the calls that led to it as notes,
+    /// innermost first.
+    fn error(&self, message: String, node: &ASTNode) -> Unwind<'a> {
+        let mut trace = vec![];
+        for (caller, callee) in self.calls.iter().zip(self.calls.iter().skip(1)).rev() {
+            let site = match callee.site {
+                Some(site) => site,
+                None => continue,
+            };
+            trace.push(Note {
+                message: format!("`{}` called from `{}`", callee.name, caller.name),
+                source: site.source,
+                line_number: site.line_number,
+                start_col: site.start_col,
+            });
+        }
+        Unwind::Error(Diagnostic::at(message, node).with_trace(trace))
+    }
+
+    fn this(&self, env: &Env<'a>, node: &ASTNode) -> Outcome<'a, Value<'a>> {
+        get(env, "this").ok_or_else(|| self.error(String::from("No `this` here"), node))
+    }
+
+    /// The class `class`, its parent, grandparent and so on.
+    fn lineage(&self, class: &'a ASTNode) -> Vec<&'a ASTNode> {
+        let mut lineage = vec![class];
+        let mut current = class;
+        while let Some(parent) = current
+            .find(Production::Extends)
+            .and_then(|extends| self.classes.get(&extends.name()))
+        {
+            if lineage.iter().any(|c| std::ptr::eq(*c, *parent)) {
+                break;
+            }
+            lineage.push(parent);
+            current = parent;
+        }
+        lineage
+    }
+
+    /// The method `name` of `class` or of the nearest ancestor declaring
+    /// it, and that class.
+    fn find_method(&self, class: &'a ASTNode, name: &str) -> Option<(&'a ASTNode, &'a ASTNode)> {
+        self.lineage(class).into_iter().find_map(|c| {
+            c.members()
+                .iter()
+                .find(|m| m.is_method() && m.name() == name)
+                .map(|m| (c, m))
+        })
+    }
+
+    /// `method` bound to the instance `this`.
+    fn bind(&self, class: &'a ASTNode, method: &'a ASTNode, this: Value<'a>) -> Value<'a> {
+        let env = inner(&self.globals);
+        define(&env, String::from("this"), this);
+        Value::Function(Rc::new(Closure {
+            name: format!("{}.{}", class.name(), method.name()),
+            function: method.initializer().unwrap_or(method),
+            env,
+            class: Some(class),
+        }))
+    }
+
+    fn call(
+        &mut self,
+        callee: &Value<'a>,
+        arguments: Vec<Value<'a>>,
+        site: &'a ASTNode,
+    ) -> Outcome<'a, Value<'a>> {
+        let closure = match callee {
+            Value::Function(closure) => closure.clone(),
+            other => return Err(self.error(format!("`{}` is not a function", other), site)),
+        };
+        let params = closure
+            .function
+            .find(Production::Params)
+            .map_or(&[][..], |p| &p.children[..]);
+        if params.len() != arguments.len() {
+            return Err(self.error(
+                format!(
+                    "`{}` takes {} argument(s) but {} were given",
+                    closure.name,
+                    params.len(),
+                    arguments.len()
+                ),
+                site,
+            ));
+        }
+        if self.calls.len() >= MAX_DEPTH {
+            return Err(self.error(
+                format!("Stack overflow: calls nested more than {} deep", MAX_DEPTH),
+                site,
+            ));
+        }
+        let env = inner(&closure.env);
+        for (param, argument) in params.iter().zip(arguments) {
+            define(&env, param.name(), argument);
+        }
+        self.calls.push(Frame {
+            name: closure.name.clone(),
+            site: Some(site).filter(|_| !self.calls.is_empty()),
+            class: closure.class,
+        });
+        let body = closure.function.find(Production::BlockBody);
+        let result = match body {
+            Some(body) => self.exec_block(body, &env),
+            None => Ok(()),
+        };
+        self.calls.pop();
+        match result {
+            Ok(()) => Ok(Value::Nothing),
+            Err(Unwind::Return(value)) => Ok(value),
+            Err(other) => Err(other),
+        }
+    }
+
+    /// Creates an instance of `class`: the fields of its ancestors and then
+    /// its own are initialised in declaration order, and `init` is run with
+    /// `arguments` if the class has one.
+    fn instantiate(
+        &mut self,
+        class: &'a ASTNode,
+        arguments: Vec<Value<'a>>,
+        site: &'a ASTNode,
+    ) -> Outcome<'a, Value<'a>> {
+        let instance = Value::Instance(Rc::new(Instance {
+            class,
+            fields: RefCell::new(HashMap::new()),
+        }));
+        let env = inner(&self.globals);
+        define(&env, String::from("this"), instance.clone());
+        for ancestor in self.lineage(class).into_iter().rev() {
+            for field in ancestor.members().iter().filter(|m| !m.is_method()) {
+                let value = match field.initializer() {
+                    Some(initializer) => self.eval(initializer, &env)?,
+                    None => Value::Null,
+                };
+                if let Value::Instance(instance) = &instance {
+                    instance.fields.borrow_mut().insert(field.name(), value);
+                }
+            }
+        }
+        match self.find_method(class, "init") {
+            Some((declaring, init)) => {
+                let init = self.bind(declaring, init, instance.clone());
+                self.call(&init, arguments, site)?;
+            }
+            None if !arguments.is_empty() => {
+                return Err(self.error(
+                    format!(
+                        "`{}` takes no arguments but {} were given",
+                        class.name(),
+                        arguments.len()
+                    ),
+                    site,
+                ))
+            }
+            None => {}
+        }
+        Ok(instance)
+    }
+
+    /// Layout of the enum `node` names, if it does.
+    fn enum_named(&self, node: &ASTNode) -> Option<&EnumLayout> {
+        let path = node.path()?;
+        self.enums.iter().find(|layout| layout.name == path)
+    }
+
+    /// The variant `name` of `layout` carrying `values`.
+    fn variant(layout: &EnumLayout, name: &str, values: Vec<Value<'a>>) -> Value<'a> {
+        let tag = layout.variants.iter().position(|v| v.name == name);
+        let full = format!("{}.{}", layout.name, name);
+        Value::variant(Rc::from(full), tag.unwrap_or_default(), values)
+    }
+
+    fn exec_block(&mut self, block: &'a ASTNode, env: &Env<'a>) -> Outcome<'a, ()> {
+        let env = inner(env);
+        for statement in &block.children {
+            self.exec(statement, &env)?;
+        }
+        Ok(())
+    }
+
+    fn exec(&mut self, statement: &'a ASTNode, env: &Env<'a>) -> Outcome<'a, ()> {
+        match &statement.production {
+            Some(Production::Vardec) => {
+                // defined first so that a local function can call itself
+                define(env, statement.name(), Value::Null);
+                if let Some(initializer) = statement.initializer() {
+                    let value = self.eval(initializer, env)?;
+                    define(env, statement.name(), value);
+                }
+            }
+            Some(Production::If) => {
+                if self.condition(&statement.children[0], env)? {
+                    self.exec_block(&statement.children[1], env)?;
+                } else if let Some(otherwise) = statement.children.get(2) {
+                    match &otherwise.production {
+                        Some(Production::If) => self.exec(otherwise, env)?,
+                        _ => self.exec_block(otherwise, env)?,
+                    }
+                }
+            }
+            Some(Production::Return) => {
+                let value = match statement.children.first() {
+                    Some(value) => self.eval(value, env)?,
+                    None => Value::Nothing,
+                };
+                return Err(Unwind::Return(value));
+            }
+            Some(Production::Print) => {
+                let value = self.eval(&statement.children[0], env)?;
+                if let Err(error) = writeln!(self.out, "{}", value) {
+                    return Err(self.error(format!("Cannot print: {}", error), statement));
+                }
+            }
+            Some(Production::For) => {
+                let elements = match self.eval(&statement.children[0], env)? {
+                    Value::List(list) => list.borrow().clone(),
+                    other => {
+                        return Err(self.error(
+                            format!("Cannot loop over `{}`", other),
+                            &statement.children[0],
+                        ))
+                    }
+                };
+                for element in elements {
+                    let scope = inner(env);
+                    define(&scope, statement.name(), element);
+                    match self.exec_block(&statement.children[1], &scope) {
+                        Err(Unwind::Break) => break,
+                        Err(Unwind::Continue) => continue,
+                        other => other?,
+                    }
+                }
+            }
+            Some(Production::Break) => return Err(Unwind::Break),
+            Some(Production::Continue) => return Err(Unwind::Continue),
+            Some(Production::Assign) => self.assign(statement, env)?,
+            _ => {
+                self.eval(statement, env)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn condition(&mut self, node: &'a ASTNode, env: &Env<'a>) -> Outcome<'a, bool> {
+        let value = self.eval(node, env)?;
+        value
+            .condition()
+            .map_err(|message| self.error(message, node))
+    }
+
+    fn assign(&mut self, node: &'a ASTNode, env: &Env<'a>) -> Outcome<'a, ()> {
+        let (target, value_node) = (&node.children[0], &node.children[1]);
+        let mut value = self.eval(value_node, env)?;
+        if let Some(operator) = &node.operator {
+            let current = self.eval(target, env)?;
+            let operator: String = operator.iter().collect();
+            value = value::binary(&operator, current, value).map_err(|m| self.error(m, node))?;
+        }
+        match &target.production {
+            Some(Production::Ident) => {
+                if !set(env, &target.name(), value) {
+                    return Err(self.error(format!("Unknown name `{}`", target.name()), target));
+                }
+            }
+            Some(Production::Member) => match self.eval(&target.children[0], env)? {
+                Value::Instance(instance) => {
+                    instance.fields.borrow_mut().insert(target.name(), value);
+                }
+                other => {
+                    return Err(self.error(
+                        format!("Cannot set `{}` of `{}`", target.name(), other),
+                        target,
+                    ))
+                }
+            },
+            Some(Production::Index) => {
+                let container = self.eval(&target.children[0], env)?;
+                let key = self.eval(&target.children[1], env)?;
+                container
+                    .set_index(key, value)
+                    .map_err(|message| self.error(message, &target.children[1]))?;
+            }
+            _ => return Err(self.error(String::from("Cannot assign to this"), target)),
+        }
+        Ok(())
+    }
+
+    fn eval_all(&mut self, nodes: &'a [ASTNode], env: &Env<'a>) -> Outcome<'a, Vec<Value<'a>>> {
+        nodes.iter().map(|node| self.eval(node, env)).collect()
+    }
+
+    fn eval(&mut self, node: &'a ASTNode, env: &Env<'a>) -> Outcome<'a, Value<'a>> {
+        let value = match &node.production {
+            Some(Production::NumberLiteral) => match node.name().parse() {
+                Ok(number) => Value::Number(number),
+                Err(_) => return Err(self.error(String::from("Number too large"), node)),
+            },
+            Some(Production::WordLiteral) => Value::word(node.name()),
+            Some(Production::BooleanLiteral) => Value::Boolean(node.name() == "true"),
+            Some(Production::NullLiteral) => Value::Null,
+            Some(Production::Ident) => match get(env, &node.name()) {
+                Some(value) => value,
+                None => return Err(self.error(format!("Unknown name `{}`", node.name()), node)),
+            },
+            Some(Production::This) => self.this(env, node)?,
+            Some(Production::ListLiteral) => Value::list(self.eval_all(&node.children, env)?),
+            Some(Production::MapLiteral) => {
+                let mut entries: Vec<(Value<'a>, Value<'a>)> = vec![];
+                for entry in &node.children {
+                    let key = self.eval(&entry.children[0], env)?;
+                    let value = self.eval(&entry.children[1], env)?;
+                    match entries.iter_mut().find(|(k, _)| k.equals(&key)) {
+                        Some((_, slot)) => *slot = value,
+                        None => entries.push((key, value)),
+                    }
+                }
+                Value::Map(Rc::new(RefCell::new(entries)))
+            }
+            Some(Production::FunctionDec) => Value::Function(Rc::new(Closure {
+                name: String::from("function literal"),
+                function: node,
+                env: env.clone(),
+                class: self.calls.last().and_then(|f| f.class),
+            })),
+            Some(Production::New) => {
+                let class = match self.classes.get(&node.name()) {
+                    Some(class) => *class,
+                    None => {
+                        return Err(self.error(format!("Unknown class `{}`", node.name()), node))
+                    }
+                };
+                let arguments = self.eval_all(&node.children, env)?;
+                self.instantiate(class, arguments, node)?
+            }
+            Some(Production::Unary) => {
+                let operand = self.eval(&node.children[0], env)?;
+                let value = match node.operator.as_deref() {
+                    Some(['!']) => operand.not(),
+                    _ => operand.negate(),
+                };
+                value.map_err(|message| self.error(message, node))?
+            }
+            Some(Production::Expression) => {
+                let operator: String = node.operator.iter().flatten().collect();
+                let lhs = self.eval(&node.children[0], env)?;
+                match (operator.as_str(), &lhs) {
+                    ("&&", Value::Boolean(false)) => Value::Boolean(false),
+                    ("||", Value::Boolean(true)) => Value::Boolean(true),
+                    ("&&", _) | ("||", _) => {
+                        Value::Boolean(self.condition(&node.children[1], env)?)
+                    }
+                    ("??", Value::Null) => self.eval(&node.children[1], env)?,
+                    ("??", _) => lhs,
+                    _ => {
+                        let rhs = self.eval(&node.children[1], env)?;
+                        value::binary(&operator, lhs, rhs).map_err(|m| self.error(m, node))?
+                    }
+                }
+            }
+            Some(Production::Member) => self.member(node, env)?,
+            Some(Production::Call) => self.eval_call(node, env)?,
+            Some(Production::Index) => {
+                let container = self.eval(&node.children[0], env)?;
+                let key = self.eval(&node.children[1], env)?;
+                container
+                    .get_index(&key)
+                    .map_err(|message| self.error(message, &node.children[1]))?
+            }
+            Some(Production::Ok) | Some(Production::Err) => {
+                let (name, tag) = if node.is(Production::Ok) {
+                    ("Ok", 0)
+                } else {
+                    ("Err", 1)
+                };
+                let value = self.eval(&node.children[0], env)?;
+                Value::variant(Rc::from(name), tag, vec![value])
+            }
+            Some(Production::Try) => match self.eval(&node.children[0], env)? {
+                Value::Variant(variant) if &*variant.name == "Ok" => variant.values[0].clone(),
+                Value::Variant(variant) if &*variant.name == "Err" => {
+                    return Err(Unwind::Return(Value::Variant(variant)))
+                }
+                other => return Err(self.error(format!("`{}` is not a `Result`", other), node)),
+            },
+            Some(Production::Match) => self.eval_match(node, env)?,
+            _ => return Err(self.error(String::from("Cannot evaluate this"), node)),
+        };
+        Ok(value)
+    }
+
+    /// `object.name` outside of a call.
+    fn member(&mut self, node: &'a ASTNode, env: &Env<'a>) -> Outcome<'a, Value<'a>> {
+        let object_node = &node.children[0];
+        if let Some(layout) = self.enum_named(object_node) {
+            return Ok(Self::variant(layout, &node.name(), vec![]));
+        }
+        if object_node.is(Production::Super) {
+            return self.super_method(node, env);
+        }
+        let object = self.eval(object_node, env)?;
+        let name = node.name();
+        match &object {
+            Value::Null if node.operator.is_some() => Ok(Value::Null),
+            Value::Instance(instance) => {
+                if let Some(value) = instance.fields.borrow().get(&name) {
+                    return Ok(value.clone());
+                }
+                match self.find_method(instance.class, &name) {
+                    Some((class, method)) => Ok(self.bind(class, method, object.clone())),
+                    None => Err(self.error(
+                        format!("`{}` has no member `{}`", instance.class.name(), name),
+                        node,
+                    )),
+                }
+            }
+            other => other
+                .builtin_member(&name)
+                .ok_or_else(|| self.error(format!("`{}` has no member `{}`", other, name), node)),
+        }
+    }
+
+    /// `super.name`: the method of the parent of the class whose method is
+    /// running, bound to `this`.
+    fn super_method(&mut self, node: &'a ASTNode, env: &Env<'a>) -> Outcome<'a, Value<'a>> {
+        let parent = self
+            .calls
+            .last()
+            .and_then(|f| f.class)
+            .and_then(|class| class.find(Production::Extends))
+            .and_then(|extends| self.classes.get(&extends.name()).copied());
+        let this = self.this(env, node)?;
+        match parent.and_then(|parent| self.find_method(parent, &node.name())) {
+            Some((class, method)) => Ok(self.bind(class, method, this)),
+            None => Err(self.error(format!("No parent method `{}`", node.name()), node)),
+        }
+    }
+
+    fn eval_call(&mut self, node: &'a ASTNode, env: &Env<'a>) -> Outcome<'a, Value<'a>> {
+        let callee = &node.children[0];
+        let arguments = &node.children[1..];
+        if !callee.is(Production::Member) {
+            let function = self.eval(callee, env)?;
+            let arguments = self.eval_all(arguments, env)?;
+            return self.call(&function, arguments, node);
+        }
+        if let Some(layout) = self.enum_named(&callee.children[0]) {
+            let layout = layout.clone();
+            let values = self.eval_all(arguments, env)?;
+            return Ok(Self::variant(&layout, &callee.name(), values));
+        }
+        if callee.children[0].is(Production::Super) {
+            let method = self.super_method(callee, env)?;
+            let arguments = self.eval_all(arguments, env)?;
+            return self.call(&method, arguments, node);
+        }
+        let object = self.eval(&callee.children[0], env)?;
+        let name = callee.name();
+        let function = match &object {
+            Value::Null if callee.operator.is_some() => return Ok(Value::Null),
+            Value::List(_) | Value::Map(_) => {
+                let arguments = self.eval_all(arguments, env)?;
+                if let Some(result) = object.call_builtin(&name, arguments) {
+                    return Ok(result);
+                }
+                return Err(self.error(format!("`{}` has no method `{}`", object, name), callee));
+            }
+            Value::Instance(instance) => {
+                let field = instance.fields.borrow().get(&name).cloned();
+                match field {
+                    Some(value) => value,
+                    None => match self.find_method(instance.class, &name) {
+                        Some((class, method)) => self.bind(class, method, object.clone()),
+                        None => {
+                            return Err(self.error(
+                                format!("`{}` has no method `{}`", instance.class.name(), name),
+                                callee,
+                            ))
+                        }
+                    },
+                }
+            }
+            other => {
+                return Err(self.error(format!("`{}` has no method `{}`", other, name), callee))
+            }
+        };
+        let arguments = self.eval_all(arguments, env)?;
+        self.call(&function, arguments, node)
+    }
+
+    fn eval_match(&mut self, node: &'a ASTNode, env: &Env<'a>) -> Outcome<'a, Value<'a>> {
+        let value = self.eval(&node.children[0], env)?;
+        for arm in &node.children[1..] {
+            let scope = inner(env);
+            if !matches(&arm.children[0], &value, &scope) {
+                continue;
+            }
+            let body = &arm.children[1];
+            if body.is(Production::BlockBody) {
+                self.exec_block(body, &scope)?;
+                return Ok(Value::Nothing);
+            }
+            return self.eval(body, &scope);
+        }
+        Err(self.error(format!("No arm matches `{}`", value), node))
+    }
+
+    /// The class named `class`, which has to have a method `method` to run.
+    fn runnable(&self, program: &ASTNode, class: &str, method: &str) -> Outcome<'a, &'a ASTNode> {
+        self.classes.get(class).copied().ok_or_else(|| {
+            Unwind::Error(Diagnostic::error(
+                format!(
+                    "There is no `{}` class with a `{}` method to run",
+                    class, method
+                ),
+                program.line_number,
+                program.start_col,
+            ))
+        })
+    }
+
+    /// Calls `method` on a new instance of the class named `class`.
+    fn start(&mut self, program: &'a ASTNode, class: &str, method: &str) -> Outcome<'a, Value<'a>> {
+        let declaration = self.runnable(program, class, method)?;
+        let instance = self.instantiate(declaration, vec![], declaration)?;
+        let bound = match self.find_method(declaration, method) {
+            Some((owner, found)) => self.bind(owner, found, instance),
+            None => {
+                return Err(self.error(
+                    format!("`{}` has no `{}` method to run", class, method),
+                    declaration,
+                ))
+            }
+        };
+        self.call(&bound, vec![], declaration)
+    }
+
+    /// Runs `statements` of `method` of the class named `class` in the
+    /// variables kept in `locals`, see `resume`.
+    fn resume(
+        &mut self,
+        program: &'a ASTNode,
+        class: &str,
+        method: &str,
+        statements: &'a [ASTNode],
+        locals: &mut Locals<'a>,
+    ) -> Outcome<'a, Value<'a>> {
+        let declaration = self.runnable(program, class, method)?;
+        let env = match &locals.env {
+            Some(env) => env.clone(),
+            None => {
+                let instance = self.instantiate(declaration, vec![], declaration)?;
+                let this = inner(&self.globals);
+                define(&this, String::from("this"), instance);
+                locals.env.insert(inner(&this)).clone()
+            }
+        };
+        self.calls.push(Frame {
+            name: format!("{}.{}", class, method),
+            site: None,
+            class: Some(declaration),
+        });
+        let result = statements.iter().try_for_each(|s| self.exec(s, &env));
+        self.calls.pop();
+        match result {
+            Ok(()) => Ok(Value::Nothing),
+            Err(Unwind::Return(value)) => Ok(value),
+            Err(other) => Err(other),
+        }
+    }
+}
+
+/// Whether `value` fits `pattern`, defining the names the pattern binds
+/// in `scope` if so.
+fn matches<'a>(pattern: &ASTNode, value: &Value<'a>, scope: &Env<'a>) -> bool {
+    match (&pattern.production, value) {
+        (Some(Production::WildcardPattern), _) => true,
+        (Some(Production::BindingPattern), _) => {
+            define(scope, pattern.name(), value.clone());
+            true
+        }
+        (Some(Production::NumberLiteral), Value::Number(number)) => {
+            pattern.name() == number.to_string()
+        }
+        (Some(Production::WordLiteral), Value::Word(word)) => pattern.name() == **word,
+        (Some(Production::BooleanLiteral), Value::Boolean(boolean)) => {
+            pattern.name() == boolean.to_string()
+        }
+        (Some(Production::VariantPattern), Value::Variant(variant)) => {
+            *variant.name == pattern.name()
+                && variant.values.len() == pattern.children.len()
+                && pattern
+                    .children
+                    .iter()
+                    .zip(&variant.values)
+                    .all(|(p, v)| matches(p, v, scope))
+        }
+        _ => false,
+    }
+}
+
+/// Variables of a method whose statements are run a few at a time, kept
+/// from one call of `resume` to the next, as a REPL session runs its
+/// inputs. `this` is a new instance of the method's class, made when the
+/// first statements are run.
+#[derive(Default)]
+pub struct Locals<'a> {
+    env: Option<Env<'a>>,
+}
+
+/// Runs `statements`, which belong to the body of `method` of the class
+/// named `class` in `program`, with the variables earlier statements left in
+/// `locals`, writing what they print to `out`. Returns the value a `return`
+/// among them gave as it is written in a list, `None` if none did. A
+/// runtime error is returned with the calls that led to it as notes.
+pub fn resume<'a>(
+    program: &'a ASTNode,
+    class: &str,
+    method: &str,
+    statements: &'a [ASTNode],
+    locals: &mut Locals<'a>,
+    out: &mut dyn Write,
+) -> Result<Option<String>, Diagnostic> {
+    let mut interpreter = Interpreter::new(program, out);
+    match interpreter.resume(program, class, method, statements, locals) {
+        Ok(Value::Nothing) => Ok(None),
+        Ok(value) => Ok(Some(value.nested())),
+        Err(Unwind::Error(diagnostic)) => Err(diagnostic),
+        // `break` and `continue` are checked to be inside loops
+        Err(_) => Ok(None),
+    }
+}
+
+/// Runs a program from the `main` method of its `Main` class, writing what
+/// it prints to `out`. A runtime error is returned with the calls that led
+/// to it as notes.
+pub fn run(program: &ASTNode, out: &mut dyn Write) -> Result<(), Diagnostic> {
+    match Interpreter::new(program, out).start(program, "Main", "main") {
+        Ok(_) => Ok(()),
+        Err(Unwind::Error(diagnostic)) => Err(diagnostic),
+        // `break` and `continue` are checked to be inside loops
+        Err(_) => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codegen;
+    use crate::parser::parse_text;
+
+    type Failure = (String, i32, Vec<(String, i32)>);
+
+    /// What running `text` prints, or its runtime error with the lines of
+    /// its notes. Runs on a thread with a stack as deep as `main` gives the
+    /// interpreter.
+    fn run_text(text: &str) -> Result<String, Failure> {
+        let program = parse_text(text);
+        let program = codegen::include_snippets(&codegen::flatten_sections(&program));
+        let program = codegen::lower_operators(&program);
+        std::thread::Builder::new()
+            .stack_size(1 << 28)
+            .spawn(move || {
+                let mut out = vec![];
+                match run(&program, &mut out) {
+                    Ok(()) => Ok(String::from_utf8(out).unwrap()),
+                    Err(d) => Err((
+                        d.message,
+                        d.line_number,
+                        d.notes
+                            .into_iter()
+                            .map(|n| (n.message, n.line_number))
+                            .collect(),
+                    )),
+                }
+            })
+            .unwrap()
+            .join()
+            .unwrap()
+    }
+
+    #[test]
+    fn runs_programs() {
+        let output = run_text(
+            "snippet Named { let name: Word; }
+             class Animal {
+                include Named;
+                let init: Function = (name: Word) { this.name = name; }
+                let sound: Function = () ~Word { return \"...\"; }
+                let speak: Function = () ~Word { return this.name + \" says \" + this.sound(); }
+             }
+             class Dog extends Animal {
+                let sound: Function = () ~Word { return \"woof, \" + super.sound(); }
+             }
+             class Money {
+                let cents: Number;
+                let init: Function = (cents: Number) { this.cents = cents; }
+                let add: Function = (other: Money) ~Money { return new Money(this.cents + other.cents); }
+             }
+             class Main {
+                let friend: Animal? = null;
+                let make: Function = (start: Number) ~Function {
+                    let count: Number = start;
+                    return () ~Number { count += 1; return count; };
+                }
+                let half: Function = (n: Number) ~Result<Number, Word> {
+                    if (n / 2 * 2 != n) { return Err(\"odd: \" + n); }
+                    return Ok(n / 2);
+                }
+                let quarter: Function = (n: Number) ~Result<Number, Word> {
+                    return Ok(this.half(this.half(n)?)?);
+                }
+                let main: Function = () {
+                    print(new Dog(\"Rex\").speak());
+                    let next: Function = this.make(10);
+                    next();
+                    print(next());
+                    print((new Money(5) + new Money(7)).cents);
+                    print(this.friend?.name ?? \"nobody\");
+                    print(this.quarter(12));
+                    print(this.quarter(6));
+                    let total: Number = 0;
+                    for (n in [1, 2, 3, 4, 5]) {
+                        if (n == 2) { continue; }
+                        if (n == 5) { break; }
+                        total += n;
+                    }
+                    print(total);
+                    let words: Word[] = [\"a\"];
+                    words.push(\"b\");
+                    print(words);
+                }
+             }",
+        );
+        assert_eq!(
+            output,
+            Ok(String::from(
+                "Rex says woof, ...\n12\n12\nnobody\nOk(3)\nErr(\"odd: 3\")\n8\n[\"a\", \"b\"]\n"
+            ))
+        );
+    }
+
+    #[test]
+    fn runtime_errors_carry_a_stack_trace() {
+        let program = |statement: &str| {
+            format!(
+                "class Main {{
+                    let callback: Function;
+                    let inner: Function = (a: Number, b: Number) ~Number {{
+                        {}
+                        return 0;
+                    }}
+                    let outer: Function = (n: Number) ~Number {{
+                        return this.inner(n, 0);
+                    }}
+                    let main: Function = () {{ print(this.outer(1)); }}
+                 }}",
+                statement
+            )
+        };
+        let trace = vec![
+            (String::from("`Main.inner` called from `Main.outer`"), 8),
+            (String::from("`Main.outer` called from `Main.main`"), 10),
+        ];
+        assert_eq!(
+            run_text(&program("return a / b;")),
+            Err((String::from("Division by zero"), 4, trace.clone()))
+        );
+        assert_eq!(
+            run_text(&program("this.callback();")),
+            Err((String::from("`null` is not a function"), 4, trace.clone()))
+        );
+        assert_eq!(
+            run_text(&program("let f: Function = (x: Number) { }; f(a, b);")),
+            Err((
+                String::from("`function literal` takes 1 argument(s) but 2 were given"),
+                4,
+                trace.clone()
+            ))
+        );
+        assert_eq!(
+            run_text(&program("let xs: List<Number> = [a]; print(xs[b + 1]);")),
+            Err((
+                String::from("Index 1 is out of bounds for a list of 1 element(s)"),
+                4,
+                trace
+            ))
+        );
+        assert_eq!(
+            run_text("class Main { let main: Function = () { this.main(); } }").map_err(|e| e.0),
+            Err(String::from(
+                "Stack overflow: calls nested more than 1000 deep"
+            ))
+        );
+        assert_eq!(
+            run_text(
+                "class Main {
+                    let down: Function = (n: Number) ~Number { return this.down(n - 1); }
+                    let main: Function = () { print(this.down(5000)); }
+                 }"
+            ),
+            Err((
+                String::from("Stack overflow: calls nested more than 1000 deep"),
+                2,
+                vec![
+                    (String::from("`Main.down` called from `Main.down`"), 2),
+                    (String::from("... repeated 997 more time(s)"), 2),
+                    (String::from("`Main.down` called from `Main.main`"), 3),
+                ]
+            ))
+        );
+    }
+}
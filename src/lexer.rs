@@ -1,16 +1,38 @@
-#[derive(Debug, PartialEq)]
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum Keyword {
     IF,
     RETURN,
     ELSE,
     CLASS,
     LET,
+    CONST,
+    PRIVATE,
     PRINT,
     SECTION,
     SNIPPET,
+    INCLUDE,
+    NEW,
+    THIS,
+    EXTENDS,
+    SUPER,
+    INTERFACE,
+    IMPLEMENTS,
+    FOR,
+    IN,
+    BREAK,
+    CONTINUE,
+    ENUM,
+    MATCH,
+    NULL,
+    OK,
+    ERR,
+    IMPORT,
+    FROM,
+    EXPORT,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum Symbol {
     LParen,
     RParen,
@@ -23,18 +45,31 @@ pub enum Symbol {
     Colon,
     SemiColon,
     Assignment,
+    CompoundAssignment(char), // +=, -=, *=, /=
     Comparison,
     ReturnType, // ~
     TempLiteral,
     And,
-    Or
+    Or,
+    Dot,
+    LBracket,
+    RBracket,
+    FatArrow, // =>
+    NotEqual, // !=
+    Not,      // !
+    Question,
+    SafeDot,  // ?.
+    Coalesce, // ??
+    At,       // @, starts an attribute
 }
 
 // TODO create generic lexeme for types
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum Lexeme {
     Word(Vec<char>),
     Number(i64),
+    /// Digits too many for a `Number`, which the parser reports.
+    Overflow(Vec<char>),
     Boolean(bool),
     Function,
     Identifier(Vec<char>),
@@ -43,7 +78,7 @@ pub enum Lexeme {
     Unknown,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct Node {
     pub lexeme: Lexeme,
     pub line_number: i32,
@@ -61,177 +96,127 @@ pub struct Lexer {
 }
 
 impl Lexer {
+    #[allow(clippy::ptr_arg)]
     pub fn new(input: &Vec<char>) -> Lexer {
         Lexer {
-            input: input.clone(),
+            input: input.to_vec(),
             position: 0,
             read_position: 1,
-            current_char: *input.get(0).unwrap_or_else(|| panic!("panic")),
+            current_char: *input.first().unwrap_or(&'\0'),
             current_line_no: 1,
         }
     }
 
     fn peek_next_char(&self) -> char {
-        let next_char = self.input.iter().nth(self.position + 1);
-        if let Some(inside) = next_char {
-            *inside
-        } else {
-            panic!("Next char is unknown");
-        }
+        *self.input.get(self.position + 1).unwrap_or(&'\0')
     }
 
     fn set_file_navigators(&mut self) {
-        // TODO ignore whitespace
-        self.position = self.position + 1;
-        self.read_position = self.read_position + 1;
+        self.position += 1;
+        self.read_position += 1;
         self.current_char = *self.input.get(self.position).unwrap_or(&'0');
     }
-    
+
     fn go_to_next_line(&mut self) {
-        while self.current_char != '\n' {
-            println!("{}", self.current_char);
+        // stop on the newline itself so `lex` counts the line
+        while self.current_char != '\n' && self.position < self.input.len() {
             self.set_file_navigators();
         }
-        self.current_line_no += 1;
-        self.read_position = 0;
-        self.set_file_navigators();
     }
 
     fn parse_string(&mut self) -> Node {
-        let index_of_closing_quote = &self.input[self.position + 1..].iter().position(|&val| {val == '"'});  
-        let tok_value: Vec<char> = self.input[self.position + 1..index_of_closing_quote.unwrap() + self.position + 1].to_vec();
+        let index_of_closing_quote = self.input[self.position + 1..]
+            .iter()
+            .position(|&val| val == '"')
+            .unwrap_or(self.input.len() - self.position - 1);
+        let tok_value: Vec<char> =
+            self.input[self.position + 1..index_of_closing_quote + self.position + 1].to_vec();
         let string: String = tok_value.iter().collect();
-        self.read_position = self.read_position + string.len();
-        self.position = index_of_closing_quote.unwrap()  + self.position + 1;
+        self.read_position += string.len();
+        self.position += index_of_closing_quote + 1;
 
         Node {
             line_number: self.current_line_no,
             start_col: self.read_position - string.len(),
             end_col: self.read_position,
-            lexeme: Lexeme::Word(tok_value)
+            lexeme: Lexeme::Word(tok_value),
         }
     }
 
     fn get_index_of_next_terminal(&self) -> Option<usize> {
-        self.input[self.position..].iter().position(|&val| {
-            val == ' '
-                || val == ';'
-                || val == '\r'
-                || val == '\n'
-                || val == '('
-                || val == ')'
-                || val == '"'
-                || val == ':'
-                || val == '~'
-                || val == '='
-        })
+        self.input[self.position..]
+            .iter()
+            .position(|&val| !(val.is_alphanumeric() || val == '_'))
+    }
+
+    fn keyword(string: &str) -> Option<Keyword> {
+        match string {
+            "if" => Some(Keyword::IF),
+            "else" => Some(Keyword::ELSE),
+            "class" => Some(Keyword::CLASS),
+            "let" => Some(Keyword::LET),
+            "const" => Some(Keyword::CONST),
+            "private" => Some(Keyword::PRIVATE),
+            "print" => Some(Keyword::PRINT),
+            "section" => Some(Keyword::SECTION),
+            "snippet" => Some(Keyword::SNIPPET),
+            "include" => Some(Keyword::INCLUDE),
+            "new" => Some(Keyword::NEW),
+            "this" => Some(Keyword::THIS),
+            "extends" => Some(Keyword::EXTENDS),
+            "super" => Some(Keyword::SUPER),
+            "interface" => Some(Keyword::INTERFACE),
+            "implements" => Some(Keyword::IMPLEMENTS),
+            "for" => Some(Keyword::FOR),
+            "in" => Some(Keyword::IN),
+            "break" => Some(Keyword::BREAK),
+            "continue" => Some(Keyword::CONTINUE),
+            "enum" => Some(Keyword::ENUM),
+            "match" => Some(Keyword::MATCH),
+            "null" => Some(Keyword::NULL),
+            "Ok" => Some(Keyword::OK),
+            "Err" => Some(Keyword::ERR),
+            "import" => Some(Keyword::IMPORT),
+            "from" => Some(Keyword::FROM),
+            "export" => Some(Keyword::EXPORT),
+            "return" => Some(Keyword::RETURN),
+            _ => None,
+        }
     }
 
     fn parse_token(&mut self) -> Node {
         // only check after our current position -- micro-optimization
-        let mut index_of_terminal = self.get_index_of_next_terminal();
-        if let None = index_of_terminal {
-            index_of_terminal = Some(self.input.len());
-        }
-        if let Some(index) = index_of_terminal {
-            let string: String =
-                self.input[self.position..index + self.position].into_iter().collect();
-            self.position = index - 1 + self.position;
-            self.read_position = self.read_position + string.len();
-            match string.as_str() {
-                "if" => {
-                    Node {
-                        lexeme: Lexeme::Keyword(Keyword::IF),
-                        line_number: self.current_line_no,
-                        start_col: self.read_position - string.len(),
-                        end_col: self.read_position,
-                    }
-                }
-                "else" => {
-                    Node {
-                        lexeme: Lexeme::Keyword(Keyword::ELSE),
-                        line_number: self.current_line_no,
-                        start_col: self.read_position - string.len(),
-                        end_col: self.read_position,
-                    }
-                },
-                "class" =>  {
-                    Node {
-                        lexeme: Lexeme::Keyword(Keyword::CLASS),
-                        line_number: self.current_line_no,
-                        start_col: self.read_position - string.len(),
-                        end_col: self.read_position,
-                    }
-                },
-                "let"  => {
-                    Node {
-                        lexeme: Lexeme::Keyword(Keyword::LET),
-                        line_number: self.current_line_no,
-                        start_col: self.read_position - string.len(),
-                        end_col: self.read_position,
-                    }
-                },
-                "print" =>  {
-                    Node {
-                        lexeme: Lexeme::Keyword(Keyword::PRINT),
-                        line_number: self.current_line_no,
-                        start_col: self.read_position - string.len(),
-                        end_col: self.read_position,
-                    }
-                },
-                "section" =>  {
-                    Node {
-                        lexeme: Lexeme::Keyword(Keyword::SECTION),
-                        line_number: self.current_line_no,
-                        start_col: self.read_position - string.len(),
-                        end_col: self.read_position,
-                    }
-                },
-                "snippet" =>  {
-                    Node {
-                        lexeme: Lexeme::Keyword(Keyword::SNIPPET),
-                        line_number: self.current_line_no,
-                        start_col: self.read_position - string.len(),
-                        end_col: self.read_position,
-                    }
-                },
-                "return" => {
-                    Node {
-                        lexeme: Lexeme::Keyword(Keyword::RETURN),
-                        line_number: self.current_line_no,
-                        start_col: self.read_position - string.len(),
-                        end_col: self.read_position,
-                    }
-                }
-                _ => {
-                    let node: Node = match string.parse::<i64>() {
-                        Ok(number) => Node {
-                            lexeme: Lexeme::Number(number),
-                            line_number: self.current_line_no,
-                            start_col: self.read_position - string.len(),
-                            end_col: self.read_position,
-                        },
-                        Err(_e) => Node {
-                            lexeme: Lexeme::Identifier(string.chars().collect()),
-                            line_number: self.current_line_no,
-                            start_col: self.read_position - string.len(),
-                            end_col: self.read_position,
-                        },
-                    };
-                    node
-                }
-            }
+        let index = self
+            .get_index_of_next_terminal()
+            .unwrap_or(self.input.len() - self.position);
+        let string: String = self.input[self.position..index + self.position]
+            .iter()
+            .collect();
+        self.position += index - 1;
+        self.read_position += string.len();
+        let lexeme = if let Some(keyword) = Lexer::keyword(&string) {
+            Lexeme::Keyword(keyword)
+        } else if string == "Function" {
+            Lexeme::Function
+        } else if string == "true" || string == "false" {
+            Lexeme::Boolean(string == "true")
         } else {
-            Node {
-                lexeme: Lexeme::Unknown,
-                line_number: self.current_line_no,
-                start_col: self.position,
-                end_col: self.position + 1,
+            match string.parse::<i64>() {
+                Ok(number) => Lexeme::Number(number),
+                Err(_e) if string.chars().all(|c| c.is_ascii_digit()) => {
+                    Lexeme::Overflow(string.chars().collect())
+                }
+                Err(_e) => Lexeme::Identifier(string.chars().collect()),
             }
+        };
+        Node {
+            lexeme,
+            line_number: self.current_line_no,
+            start_col: self.read_position - string.len(),
+            end_col: self.read_position,
         }
     }
 
-
     pub fn lex(&mut self) -> Vec<Node> {
         let mut nodes: Vec<Node> = Vec::new();
         while self.position < self.input.len() {
@@ -254,6 +239,24 @@ impl Lexer {
                     };
                     nodes.push(node);
                 }
+                '[' => {
+                    let node = Node {
+                        lexeme: Lexeme::Symbol(Symbol::LBracket),
+                        line_number: self.current_line_no,
+                        start_col: self.read_position,
+                        end_col: self.read_position + 1,
+                    };
+                    nodes.push(node);
+                }
+                ']' => {
+                    let node = Node {
+                        lexeme: Lexeme::Symbol(Symbol::RBracket),
+                        line_number: self.current_line_no,
+                        start_col: self.read_position,
+                        end_col: self.read_position + 1,
+                    };
+                    nodes.push(node);
+                }
                 '"' => {
                     nodes.push(self.parse_string());
                     self.read_position += 1;
@@ -285,6 +288,15 @@ impl Lexer {
                     };
                     nodes.push(node);
                 },
+                '.' => {
+                    let node = Node {
+                        lexeme: Lexeme::Symbol(Symbol::Dot),
+                        line_number: self.current_line_no,
+                        start_col: self.read_position,
+                        end_col: self.read_position + 1,
+                    };
+                    nodes.push(node);
+                },
                 ',' => {
                     let node = Node {
                         lexeme: Lexeme::Symbol(Symbol::Comma),
@@ -295,21 +307,29 @@ impl Lexer {
                     nodes.push(node);
                 },
                 '&' => {
-                    let node = Node {
+                    let mut node = Node {
                         lexeme: Lexeme::Symbol(Symbol::And),
                         line_number: self.current_line_no,
                         start_col: self.read_position,
                         end_col: self.read_position + 1,
                     };
+                    if self.peek_next_char() == '&' {
+                        self.set_file_navigators();
+                        node.end_col += 1;
+                    }
                     nodes.push(node);
                 },
                 '|' => {
-                    let node = Node {
+                    let mut node = Node {
                         lexeme: Lexeme::Symbol(Symbol::Or),
                         line_number: self.current_line_no,
                         start_col: self.read_position,
                         end_col: self.read_position + 1,
                     };
+                    if self.peek_next_char() == '|' {
+                        self.set_file_navigators();
+                        node.end_col += 1;
+                    }
                     nodes.push(node);
                 },
                 '<' => {
@@ -342,6 +362,33 @@ impl Lexer {
                     }
                     nodes.push(node);
                 },
+                '^' => {
+                    let node = Node {
+                        lexeme: Lexeme::Symbol(Symbol::Sqrt),
+                        line_number: self.current_line_no,
+                        start_col: self.read_position,
+                        end_col: self.read_position + 1,
+                    };
+                    nodes.push(node);
+                }
+                '`' => {
+                    let node = Node {
+                        lexeme: Lexeme::Symbol(Symbol::TempLiteral),
+                        line_number: self.current_line_no,
+                        start_col: self.read_position,
+                        end_col: self.read_position + 1,
+                    };
+                    nodes.push(node);
+                }
+                '@' => {
+                    let node = Node {
+                        lexeme: Lexeme::Symbol(Symbol::At),
+                        line_number: self.current_line_no,
+                        start_col: self.read_position,
+                        end_col: self.read_position + 1,
+                    };
+                    nodes.push(node);
+                }
                 '~' => {
                     let node = Node {
                         lexeme: Lexeme::Symbol(Symbol::ReturnType),
@@ -351,8 +398,47 @@ impl Lexer {
                     };
                     nodes.push(node);
                 }
+                '!' if self.peek_next_char() == '=' => {
+                    let node = Node {
+                        lexeme: Lexeme::Symbol(Symbol::NotEqual),
+                        line_number: self.current_line_no,
+                        start_col: self.read_position,
+                        end_col: self.read_position + 2,
+                    };
+                    self.set_file_navigators();
+                    nodes.push(node);
+                }
+                '!' => {
+                    let node = Node {
+                        lexeme: Lexeme::Symbol(Symbol::Not),
+                        line_number: self.current_line_no,
+                        start_col: self.read_position,
+                        end_col: self.read_position + 1,
+                    };
+                    nodes.push(node);
+                }
+                '?' => {
+                    let mut node = Node {
+                        lexeme: Lexeme::Symbol(Symbol::Question),
+                        line_number: self.current_line_no,
+                        start_col: self.read_position,
+                        end_col: self.read_position + 1,
+                    };
+                    let next_char = self.peek_next_char();
+                    if next_char == '.' || next_char == '?' {
+                        node.lexeme = Lexeme::Symbol(if next_char == '.' {
+                            Symbol::SafeDot
+                        } else {
+                            Symbol::Coalesce
+                        });
+                        node.end_col += 1;
+                        self.set_file_navigators();
+                    }
+                    nodes.push(node);
+                }
                 '/' if self.peek_next_char() == '/' => {
                     self.go_to_next_line();
+                    continue;
                 },
                 '=' => {
                     let next_char = self.peek_next_char();
@@ -365,6 +451,15 @@ impl Lexer {
                         };
                         self.set_file_navigators();
                         nodes.push(node);
+                    } else if next_char == '>' {
+                        let node = Node {
+                            lexeme: Lexeme::Symbol(Symbol::FatArrow),
+                            line_number: self.current_line_no,
+                            start_col: self.read_position,
+                            end_col: self.read_position + 2,
+                        };
+                        self.set_file_navigators();
+                        nodes.push(node);
                     }  else {
                         let node = Node {
                             lexeme: Lexeme::Symbol(Symbol::Assignment),
@@ -375,6 +470,16 @@ impl Lexer {
                         nodes.push(node);
                     }                 
                 },
+                '+' | '-' | '*' | '/' if self.peek_next_char() == '=' => {
+                    let node = Node {
+                        lexeme: Lexeme::Symbol(Symbol::CompoundAssignment(self.current_char)),
+                        line_number: self.current_line_no,
+                        start_col: self.read_position,
+                        end_col: self.read_position + 2,
+                    };
+                    self.set_file_navigators();
+                    nodes.push(node);
+                },
                 '+' | '-' | '*' | '/' => {
                     let node = Node {
                         lexeme: Lexeme::Symbol(Symbol::BinOp(self.current_char)),
@@ -394,11 +499,11 @@ impl Lexer {
                     nodes.push(node);
                 },
                 '\n' => {
-                    self.current_line_no = self.current_line_no + 1;
+                    self.current_line_no += 1;
                     self.read_position = 0;
                 }
                 _ => {
-                    if self.current_char.is_alphanumeric() {
+                    if self.current_char.is_alphanumeric() || self.current_char == '_' {
                         nodes.push(self.parse_token());
                     }
                 }
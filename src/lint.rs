@@ -0,0 +1,433 @@
+use super::diagnostic::Diagnostic;
+use super::diagnostic::Severity;
+use super::parser::ASTNode;
+use super::parser::Location;
+use super::parser::Production;
+use super::resolver;
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+/// Every lint, by the name used for it in flags and attributes.
+pub const LINTS: [&str; 7] = [
+    "unused_variable",
+    "unused_parameter",
+    "unused_private_field",
+    "shadowing",
+    "constant_condition",
+    "self_comparison",
+    "naming",
+];
+
+/// What a lint that fires turns into. Lints warn unless told otherwise.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Level {
+    Allow,
+    Warn,
+    Deny,
+}
+
+impl Level {
+    /// The level an attribute names, `allow`, `warn` or `deny`.
+    fn named(name: &str) -> Option<Level> {
+        match name {
+            "allow" => Some(Level::Allow),
+            "warn" => Some(Level::Warn),
+            "deny" => Some(Level::Deny),
+            _ => None,
+        }
+    }
+}
+
+fn is_pascal_case(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_uppercase()) && !name.contains('_')
+}
+
+/// Leading underscores are allowed, they mark names left unused on
+/// purpose.
+fn is_camel_case(name: &str) -> bool {
+    let name = name.trim_start_matches('_');
+    name.is_empty() || name.starts_with(|c: char| c.is_ascii_lowercase()) && !name.contains('_')
+}
+
+/// Whether an expression is made of nothing but literals.
+fn is_constant(node: &ASTNode) -> bool {
+    match &node.production {
+        Some(Production::NumberLiteral)
+        | Some(Production::WordLiteral)
+        | Some(Production::BooleanLiteral)
+        | Some(Production::NullLiteral) => true,
+        Some(Production::Expression) | Some(Production::Unary) => {
+            node.children.iter().all(is_constant)
+        }
+        _ => false,
+    }
+}
+
+/// Source text of a variable or a chain of field accesses on one, which
+/// evaluate to the same value each time they are read.
+fn plain_path(node: &ASTNode) -> Option<String> {
+    match &node.production {
+        Some(Production::Ident) => Some(node.name()),
+        Some(Production::This) => Some(String::from("this")),
+        Some(Production::Member) => Some(format!(
+            "{}.{}",
+            plain_path(&node.children[0])?,
+            node.name()
+        )),
+        _ => None,
+    }
+}
+
+/// Names of the fields read in `node`. The field a plain assignment sets
+/// is written rather than read.
+fn fields_read(node: &ASTNode, found: &mut HashSet<String>) {
+    match &node.production {
+        Some(Production::Assign)
+            if node.operator.is_none() && node.children[0].is(Production::Member) =>
+        {
+            fields_read(&node.children[0].children[0], found);
+            fields_read(&node.children[1], found);
+        }
+        Some(Production::Member) => {
+            found.insert(node.name());
+            fields_read(&node.children[0], found);
+        }
+        _ => {
+            for child in &node.children {
+                fields_read(child, found);
+            }
+        }
+    }
+}
+
+/// Walks the program with the lint levels in force and the locals in
+/// scope.
+struct Linter<'a> {
+    /// Levels given on the command line.
+    levels: &'a HashMap<String, Level>,
+    /// Levels set by the attributes of the declarations being walked,
+    /// innermost last.
+    overrides: Vec<(String, Level)>,
+    /// Declarations of the locals, parameters and loop variables that are
+    /// used somewhere.
+    used: HashSet<Location>,
+    /// Locals in scope, innermost scope last.
+    scopes: Vec<Vec<&'a ASTNode>>,
+    /// How many function literals the walk is inside of.
+    depth: usize,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Linter<'a> {
+    fn level(&self, lint: &str) -> Level {
+        self.overrides
+            .iter()
+            .rev()
+            .find(|(name, _)| name == lint)
+            .map(|(_, level)| *level)
+            .or_else(|| self.levels.get(lint).cloned())
+            .unwrap_or(Level::Warn)
+    }
+
+    /// Reports `diagnostic` as `lint` at the level it is set to.
+    fn emit(&mut self, lint: &str, mut diagnostic: Diagnostic) {
+        diagnostic.severity = match self.level(lint) {
+            Level::Allow => return,
+            Level::Warn => Severity::Warning,
+            Level::Deny => Severity::Error,
+        };
+        diagnostic.message = format!("{} [{}]", diagnostic.message, lint);
+        self.diagnostics.push(diagnostic);
+    }
+
+    fn check_name(&mut self, node: &ASTNode, pascal_case: bool) {
+        let name = node.name();
+        if pascal_case && !is_pascal_case(&name) {
+            self.emit(
+                "naming",
+                Diagnostic::warning_at(format!("`{}` should be written in PascalCase", name), node),
+            );
+        } else if !pascal_case && !is_camel_case(&name) {
+            self.emit(
+                "naming",
+                Diagnostic::warning_at(format!("`{}` should be written in camelCase", name), node),
+            );
+        }
+    }
+
+    /// Declares a local, parameter, loop variable or binding in the
+    /// innermost scope.
+    fn declare(&mut self, node: &'a ASTNode, lint: &str) {
+        let name = node.name();
+        self.check_name(node, false);
+        if !name.starts_with('_') && !self.used.contains(&node.location()) {
+            self.emit(
+                lint,
+                Diagnostic::warning_at(format!("`{}` is never used", name), node),
+            );
+        }
+        let outer = self.scopes[..self.scopes.len().saturating_sub(1)]
+            .iter()
+            .rev()
+            .flat_map(|scope| scope.iter().rev())
+            .find(|declared| declared.name() == name)
+            .cloned();
+        if let Some(outer) = outer {
+            self.emit(
+                "shadowing",
+                Diagnostic::warning_at(format!("`{}` shadows an outer declaration", name), node)
+                    .with_note(String::from("declared here"), outer),
+            );
+        }
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.push(node);
+        }
+    }
+
+    /// Puts the levels set by the attributes of `node` in force, reporting
+    /// those naming unknown lints when `report` is set. Returns how many
+    /// levels were in force before, to go back to when leaving `node`.
+    fn enter_attributes(&mut self, node: &ASTNode, report: bool) -> usize {
+        let overridden = self.overrides.len();
+        for attribute in node.attributes() {
+            let level = Level::named(&attribute.operator.iter().flatten().collect::<String>());
+            match level {
+                Some(level) if LINTS.contains(&attribute.name().as_str()) => {
+                    self.overrides.push((attribute.name(), level))
+                }
+                _ if report => self.diagnostics.push(Diagnostic::warning_at(
+                    format!("Unknown lint `{}`", attribute.name()),
+                    attribute,
+                )),
+                _ => {}
+            }
+        }
+        overridden
+    }
+
+    fn check_private_fields(&mut self, class: &ASTNode) {
+        let mut read = HashSet::new();
+        fields_read(class, &mut read);
+        for member in class.members() {
+            if member.is_private() && !member.is_method() && !read.contains(&member.name()) {
+                let overridden = self.enter_attributes(member, false);
+                self.emit(
+                    "unused_private_field",
+                    Diagnostic::warning_at(
+                        format!("Private field `{}` is never read", member.name()),
+                        member,
+                    ),
+                );
+                self.overrides.truncate(overridden);
+            }
+        }
+    }
+
+    fn check_comparison(&mut self, node: &ASTNode) {
+        let operator: String = node.operator.iter().flatten().collect();
+        if !["==", "!=", "<", ">", "<=", ">="].contains(&operator.as_str()) {
+            return;
+        }
+        let lhs = plain_path(&node.children[0]);
+        if lhs.is_some() && lhs == plain_path(&node.children[1]) {
+            let lhs = lhs.unwrap_or_default();
+            self.emit(
+                "self_comparison",
+                Diagnostic::warning_at(
+                    format!(
+                        "`{} {} {}` compares a value with itself",
+                        lhs, operator, lhs
+                    ),
+                    node,
+                ),
+            );
+        }
+    }
+
+    fn walk(&mut self, node: &'a ASTNode) {
+        let overridden = self.enter_attributes(node, true);
+        let scoped = matches!(
+            &node.production,
+            Some(Production::FunctionDec)
+                | Some(Production::BlockBody)
+                | Some(Production::MatchArm)
+                | Some(Production::For)
+        );
+        match &node.production {
+            Some(Production::ClassDec) => {
+                self.check_name(node, true);
+                self.check_private_fields(node);
+            }
+            Some(Production::SnippetDec)
+            | Some(Production::InterfaceDec)
+            | Some(Production::EnumDec)
+            | Some(Production::Variant) => self.check_name(node, true),
+            Some(Production::Vardec) | Some(Production::MethodSig) if self.depth == 0 => {
+                self.check_name(node, false)
+            }
+            Some(Production::If) if is_constant(&node.children[0]) => self.emit(
+                "constant_condition",
+                Diagnostic::warning_at(
+                    String::from("This condition is always the same"),
+                    &node.children[0],
+                ),
+            ),
+            Some(Production::Expression) => self.check_comparison(node),
+            _ => {}
+        }
+        let local = self.depth > 0 && node.is(Production::Vardec);
+        // a local function is declared first so that it can call itself
+        let declared_first = local && node.is_method();
+        if declared_first {
+            self.declare(node, "unused_variable");
+        }
+        if node.is(Production::For) {
+            self.walk(&node.children[0]);
+        }
+        if scoped {
+            self.scopes.push(vec![]);
+        }
+        if node.is(Production::FunctionDec) {
+            self.depth += 1;
+        }
+        match &node.production {
+            Some(Production::For) => {
+                self.declare(node, "unused_variable");
+                self.walk(&node.children[1]);
+            }
+            // the parameters of interface methods are not declarations
+            Some(Production::MethodSig) => {}
+            _ => {
+                for child in &node.children {
+                    self.walk(child);
+                }
+            }
+        }
+        if node.is(Production::FunctionDec) {
+            self.depth -= 1;
+        }
+        if scoped {
+            self.scopes.pop();
+        }
+        match &node.production {
+            Some(Production::Vardec) if local && !declared_first => {
+                self.declare(node, "unused_variable")
+            }
+            Some(Production::Param) => self.declare(node, "unused_parameter"),
+            Some(Production::BindingPattern) => self.declare(node, "unused_variable"),
+            _ => {}
+        }
+        self.overrides.truncate(overridden);
+    }
+}
+
+/// Runs every lint over the program, with the levels given in `levels`
+/// unless an attribute on an enclosing declaration sets another.
+pub fn lint(program: &ASTNode, levels: &HashMap<String, Level>) -> Vec<Diagnostic> {
+    let used = resolver::references(program)
+        .into_iter()
+        .map(|r| r.declaration)
+        .collect();
+    let mut linter = Linter {
+        levels,
+        overrides: vec![],
+        used,
+        scopes: vec![],
+        depth: 0,
+        diagnostics: vec![],
+    };
+    linter.walk(program);
+    linter.diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_text;
+
+    fn lints(text: &str, levels: &[(&str, Level)]) -> Vec<(String, i32, Severity)> {
+        let program = parse_text(text);
+        let levels = levels
+            .iter()
+            .map(|(lint, level)| (String::from(*lint), *level))
+            .collect();
+        lint(&program, &levels)
+            .into_iter()
+            .map(|d| (d.message, d.line_number, d.severity))
+            .collect()
+    }
+
+    fn warning(message: &str, line: i32) -> (String, i32, Severity) {
+        (String::from(message), line, Severity::Warning)
+    }
+
+    #[test]
+    fn finds_each_lint() {
+        let found = lints(
+            "class person {
+                private let age: Number = 0;
+                private let secret: Word = \"\";
+                @allow(unused_private_field) private let hidden: Word = \"\";
+                let Greet: Function = (times: Number, _unused: Number) {
+                    let count: Number = 0;
+                    let total: Number = 1;
+                    if (true) { print(total); }
+                    if (this.age == this.age) { print(total); }
+                    for (times in [1, 2]) { print(times); }
+                }
+             }",
+            &[],
+        );
+        assert_eq!(
+            found,
+            vec![
+                warning("`person` should be written in PascalCase [naming]", 1),
+                warning(
+                    "Private field `secret` is never read [unused_private_field]",
+                    3
+                ),
+                warning("`Greet` should be written in camelCase [naming]", 5),
+                warning("`times` is never used [unused_parameter]", 5),
+                warning("`count` is never used [unused_variable]", 6),
+                warning("This condition is always the same [constant_condition]", 8),
+                warning(
+                    "`this.age == this.age` compares a value with itself [self_comparison]",
+                    9
+                ),
+                warning("`times` shadows an outer declaration [shadowing]", 10),
+            ]
+        );
+    }
+
+    #[test]
+    fn levels_from_flags_and_attributes() {
+        let text = "@deny(unused_variable) @allow(naming)
+             class a {
+                let f: Function = () {
+                    let x: Number = 1;
+                    @allow(unused_variable) let y: Number = 2;
+                    @warn(unused_variable, shadowing) let z: Number = 3;
+                    @allow(unknown_lint) let w: Number = 4;
+                    print(w);
+                }
+             }
+             class b { }";
+        assert_eq!(
+            lints(text, &[("naming", Level::Deny)]),
+            vec![
+                (
+                    String::from("`x` is never used [unused_variable]"),
+                    4,
+                    Severity::Error
+                ),
+                warning("`z` is never used [unused_variable]", 6),
+                warning("Unknown lint `unknown_lint`", 7),
+                (
+                    String::from("`b` should be written in PascalCase [naming]"),
+                    11,
+                    Severity::Error
+                ),
+            ]
+        );
+    }
+}
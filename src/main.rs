@@ -1,25 +1,288 @@
+use std::collections::HashMap;
 use std::env;
 use std::fs::File;
 use std::io;
 use std::io::Read;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+mod bytecode;
+mod cfg;
+mod checker;
+mod codegen;
+mod dataflow;
+mod diagnostic;
+mod fold;
+mod interpreter;
 mod lexer;
+mod lint;
+mod module;
 mod parser;
+mod repl;
+mod resolver;
+mod section;
+mod types;
+mod value;
+mod vm;
 
+use diagnostic::Diagnostic;
+
+/// What the compiler was asked to do with the program.
+#[derive(PartialEq, Clone, Copy)]
+enum Mode {
+    /// Check the program and print what each stage made of it.
+    Compile,
+    /// Check the program and run it on the virtual machine, or with the
+    /// interpreter when `--interpret` is given.
+    Run,
+    /// Check the program and print the bytecode it compiles to.
+    Disasm,
+    /// Run the program with the interpreter and on the virtual machine,
+    /// timing both.
+    Bench,
+    /// Read declarations and expressions from stdin and run them.
+    Repl,
+}
+
+/// Compiles the files named on the command line along with every module
+/// they import, and runs the program when the first argument is `run`, on
+/// the virtual machine unless `--interpret` asks for the interpreter.
+/// `disasm` prints its bytecode instead and `bench` times it with the
+/// interpreter and the virtual machine. `repl` starts a session reading the
+/// program from stdin.
+/// `-I DIRECTORY` adds a directory to search for imported modules whose
+/// path is not relative, and `-A LINT`, `-W LINT` and
+/// `-D LINT` allow a lint, make it warn or make it an error.
 fn main() {
-    let file = env::args().nth(1).expect("Supply a file name");
-    let text = file_as_text(&file).expect("Bad file");
-    println!("{}", &text);
+    let mut entries = vec![];
+    let mut search_path = vec![];
+    let mut levels = HashMap::new();
+    let mut interpret = false;
+    let mut args = env::args().skip(1).peekable();
+    let mode = match args.peek().map(|a| a.as_str()) {
+        Some("run") => {
+            args.next();
+            Mode::Run
+        }
+        Some("repl") => {
+            args.next();
+            Mode::Repl
+        }
+        Some("disasm") => {
+            args.next();
+            Mode::Disasm
+        }
+        Some("bench") => {
+            args.next();
+            Mode::Bench
+        }
+        _ => Mode::Compile,
+    };
+    while let Some(arg) = args.next() {
+        let level = match arg.as_str() {
+            "-A" => Some(lint::Level::Allow),
+            "-W" => Some(lint::Level::Warn),
+            "-D" => Some(lint::Level::Deny),
+            _ => None,
+        };
+        if let Some(level) = level {
+            let name = args.next().expect("Lint flags need a lint name");
+            if !lint::LINTS.contains(&name.as_str()) {
+                panic!("Unknown lint `{}`, expected one of {}", name, lint::LINTS.join(", "));
+            }
+            levels.insert(name, level);
+        } else if arg == "--interpret" {
+            interpret = true;
+        } else if arg == "-I" {
+            search_path.push(PathBuf::from(args.next().expect("-I needs a directory")));
+        } else {
+            entries.push(PathBuf::from(arg));
+        }
+    }
+    if mode == Mode::Repl {
+        with_deep_stack(repl::repl);
+        return;
+    }
+    if entries.is_empty() {
+        panic!("Supply a file name");
+    }
+    let mut loader = module::Loader::new(search_path);
+    for entry in &entries {
+        let mut load = |file: &Path, source| load(file, source, mode == Mode::Compile);
+        if let Err(diagnostic) = loader.load(entry, &mut load) {
+            eprintln!("{}", diagnostic.render(&loader.file_names()));
+            process::exit(1);
+        }
+    }
+    let files = loader.file_names();
+    let ast = section::merge(loader.into_programs());
+    report(resolver::resolve(&ast), &files);
+    // linted before snippets are pulled into classes, so that their
+    // members are only linted once
+    let lints = lint::lint(&ast, &levels);
+    let ast = codegen::include_snippets(&codegen::flatten_sections(&ast));
+    report(checker::check(&ast), &files);
+    report(cfg::check(&ast), &files);
+    report(dataflow::check(&ast), &files);
+    report(lints, &files);
+    let ast = codegen::lower_operators(&ast);
+    let (ast, diagnostics) = fold::fold(&ast);
+    report(diagnostics, &files);
+    match mode {
+        Mode::Run if interpret => with_deep_stack(move || run_interpreted(&ast, &files)),
+        Mode::Run => run(&compile(&ast, &files), &files),
+        Mode::Disasm => print!("{}", bytecode::disassemble(&compile(&ast, &files))),
+        Mode::Bench => bench(ast, files),
+        Mode::Compile | Mode::Repl => print_stages(&ast),
+    }
+}
+
+/// Prints what the stages after checking make of a program.
+fn print_stages(ast: &parser::ASTNode) {
+    println!("ast: {:#?}", ast);
+    println!("layouts: {:#?}", codegen::layouts(ast));
+    println!("enums: {:#?}", codegen::enum_layouts(ast));
+    println!("closures: {:#?}", codegen::closure_layouts(ast));
+    println!("references: {:#?}", resolver::references(ast));
+    println!("inferred: {:#?}", checker::inferred_types(ast));
+}
+
+/// Prints the diagnostics of one stage, stopping the compiler if any of them
+/// is an error.
+fn report(diagnostics: Vec<Diagnostic>, files: &[String]) {
+    for diagnostic in &diagnostics {
+        eprintln!("{}", diagnostic.render(files));
+    }
+    if diagnostics.iter().any(|d| d.is_error()) {
+        process::exit(1);
+    }
+}
+
+/// Calls `f` on a thread with a stack deep enough for the recursion the
+/// interpreter does on behalf of the program.
+fn with_deep_stack<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> T {
+    thread::Builder::new()
+        .stack_size(1 << 28)
+        .spawn(f)
+        .expect("Cannot start the interpreter")
+        .join()
+        .expect("The interpreter crashed")
+}
+
+/// Compiles a checked program to bytecode, exiting with an error if it
+/// cannot be.
+fn compile(ast: &parser::ASTNode, files: &[String]) -> bytecode::Program {
+    match bytecode::compile(ast) {
+        Ok(program) => program,
+        Err(diagnostic) => {
+            eprintln!("{}", diagnostic.render(files));
+            process::exit(1);
+        }
+    }
+}
+
+/// Runs a compiled program on the virtual machine, exiting with an error if
+/// it stops with one.
+fn run(program: &bytecode::Program, files: &[String]) {
+    let stdout = io::stdout();
+    if let Err(diagnostic) = vm::run(program, &mut stdout.lock()) {
+        eprintln!("{}", diagnostic.render(files));
+        process::exit(1);
+    }
+}
+
+/// Runs a checked program with the interpreter, exiting with an error if
+/// it stops with one.
+fn run_interpreted(ast: &parser::ASTNode, files: &[String]) {
+    let stdout = io::stdout();
+    if let Err(diagnostic) = interpreter::run(ast, &mut stdout.lock()) {
+        eprintln!("{}", diagnostic.render(files));
+        process::exit(1);
+    }
+}
+
+/// How many times `bench` runs a program each way, keeping the fastest.
+const BENCH_RUNS: usize = 5;
+
+/// The fastest of `BENCH_RUNS` runs of `run`, and what the last one gave.
+fn time<T>(mut run: impl FnMut() -> T) -> (Duration, T) {
+    let mut fastest = Duration::MAX;
+    let mut result = None;
+    for _ in 0..BENCH_RUNS {
+        let start = Instant::now();
+        result = Some(run());
+        fastest = fastest.min(start.elapsed());
+    }
+    (fastest, result.expect("the program is run at least once"))
+}
+
+/// Times a checked program with the interpreter and on the virtual
+/// machine, exiting with an error if they print different things or stop
+/// with different errors.
+fn bench(ast: parser::ASTNode, files: Vec<String>) {
+    let program = compile(&ast, &files);
+    let rendered = files.clone();
+    let (interpreted, expected) = with_deep_stack(move || {
+        time(|| {
+            let mut out = vec![];
+            let result = interpreter::run(&ast, &mut out).map_err(|d| d.render(&rendered));
+            (out, result)
+        })
+    });
+    let (compiled, found) = time(|| {
+        let mut out = vec![];
+        let result = vm::run(&program, &mut out).map_err(|d| d.render(&files));
+        (out, result)
+    });
+    if found != expected {
+        eprintln!("The interpreter and the virtual machine disagree");
+        process::exit(1);
+    }
+    println!("interpreter: {:.2?}", interpreted);
+    println!("vm: {:.2?}", compiled);
+    println!(
+        "speedup: {:.2}x",
+        interpreted.as_secs_f64() / compiled.as_secs_f64()
+    );
+}
+
+/// Lexes and parses one input file, printing the text and tokens when
+/// `verbose` is set. Files ending in `.xs` are section sources and may only
+/// declare sections.
+fn load(file: &Path, source: usize, verbose: bool) -> Result<parser::ASTNode, Diagnostic> {
+    let text = file_as_text(file).expect("Bad file");
+    if verbose {
+        println!("{}", &text);
+    }
     let mut lexer = lexer::Lexer::new(&text.chars().collect());
     let mut tokens = lexer.lex();
     tokens.reverse();
-    println!("tokens: {:#?}", tokens);
+    if verbose {
+        println!("tokens: {:#?}", tokens);
+    }
     let mut parser = parser::Parser::new(tokens);
-    let ast = parser.parse();
-    println!("ast: {:#?}", ast);
+    let parsed = if file.extension().is_some_and(|e| e == "xs") {
+        parser.parse_section_source()
+    } else {
+        parser.parse()
+    };
+    match parsed {
+        Ok(mut program) => {
+            program.set_source(source);
+            Ok(program)
+        }
+        Err(diagnostic) => Err(Diagnostic {
+            source,
+            ..diagnostic
+        }),
+    }
 }
 
-fn file_as_text(filename: &str) -> Result<String, io::Error> {
-    let mut file = File::open(&filename)?;
+fn file_as_text(filename: &Path) -> Result<String, io::Error> {
+    let mut file = File::open(filename)?;
     let mut text = String::new();
     file.read_to_string(&mut text)?;
     Ok(text)
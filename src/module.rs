@@ -0,0 +1,290 @@
+use super::diagnostic::Diagnostic;
+use super::parser::ASTNode;
+use super::parser::Production;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// Finds the file an import names. Paths starting with `./` or `../` are
+/// relative to the directory of the importing file; any other path is
+/// looked up in each directory of the search path in turn.
+pub fn locate(path: &str, importer: &Path, search_path: &[PathBuf]) -> Option<PathBuf> {
+    if path.starts_with("./") || path.starts_with("../") {
+        // collecting the components drops the `./` of the path
+        let located: PathBuf = importer
+            .parent()
+            .unwrap_or_else(|| Path::new(""))
+            .join(path)
+            .components()
+            .collect();
+        return Some(located).filter(|l| l.is_file());
+    }
+    search_path
+        .iter()
+        .map(|directory| directory.join(path))
+        .find(|located| located.is_file())
+}
+
+fn canonical(file: &Path) -> PathBuf {
+    fs::canonicalize(file).unwrap_or_else(|_| file.to_path_buf())
+}
+
+/// Loads a project from its entry files: every module they import,
+/// directly or not, is loaded once, and the dependency graph is checked
+/// for cycles and for imports of names a module does not export.
+///
+/// Exported names share one namespace across the project, as sections do,
+/// and a module uses those of another only through an import. Names a
+/// module does not export are private to it, see `SymbolTable::qualified`.
+#[derive(Debug, Default)]
+pub struct Loader {
+    pub search_path: Vec<PathBuf>,
+    /// Files in the order they were opened. The `source` of a node indexes
+    /// this list.
+    pub files: Vec<PathBuf>,
+    /// `files` made absolute, to tell when two paths name one file.
+    canonical: Vec<PathBuf>,
+    /// Programs parsed from `files`, at the same index.
+    programs: Vec<Option<ASTNode>>,
+    /// Modules whose imports are being loaded, importers first.
+    loading: Vec<usize>,
+    /// Modules in the order they finished loading, which puts every module
+    /// after the ones it imports.
+    order: Vec<usize>,
+}
+
+impl Loader {
+    pub fn new(search_path: Vec<PathBuf>) -> Loader {
+        Loader {
+            search_path,
+            ..Loader::default()
+        }
+    }
+
+    /// Names of the loaded files, for rendering diagnostics.
+    pub fn file_names(&self) -> Vec<String> {
+        self.files.iter().map(|f| f.display().to_string()).collect()
+    }
+
+    /// Loads `file` and the modules it imports. `parse` reads and parses one
+    /// file given the index its nodes are to be marked with.
+    pub fn load(
+        &mut self,
+        file: &Path,
+        parse: &mut dyn FnMut(&Path, usize) -> Result<ASTNode, Diagnostic>,
+    ) -> Result<usize, Diagnostic> {
+        let canonical = canonical(file);
+        if let Some(source) = self.canonical.iter().position(|c| *c == canonical) {
+            return Ok(source);
+        }
+        let source = self.files.len();
+        self.files.push(file.to_path_buf());
+        self.canonical.push(canonical);
+        self.programs.push(None);
+        self.loading.push(source);
+        let program = parse(file, source)?;
+        for import in program.children.iter().filter(|d| d.is(Production::Import)) {
+            let imported = self.load_import(import, file, parse)?;
+            self.check_exports(import, imported)?;
+        }
+        self.loading.pop();
+        self.programs[source] = Some(program);
+        self.order.push(source);
+        Ok(source)
+    }
+
+    fn load_import(
+        &mut self,
+        import: &ASTNode,
+        importer: &Path,
+        parse: &mut dyn FnMut(&Path, usize) -> Result<ASTNode, Diagnostic>,
+    ) -> Result<usize, Diagnostic> {
+        let path = import.name();
+        let located = locate(&path, importer, &self.search_path)
+            .ok_or_else(|| Diagnostic::at(format!("Cannot find module `{}`", path), import))?;
+        let located_canonical = canonical(&located);
+        let cycle_start = self
+            .loading
+            .iter()
+            .position(|&loading| self.canonical[loading] == located_canonical);
+        if let Some(start) = cycle_start {
+            let mut cycle: Vec<String> = self.loading[start..]
+                .iter()
+                .map(|&m| self.files[m].display().to_string())
+                .collect();
+            cycle.push(self.files[self.loading[start]].display().to_string());
+            return Err(Diagnostic::at(
+                format!("Modules import each other: {}", cycle.join(" -> ")),
+                import,
+            ));
+        }
+        self.load(&located, parse)
+    }
+
+    /// Checks that the module at `imported` exports every name `import`
+    /// asks for.
+    fn check_exports(&self, import: &ASTNode, imported: usize) -> Result<(), Diagnostic> {
+        let program = match &self.programs[imported] {
+            Some(program) => program,
+            None => return Ok(()),
+        };
+        for name in &import.children {
+            let declaration = program
+                .children
+                .iter()
+                .find(|d| !d.is(Production::Import) && d.name() == name.name());
+            match declaration {
+                Some(declaration) if declaration.is_exported() => {}
+                Some(declaration) => {
+                    return Err(Diagnostic::at(
+                        format!("`{}` is not exported by `{}`", name.name(), import.name()),
+                        name,
+                    )
+                    .with_note(String::from("declared here"), declaration))
+                }
+                None => {
+                    return Err(Diagnostic::at(
+                        format!("`{}` declares no `{}`", import.name(), name.name()),
+                        name,
+                    ))
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The loaded programs, every module after the ones it imports.
+    pub fn into_programs(mut self) -> Vec<ASTNode> {
+        let mut programs = vec![];
+        for source in self.order {
+            programs.extend(self.programs[source].take());
+        }
+        programs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parser_for;
+    use std::env;
+
+    /// Writes `files` below a fresh directory named after the test.
+    fn project(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let root = env::temp_dir().join(format!("xonyx-modules-{}", name));
+        let _ = fs::remove_dir_all(&root);
+        for (path, text) in files {
+            let file = root.join(path);
+            fs::create_dir_all(file.parent().unwrap()).unwrap();
+            fs::write(file, text).unwrap();
+        }
+        root
+    }
+
+    fn parse(file: &Path, source: usize) -> Result<ASTNode, Diagnostic> {
+        let text = fs::read_to_string(file).unwrap();
+        let mut program = parser_for(&text).parse()?;
+        program.set_source(source);
+        Ok(program)
+    }
+
+    fn load(root: &Path, search_path: Vec<PathBuf>) -> (Loader, Result<usize, Diagnostic>) {
+        let mut loader = Loader::new(search_path);
+        let loaded = loader.load(&root.join("main.xo"), &mut parse);
+        (loader, loaded)
+    }
+
+    #[test]
+    fn loads_imports_before_importers() {
+        let root = project(
+            "graph",
+            &[
+                (
+                    "main.xo",
+                    "import { Person } from \"./people/person.xo\";
+                     import { Team } from \"team.xo\";
+                     class Main { }",
+                ),
+                (
+                    "people/person.xo",
+                    "import { Name } from \"../name.xo\"; export class Person { }",
+                ),
+                ("name.xo", "export class Name { }"),
+                (
+                    "lib/team.xo",
+                    "import { Person } from \"../people/person.xo\"; export class Team { }",
+                ),
+            ],
+        );
+        let (loader, loaded) = load(&root, vec![root.join("lib")]);
+        assert!(loaded.is_ok());
+        assert_eq!(loader.files.len(), 4);
+        let names: Vec<String> = loader
+            .into_programs()
+            .iter()
+            .map(|p| p.children.last().unwrap().name())
+            .collect();
+        assert_eq!(names, vec!["Name", "Person", "Team", "Main"]);
+    }
+
+    #[test]
+    fn reports_cycles() {
+        let root = project(
+            "cycle",
+            &[
+                ("main.xo", "import { A } from \"./a.xo\";"),
+                ("a.xo", "import { B } from \"./b.xo\"; export class A { }"),
+                ("b.xo", "import { A } from \"./a.xo\"; export class B { }"),
+            ],
+        );
+        let (loader, loaded) = load(&root, vec![]);
+        let error = loaded.unwrap_err();
+        let files = loader.file_names();
+        assert_eq!(
+            error.message,
+            format!(
+                "Modules import each other: {} -> {} -> {}",
+                files[1], files[2], files[1]
+            )
+        );
+        assert_eq!(error.source, 2);
+    }
+
+    #[test]
+    fn reports_missing_modules_and_names() {
+        let root = project(
+            "missing",
+            &[("main.xo", "import { Person } from \"person.xo\";")],
+        );
+        let (_, loaded) = load(&root, vec![]);
+        assert_eq!(
+            loaded.unwrap_err().message,
+            "Cannot find module `person.xo`"
+        );
+
+        let root = project(
+            "private",
+            &[
+                ("main.xo", "import { Person, Pet } from \"./person.xo\";"),
+                ("person.xo", "class Person { }"),
+            ],
+        );
+        let (_, loaded) = load(&root, vec![]);
+        let error = loaded.unwrap_err();
+        assert_eq!(error.message, "`Person` is not exported by `./person.xo`");
+        assert_eq!(error.notes[0].source, 1);
+
+        let root = project(
+            "undeclared",
+            &[
+                ("main.xo", "import { Pet } from \"./person.xo\";"),
+                ("person.xo", "export class Person { }"),
+            ],
+        );
+        let (_, loaded) = load(&root, vec![]);
+        assert_eq!(
+            loaded.unwrap_err().message,
+            "`./person.xo` declares no `Pet`"
+        );
+    }
+}
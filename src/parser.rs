@@ -1,197 +1,1739 @@
+use super::diagnostic::Diagnostic;
 use super::lexer;
 use super::lexer::Keyword;
 use super::lexer::Lexeme;
 use super::lexer::Symbol;
 
-#[derive(Debug, PartialEq)]
+/// Kind of an `ASTNode`. The comment on each variant documents which of the
+/// node's fields are used and what its children are.
+#[derive(Debug, PartialEq, Clone)]
 pub enum Production {
+    /// children: top level declarations, which may be exported (operator:
+    /// `export`), and Import. Declarations, members and local variables
+    /// end in an Attribute child for each lint named in the `@level(...)`
+    /// attributes put before them, after the children listed below.
     ProgStart,
+    /// value: path of the imported module as written, children: Ident of
+    /// each declaration imported from it
+    Import,
+    /// value: class name, children: [TypeParams?, Extends?, Implements*,
+    /// BlockBody of members]
     ClassDec,
+    /// children: TypeParam
+    TypeParams,
+    /// value: parameter name, children: [TypeDec of the interface bounding
+    /// it?]
+    TypeParam,
+    /// value: name of the parent class, possibly qualified
+    Extends,
+    /// value: name of an interface the class conforms to, possibly qualified
+    Implements,
+    /// value: interface name, children: [BlockBody of MethodSig]
+    InterfaceDec,
+    /// value: method name, children: [Params, TypeDec?]
+    MethodSig,
+    /// value: enum name, children: [BlockBody of Variant]
+    EnumDec,
+    /// value: variant name, children: TypeDec of each value it carries
+    Variant,
+    /// value: snippet name, children: [BlockBody of members]
+    SnippetDec,
+    /// value: section name, children: [BlockBody of ClassDec, SnippetDec,
+    /// InterfaceDec and EnumDec]
+    SectionDec,
+    /// value: name of the snippet pulled into the enclosing class, possibly
+    /// qualified with its section as in `Shapes.Round`
+    Include,
+    /// children: statements or members
     BlockBody,
+    /// value: variable name, operator: the modifiers `private` and `const`
+    /// separated by a space, children: [TypeDec?, initializer?], at least
+    /// one of them
     Vardec,
+    /// value: type name, children: type arguments, as the element type of
+    /// `List<Number>`. `T[]` and `T?` are stored as `List<T>` and
+    /// `Option<T>`.
     TypeDec,
+    /// children: [TypeParams?, Params, TypeDec of the return type?,
+    /// BlockBody]
     FunctionDec,
+    /// children: Param
+    Params,
+    /// value: parameter name, children: [TypeDec]
+    Param,
+    /// children: [condition, BlockBody, else (BlockBody or If)?]
+    If,
+    /// children: [expression?]
+    Return,
+    /// children: [expression]
+    Print,
+    /// value: loop variable, children: [iterated list, BlockBody]
+    For,
+    /// leaves the innermost `for` loop
+    Break,
+    /// goes on with the next round of the innermost `for` loop
+    Continue,
+    /// operator: binary operator, children: [lhs, rhs]
     Expression,
+    /// operator: prefix operator, children: [operand]
+    Unary,
+    /// children: [callee, arguments...]
+    Call,
+    /// value: member name, operator: `?.` when the access is skipped for a
+    /// null object, children: [object]
+    Member,
+    /// value: class name, possibly qualified, children: constructor arguments
+    New,
+    /// the instance a method was called on
+    This,
+    /// the instance a method was called on, seen as its parent class; only
+    /// ever the object of a `Member`
+    Super,
+    /// operator: `+`, `-`, `*` or `/` for compound assignments such as
+    /// `+=`, children: [target (Ident, Member or Index), value]
+    Assign,
+    /// children: elements
+    ListLiteral,
+    /// children: MapEntry
+    MapLiteral,
+    /// children: [key, value]
+    MapEntry,
+    /// children: [list or map, index or key]
+    Index,
+    /// children: [matched value, MatchArm...]
+    Match,
+    /// children: [pattern, expression or BlockBody]
+    MatchArm,
+    /// value: qualified variant name as in `Shape.Circle`, or `Ok` or `Err`
+    /// for results, children: patterns for the values the variant carries
+    VariantPattern,
+    /// `_`, matches anything
+    WildcardPattern,
+    /// value: name the matched value is bound to
+    BindingPattern,
+    /// value: identifier
     Ident,
+    /// value: name of a lint, operator: `allow`, `warn` or `deny`, the level
+    /// it is set to for the declaration the attribute belongs to
+    Attribute,
+    /// value: digits of the literal
+    NumberLiteral,
+    /// value: contents of the literal without quotes
+    WordLiteral,
+    /// value: `true` or `false`
+    BooleanLiteral,
+    /// `null`, the absence of a value of an optional type
+    NullLiteral,
+    /// children: [value of a successful `Result`]
+    Ok,
+    /// children: [error of a failed `Result`]
+    Err,
+    /// `result?`: the value of an `Ok`, or return the `Err` from the
+    /// enclosing function. children: [result]
+    Try,
 }
 
-#[derive(Debug, PartialEq)]
+/// Where a node was parsed: source, line and column. Tells apart the
+/// nodes of a program, as no two start at the same place.
+pub type Location = (usize, i32, usize);
+
+#[derive(Debug, PartialEq, Clone)]
 pub struct ASTNode {
-    production: Option<Production>,
-    children: Vec<ASTNode>,
-    value: Option<Vec<char>>,
-    operator: Option<Vec<char>>,
+    pub production: Option<Production>,
+    pub children: Vec<ASTNode>,
+    pub value: Option<Vec<char>>,
+    pub operator: Option<Vec<char>>,
+    pub line_number: i32,
+    pub start_col: usize,
+    /// Index of the file the node was parsed from.
+    pub source: usize,
+}
+
+impl ASTNode {
+    pub fn new(production: Production, at: &lexer::Node) -> ASTNode {
+        ASTNode {
+            production: Some(production),
+            children: vec![],
+            value: None,
+            operator: None,
+            line_number: at.line_number,
+            start_col: at.start_col,
+            source: 0,
+        }
+    }
+
+    /// Empty `ProgStart` node.
+    pub fn root() -> ASTNode {
+        let start = lexer::Node {
+            lexeme: Lexeme::Unknown,
+            line_number: 1,
+            start_col: 1,
+            end_col: 1,
+        };
+        ASTNode::new(Production::ProgStart, &start)
+    }
+
+    /// Marks the node and everything below it as parsed from file `source`.
+    pub fn set_source(&mut self, source: usize) {
+        self.source = source;
+        for child in self.children.iter_mut() {
+            child.set_source(source);
+        }
+    }
+
+    pub fn with_value(mut self, value: Vec<char>) -> ASTNode {
+        self.value = Some(value);
+        self
+    }
+
+    pub fn with_operator(mut self, operator: Vec<char>) -> ASTNode {
+        self.operator = Some(operator);
+        self
+    }
+
+    pub fn with_children(mut self, children: Vec<ASTNode>) -> ASTNode {
+        self.children = children;
+        self
+    }
+
+    pub fn is(&self, production: Production) -> bool {
+        self.production == Some(production)
+    }
+
+    /// The node's value as a `String`, empty when it has none.
+    pub fn name(&self) -> String {
+        self.value
+            .as_ref()
+            .map(|v| v.iter().collect())
+            .unwrap_or_default()
+    }
+
+    /// First direct child of the given production.
+    pub fn find(&self, production: Production) -> Option<&ASTNode> {
+        let production = Some(production);
+        self.children.iter().find(|c| c.production == production)
+    }
+
+    /// Initialiser of a `Vardec`, if it has one.
+    pub fn initializer(&self) -> Option<&ASTNode> {
+        self.children
+            .iter()
+            .find(|c| !c.is(Production::TypeDec) && !c.is(Production::Attribute))
+    }
+
+    /// Whether a declaration carries `modifier`, one of the words kept in
+    /// its operator.
+    fn has_modifier(&self, modifier: &str) -> bool {
+        self.operator.as_ref().is_some_and(|operator| {
+            operator
+                .iter()
+                .collect::<String>()
+                .split(' ')
+                .any(|m| m == modifier)
+        })
+    }
+
+    pub fn location(&self) -> Location {
+        (self.source, self.line_number, self.start_col)
+    }
+
+    /// Whether a variable declaration is a `const`.
+    pub fn is_const(&self) -> bool {
+        self.is(Production::Vardec) && self.has_modifier("const")
+    }
+
+    /// Whether a member is marked `private`, which the
+    /// `unused_private_field` lint looks at.
+    pub fn is_private(&self) -> bool {
+        self.is(Production::Vardec) && self.has_modifier("private")
+    }
+
+    /// Whether a top level declaration is exported to other modules.
+    pub fn is_exported(&self) -> bool {
+        self.has_modifier("export")
+    }
+
+    /// Attributes setting the level of lints for the declaration.
+    pub fn attributes(&self) -> impl Iterator<Item = &ASTNode> {
+        self.children.iter().filter(|c| c.is(Production::Attribute))
+    }
+
+    /// Whether a member is a method, i.e. initialised with a function
+    /// literal.
+    pub fn is_method(&self) -> bool {
+        self.is(Production::Vardec)
+            && self
+                .initializer()
+                .is_some_and(|i| i.is(Production::FunctionDec))
+    }
+
+    /// Dotted name spelled by an `Ident` or a chain of `Member`s on one,
+    /// `Geo.Shape` for `Geo.Shape`.
+    pub fn path(&self) -> Option<String> {
+        match &self.production {
+            Some(Production::Ident) => Some(self.name()),
+            Some(Production::Member) => {
+                Some(format!("{}.{}", self.children[0].path()?, self.name()))
+            }
+            _ => None,
+        }
+    }
+
+    /// Members of a class, snippet, enum or section declaration.
+    pub fn members(&self) -> &[ASTNode] {
+        match self.find(Production::BlockBody) {
+            Some(body) => &body.children,
+            None => &[],
+        }
+    }
 }
 
 #[derive(Debug, PartialEq)]
 pub struct Parser {
     tokens: Vec<lexer::Node>,
     root: ASTNode,
+    last_line: i32,
 }
 
 impl Parser {
+    /// `tokens` are expected in reverse order so the next one can be popped.
     pub fn new(tokens: Vec<lexer::Node>) -> Parser {
-        let root = ASTNode {
-            production: Some(Production::ProgStart),
-            children: vec![],
-            value: Some(vec![]),
-            operator: Some(vec![]),
-        };
-        Parser { tokens, root }
-    }
-
-    pub fn parse_assignment(&mut self) -> ASTNode {
-        let current_node = self.tokens.pop().unwrap();
-        let next_node = self.tokens.pop().unwrap();
-        println!("parse assignment curr node {:?}", current_node);
-        println!("parse assignment next node {:?}", next_node);
-        match current_node.lexeme {
-            Lexeme::Symbol(value) => match value {
-                Symbol::Assignment => ASTNode {
-                    children: vec![],
-                    operator: None,
-                    value: match next_node.lexeme {
-                        Lexeme::Number(value) => Some(value.to_string().chars().collect()), // fix me: need to make it to where value is vec<char> or num
-                        _ => panic!("Expected Variable dec found {:?}"),
-                    },
-                    production: Some(Production::Vardec),
-                },
-                Symbol::SemiColon => ASTNode {
-                    children: vec![],
-                    operator: None,
-                    value: None,
-                    production: None,
-                },
-                _ => panic!("Expected ':' found {:?}", value),
-            },
-            _ => panic!("Expected Assignment found {:?}", next_node.lexeme),
+        Parser {
+            tokens,
+            root: ASTNode::root(),
+            last_line: 1,
         }
     }
 
-    pub fn parse_type(&mut self) -> ASTNode {
-        let current_node = self.tokens.pop().unwrap();
-        let next_node = self.tokens.pop().unwrap();
-        println!("type {:?}", current_node);
-        ASTNode {
-            children: vec![self.parse_assignment()], // probably not always the case
-            operator: None,
-            value: match next_node.lexeme {
-                Lexeme::Identifier(value) => match value[..] {
-                    ['N', 'u', 'm', 'b', 'e', 'r'] => Some(value),
-                    ['S', 't', 'r', 'i', 'n', 'g'] => Some(value),
-                    _ => panic!("Expected String or Number found {:?}", value),
-                },
-                _ => panic!("Expected Identifier found {:?}", next_node.lexeme),
+    /// Whether every token has been consumed.
+    pub fn is_done(&self) -> bool {
+        self.tokens.is_empty()
+    }
+
+    fn peek(&self) -> Option<&Lexeme> {
+        self.tokens.last().map(|node| &node.lexeme)
+    }
+
+    /// Lexeme `n` tokens ahead, `peek_nth(0)` being the next one.
+    fn peek_nth(&self, n: usize) -> Option<&Lexeme> {
+        if n >= self.tokens.len() {
+            return None;
+        }
+        Some(&self.tokens[self.tokens.len() - 1 - n].lexeme)
+    }
+
+    fn check_symbol(&self, symbol: &Symbol) -> bool {
+        matches!(self.peek(), Some(Lexeme::Symbol(s)) if s == symbol)
+    }
+
+    fn check_keyword(&self, keyword: &Keyword) -> bool {
+        matches!(self.peek(), Some(Lexeme::Keyword(k)) if k == keyword)
+    }
+
+    fn eat_symbol(&mut self, symbol: &Symbol) -> bool {
+        if self.check_symbol(symbol) {
+            self.tokens.pop();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn next_token(&mut self) -> Result<lexer::Node, Diagnostic> {
+        match self.tokens.pop() {
+            Some(node) => {
+                self.last_line = node.line_number;
+                if let Lexeme::Overflow(digits) = &node.lexeme {
+                    return Err(Diagnostic::error(
+                        format!(
+                            "`{}` overflows a `Number`",
+                            digits.iter().collect::<String>()
+                        ),
+                        node.line_number,
+                        node.start_col,
+                    ));
+                }
+                Ok(node)
+            }
+            None => Err(Diagnostic::error(
+                String::from("Unexpected end of file"),
+                self.last_line,
+                0,
+            )),
+        }
+    }
+
+    /// Position of the next token, for nodes that start there.
+    fn here(&self) -> lexer::Node {
+        match self.tokens.last() {
+            Some(node) => node.clone(),
+            None => lexer::Node {
+                lexeme: Lexeme::Unknown,
+                line_number: self.last_line,
+                start_col: 0,
+                end_col: 0,
             },
-            production: Some(Production::TypeDec),
-        }
-    }
-
-    pub fn parse_vardec(&mut self) -> ASTNode {
-        let current_node = self.tokens.pop().unwrap();
-        let next_node = self.tokens.pop().unwrap();
-        println!("Current: {:?}", current_node);
-        println!("next: {:?}", next_node);
-        match current_node.lexeme {
-            Lexeme::Symbol(value) => match value {
-                Symbol::Colon => ASTNode {
-                    children: vec![self.parse_type()],
-                    operator: None,
-                    value: match next_node.lexeme {
-                        Lexeme::Keyword(value) => Some(vec!['w']),
-                        _ => panic!("Expected Variable dec found {:?}", next_node.lexeme),
-                    },
-                    production: Some(Production::Vardec),
-                },
-                _ => panic!("Expected ':' found {:?}", value),
+        }
+    }
+
+    pub fn throw_error(&self, expected: &str, found: &lexer::Node) -> Diagnostic {
+        Diagnostic::error(
+            format!("Expected {} found {:?}", expected, found.lexeme),
+            found.line_number,
+            found.start_col,
+        )
+    }
+
+    pub fn verify_next_symbol(
+        &mut self,
+        symbol_to_check: Symbol,
+    ) -> Result<lexer::Node, Diagnostic> {
+        let current_token = self.next_token()?;
+        match &current_token.lexeme {
+            Lexeme::Symbol(value) if *value == symbol_to_check => Ok(current_token),
+            _ => Err(self.throw_error(&format!("{:?}", symbol_to_check), &current_token)),
+        }
+    }
+
+    pub fn parse_ident(&mut self) -> Result<ASTNode, Diagnostic> {
+        let current_node = self.next_token()?;
+        match &current_node.lexeme {
+            Lexeme::Identifier(value) => {
+                Ok(ASTNode::new(Production::Ident, &current_node).with_value(value.clone()))
+            }
+            _ => Err(self.throw_error("Identifier", &current_node)),
+        }
+    }
+
+    /// `Name`, `Section.Name` or `Name<Type, ...>`, optionally followed by
+    /// any number of `[]` and `?`, short for `List<...>` and `Option<...>`.
+    pub fn parse_type(&mut self) -> Result<ASTNode, Diagnostic> {
+        let start = self.here();
+        let name = match &start.lexeme {
+            Lexeme::Function => {
+                self.next_token()?;
+                "Function".chars().collect()
+            }
+            Lexeme::Identifier(_) => self.parse_qualified_name()?,
+            _ => {
+                let found = self.next_token()?;
+                return Err(self.throw_error("type", &found));
+            }
+        };
+        let mut node = ASTNode::new(Production::TypeDec, &start).with_value(name);
+        if self.check_symbol(&Symbol::RelationshipOp(vec!['<'])) {
+            self.next_token()?;
+            loop {
+                node.children.push(self.parse_type()?);
+                if !self.eat_symbol(&Symbol::Comma) {
+                    break;
+                }
+            }
+            self.close_type_arguments()?;
+        }
+        loop {
+            let wrapper = if self.check_symbol(&Symbol::LBracket)
+                && self.peek_nth(1) == Some(&Lexeme::Symbol(Symbol::RBracket))
+            {
+                self.next_token()?;
+                "List"
+            } else if self.check_symbol(&Symbol::Question) {
+                "Option"
+            } else {
+                return Ok(node);
+            };
+            self.next_token()?;
+            node = ASTNode::new(Production::TypeDec, &start)
+                .with_value(wrapper.chars().collect())
+                .with_children(vec![node]);
+        }
+    }
+
+    /// Consumes the `>` closing a list of type arguments. The lexer reads
+    /// `>=` as one token, so for `List<Number>= ...` the `=` is put back.
+    fn close_type_arguments(&mut self) -> Result<(), Diagnostic> {
+        let token = self.next_token()?;
+        match &token.lexeme {
+            Lexeme::Symbol(Symbol::RelationshipOp(op)) if op[..] == ['>'] => Ok(()),
+            Lexeme::Symbol(Symbol::RelationshipOp(op)) if op[..] == ['>', '='] => {
+                self.tokens.push(lexer::Node {
+                    lexeme: Lexeme::Symbol(Symbol::Assignment),
+                    start_col: token.start_col + 1,
+                    ..token
+                });
+                Ok(())
+            }
+            _ => Err(self.throw_error("'>'", &token)),
+        }
+    }
+
+    /// `= expression`, the optional initialiser of a variable declaration.
+    pub fn parse_assignment(&mut self) -> Result<Option<ASTNode>, Diagnostic> {
+        if self.eat_symbol(&Symbol::Assignment) {
+            Ok(Some(self.parse_expression()?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Everything after `let` or `const`: `identifier (: type)? (=
+    /// expression)? ;`, where the type may only be left off when there is
+    /// a value to infer it from. The semicolon may be left off after a
+    /// function literal.
+    pub fn parse_vardec(&mut self, constant: bool) -> Result<ASTNode, Diagnostic> {
+        let ident = self.parse_ident()?;
+        let mut children = vec![];
+        if self.eat_symbol(&Symbol::Colon) {
+            children.push(self.parse_type()?);
+        }
+        let mut ends_in_block = false;
+        if let Some(initializer) = self.parse_assignment()? {
+            ends_in_block = initializer.is(Production::FunctionDec);
+            children.push(initializer);
+        }
+        if children.is_empty() {
+            return Err(Diagnostic::at(
+                format!("`{}` needs a type or a value", ident.name()),
+                &ident,
+            ));
+        }
+        if !(self.eat_symbol(&Symbol::SemiColon) || ends_in_block) {
+            let found = self.next_token()?;
+            return Err(self.throw_error("';'", &found));
+        }
+        Ok(ASTNode {
+            production: Some(Production::Vardec),
+            children,
+            operator: if constant {
+                Some("const".chars().collect())
+            } else {
+                None
             },
-            _ => panic!("Expected Symbol found {:?}", next_node.lexeme),
+            ..ident
+        })
+    }
+
+    fn parse_params(&mut self) -> Result<ASTNode, Diagnostic> {
+        let open = self.verify_next_symbol(Symbol::LParen)?;
+        let mut params = vec![];
+        while !self.eat_symbol(&Symbol::RParen) {
+            if !params.is_empty() {
+                self.verify_next_symbol(Symbol::Comma)?;
+            }
+            let ident = self.parse_ident()?;
+            self.verify_next_symbol(Symbol::Colon)?;
+            params.push(ASTNode {
+                production: Some(Production::Param),
+                children: vec![self.parse_type()?],
+                ..ident
+            });
+        }
+        Ok(ASTNode::new(Production::Params, &open).with_children(params))
+    }
+
+    /// `<T, U: Interface>`, the type parameters of a generic class or
+    /// function literal.
+    fn parse_type_params(&mut self) -> Result<ASTNode, Diagnostic> {
+        let start = self.next_token()?;
+        let mut params = vec![];
+        loop {
+            let ident = self.parse_ident()?;
+            let mut bound = vec![];
+            if self.eat_symbol(&Symbol::Colon) {
+                bound.push(self.parse_type()?);
+            }
+            params.push(ASTNode {
+                production: Some(Production::TypeParam),
+                children: bound,
+                ..ident
+            });
+            if !self.eat_symbol(&Symbol::Comma) {
+                break;
+            }
         }
+        self.close_type_arguments()?;
+        Ok(ASTNode::new(Production::TypeParams, &start).with_children(params))
     }
 
-    pub fn parse_ident(&mut self) -> ASTNode {
-        let current_node = self.tokens.pop().unwrap();
-        println!("Ident {:?}", current_node);
+    /// `<T>(params) ~Type { body }`, the type parameters and return type
+    /// being optional.
+    pub fn parse_function(&mut self) -> Result<ASTNode, Diagnostic> {
+        let start = self.here();
+        let mut children = vec![];
+        if self.check_symbol(&Symbol::RelationshipOp(vec!['<'])) {
+            children.push(self.parse_type_params()?);
+        }
+        children.push(self.parse_params()?);
+        if self.eat_symbol(&Symbol::ReturnType) {
+            children.push(self.parse_type()?);
+        }
+        children.push(self.parse_block()?);
+        Ok(ASTNode::new(Production::FunctionDec, &start).with_children(children))
+    }
+
+    /// `{ statements }`
+    pub fn parse_block(&mut self) -> Result<ASTNode, Diagnostic> {
+        let open = self.verify_next_symbol(Symbol::LCurly)?;
+        let mut statements = vec![];
+        while !self.eat_symbol(&Symbol::RCurly) {
+            statements.push(self.parse_statement()?);
+        }
+        Ok(ASTNode::new(Production::BlockBody, &open).with_children(statements))
+    }
+
+    pub fn parse_statement(&mut self) -> Result<ASTNode, Diagnostic> {
+        let start = self.here();
+        match &start.lexeme {
+            Lexeme::Symbol(Symbol::At) => {
+                let attributes = self.parse_attributes()?;
+                if !(self.check_keyword(&Keyword::LET) || self.check_keyword(&Keyword::CONST)) {
+                    let found = self.next_token()?;
+                    return Err(self.throw_error("variable declaration", &found));
+                }
+                let mut vardec = self.parse_statement()?;
+                vardec.children.extend(attributes);
+                Ok(vardec)
+            }
+            Lexeme::Keyword(Keyword::LET) => {
+                self.next_token()?;
+                self.parse_vardec(false)
+            }
+            Lexeme::Keyword(Keyword::CONST) => {
+                self.next_token()?;
+                let vardec = self.parse_vardec(true)?;
+                if vardec.initializer().is_none() {
+                    return Err(Diagnostic::at(
+                        format!("Constant `{}` needs a value", vardec.name()),
+                        &vardec,
+                    ));
+                }
+                Ok(vardec)
+            }
+            Lexeme::Keyword(Keyword::IF) => self.parse_if(),
+            Lexeme::Keyword(Keyword::FOR) => {
+                self.next_token()?;
+                self.verify_next_symbol(Symbol::LParen)?;
+                let ident = self.parse_ident()?;
+                let in_keyword = self.next_token()?;
+                if in_keyword.lexeme != Lexeme::Keyword(Keyword::IN) {
+                    return Err(self.throw_error("in", &in_keyword));
+                }
+                let iterated = self.parse_expression()?;
+                self.verify_next_symbol(Symbol::RParen)?;
+                let body = self.parse_block()?;
+                Ok(ASTNode::new(Production::For, &start)
+                    .with_value(ident.value.unwrap_or_default())
+                    .with_children(vec![iterated, body]))
+            }
+            Lexeme::Keyword(Keyword::RETURN) => {
+                self.next_token()?;
+                let mut children = vec![];
+                if !self.check_symbol(&Symbol::SemiColon) {
+                    children.push(self.parse_expression()?);
+                }
+                self.verify_next_symbol(Symbol::SemiColon)?;
+                Ok(ASTNode::new(Production::Return, &start).with_children(children))
+            }
+            Lexeme::Keyword(Keyword::BREAK) | Lexeme::Keyword(Keyword::CONTINUE) => {
+                let production = if start.lexeme == Lexeme::Keyword(Keyword::BREAK) {
+                    Production::Break
+                } else {
+                    Production::Continue
+                };
+                self.next_token()?;
+                self.verify_next_symbol(Symbol::SemiColon)?;
+                Ok(ASTNode::new(production, &start))
+            }
+            Lexeme::Keyword(Keyword::PRINT) => {
+                self.next_token()?;
+                self.verify_next_symbol(Symbol::LParen)?;
+                let argument = self.parse_expression()?;
+                self.verify_next_symbol(Symbol::RParen)?;
+                self.verify_next_symbol(Symbol::SemiColon)?;
+                Ok(ASTNode::new(Production::Print, &start).with_children(vec![argument]))
+            }
+            _ => {
+                let expression = self.parse_expression()?;
+                let operator = match &self.here().lexeme {
+                    Lexeme::Symbol(Symbol::Assignment) => Some(None),
+                    Lexeme::Symbol(Symbol::CompoundAssignment(operator)) => {
+                        Some(Some(vec![*operator]))
+                    }
+                    _ => None,
+                };
+                if let Some(operator) = operator {
+                    let equals = self.next_token()?;
+                    let assignable = [Production::Ident, Production::Member, Production::Index];
+                    if !assignable.iter().any(|p| expression.is(p.clone())) {
+                        return Err(Diagnostic::error(
+                            String::from(
+                                "Only variables, fields and list elements can be assigned to",
+                            ),
+                            equals.line_number,
+                            equals.start_col,
+                        ));
+                    }
+                    let value = self.parse_expression()?;
+                    self.verify_next_symbol(Symbol::SemiColon)?;
+                    return Ok(ASTNode {
+                        operator,
+                        ..ASTNode::new(Production::Assign, &start)
+                    }
+                    .with_children(vec![expression, value]));
+                }
+                // like a function literal, a match ends in a curly brace and
+                // needs no semicolon
+                if !(self.eat_symbol(&Symbol::SemiColon) || expression.is(Production::Match)) {
+                    let found = self.next_token()?;
+                    return Err(self.throw_error("';'", &found));
+                }
+                Ok(expression)
+            }
+        }
+    }
+
+    fn parse_if(&mut self) -> Result<ASTNode, Diagnostic> {
+        let start = self.next_token()?;
+        self.verify_next_symbol(Symbol::LParen)?;
+        let condition = self.parse_expression()?;
+        self.verify_next_symbol(Symbol::RParen)?;
+        let mut children = vec![condition, self.parse_block()?];
+        if self.check_keyword(&Keyword::ELSE) {
+            self.next_token()?;
+            if self.check_keyword(&Keyword::IF) {
+                children.push(self.parse_if()?);
+            } else {
+                children.push(self.parse_block()?);
+            }
+        }
+        Ok(ASTNode::new(Production::If, &start).with_children(children))
+    }
+
+    pub fn parse_expression(&mut self) -> Result<ASTNode, Diagnostic> {
+        self.parse_coalesce()
+    }
+
+    fn binary(lhs: ASTNode, operator: &str, rhs: ASTNode) -> ASTNode {
         ASTNode {
-            children: vec![], // probably not always the case
-            operator: None,
-            value: match current_node.lexeme {
-                Lexeme::Identifier(value) => Some(value),
-                _ => panic!("Expected Identifier found {:?}", current_node.lexeme),
-            },
-            production: None,
-        }
-    }
-
-    // pub fn verify_next_symbol(&mut self, symbol_to_check: Symbol) -> Result<Ok{
-    //     let current_token = self.tokens.pop().unwrap();
-    //     match current_token.lexeme {
-    //         Lexeme::Symbol(value) => match value {
-    //             symbol_to_check => Ok,
-    //             _ => panic!("Expected {:?} found {:?} ", symbol_to_check, value),
-    //         },
-    //         _ => panic!(
-    //             "Expected Symbol: {:?} found {:?} ",
-    //             symbol_to_check, current_token.lexeme
-    //         ),
-    //     }
-    // }
-
-    pub fn throw_error(&mut self) {}
-
-    pub fn parse(&mut self) -> ASTNode {
-        // check if first one is class
-        // println!("tokens: {:#?}", self.tokens);
-        let mut ast_node = ASTNode {
-            production: None,
+            production: Some(Production::Expression),
             children: vec![],
-            value: Some(vec![]),
-            operator: Some(vec![]),
+            value: None,
+            operator: Some(operator.chars().collect()),
+            line_number: lhs.line_number,
+            start_col: lhs.start_col,
+            source: lhs.source,
+        }
+        .with_children(vec![lhs, rhs])
+    }
+
+    /// `value ?? fallback`, binding loosest and grouping to the right.
+    fn parse_coalesce(&mut self) -> Result<ASTNode, Diagnostic> {
+        let lhs = self.parse_or()?;
+        if self.eat_symbol(&Symbol::Coalesce) {
+            let rhs = self.parse_coalesce()?;
+            return Ok(Parser::binary(lhs, "??", rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn parse_or(&mut self) -> Result<ASTNode, Diagnostic> {
+        let mut lhs = self.parse_and()?;
+        while self.eat_symbol(&Symbol::Or) {
+            let rhs = self.parse_and()?;
+            lhs = Parser::binary(lhs, "||", rhs);
+        }
+        Ok(lhs)
+    }
+
+    fn parse_and(&mut self) -> Result<ASTNode, Diagnostic> {
+        let mut lhs = self.parse_comparison()?;
+        while self.eat_symbol(&Symbol::And) {
+            let rhs = self.parse_comparison()?;
+            lhs = Parser::binary(lhs, "&&", rhs);
+        }
+        Ok(lhs)
+    }
+
+    fn parse_comparison(&mut self) -> Result<ASTNode, Diagnostic> {
+        let mut lhs = self.parse_additive()?;
+        loop {
+            let operator: String = match self.peek() {
+                Some(Lexeme::Symbol(Symbol::Comparison)) => String::from("=="),
+                Some(Lexeme::Symbol(Symbol::NotEqual)) => String::from("!="),
+                Some(Lexeme::Symbol(Symbol::RelationshipOp(op))) => op.iter().collect(),
+                _ => return Ok(lhs),
+            };
+            self.next_token()?;
+            let rhs = self.parse_additive()?;
+            lhs = Parser::binary(lhs, &operator, rhs);
+        }
+    }
+
+    fn parse_additive(&mut self) -> Result<ASTNode, Diagnostic> {
+        let mut lhs = self.parse_term()?;
+        while let Some(Lexeme::Symbol(Symbol::BinOp(op @ ('+' | '-')))) = self.peek() {
+            let operator = op.to_string();
+            self.next_token()?;
+            let rhs = self.parse_term()?;
+            lhs = Parser::binary(lhs, &operator, rhs);
+        }
+        Ok(lhs)
+    }
+
+    fn parse_term(&mut self) -> Result<ASTNode, Diagnostic> {
+        let mut lhs = self.parse_unary()?;
+        while let Some(Lexeme::Symbol(Symbol::BinOp(op @ ('*' | '/')))) = self.peek() {
+            let operator = op.to_string();
+            self.next_token()?;
+            let rhs = self.parse_unary()?;
+            lhs = Parser::binary(lhs, &operator, rhs);
+        }
+        Ok(lhs)
+    }
+
+    fn parse_unary(&mut self) -> Result<ASTNode, Diagnostic> {
+        let operator = if self.check_symbol(&Symbol::BinOp('-')) {
+            '-'
+        } else if self.check_symbol(&Symbol::Not) {
+            '!'
+        } else {
+            return self.parse_call();
         };
+        let start = self.next_token()?;
+        let operand = self.parse_unary()?;
+        Ok(ASTNode::new(Production::Unary, &start)
+            .with_operator(vec![operator])
+            .with_children(vec![operand]))
+    }
+
+    /// `( arguments )`
+    fn parse_arguments(&mut self) -> Result<Vec<ASTNode>, Diagnostic> {
+        self.verify_next_symbol(Symbol::LParen)?;
+        let mut arguments = vec![];
+        while !self.eat_symbol(&Symbol::RParen) {
+            if !arguments.is_empty() {
+                self.verify_next_symbol(Symbol::Comma)?;
+            }
+            arguments.push(self.parse_expression()?);
+        }
+        Ok(arguments)
+    }
+
+    /// Calls, indexing and member accesses following a primary expression.
+    fn parse_call(&mut self) -> Result<ASTNode, Diagnostic> {
+        let mut callee = self.parse_primary()?;
         loop {
-            let current_token = self.tokens.pop();
-            println!("Loop {:?}", current_token);
-            match current_token {
-                Some(node) => {
-                    match node.lexeme {
-                        Lexeme::Keyword(value) => {
-                            match value {
-                                Keyword::IF => {}
-                                Keyword::RETURN => {}
-                                Keyword::ELSE => {}
-                                Keyword::CLASS => {
-                                    // verify next symbol is curly
-                                    let n = ASTNode {
-                                        children: vec![self.parse_ident(), self.parse()],
-                                        operator: Some(vec![]),
-                                        value: Some(vec![]),
-                                        production: Some(Production::ClassDec),
-                                    };
-                                    self.root.children.push(n);
-                                }
-                                Keyword::LET => {
-                                    ast_node = ASTNode {
-                                        children: vec![self.parse_ident(), self.parse_vardec()],
-                                        operator: Some(vec![]),
-                                        value: Some(vec![]),
-                                        production: Some(Production::ClassDec),
-                                    };
-                                }
-                                Keyword::PRINT => {}
-                                Keyword::SECTION => {}
-                                Keyword::SNIPPET => {}
-                            }
+            let start = self.here();
+            if self.check_symbol(&Symbol::LParen) {
+                let mut children = vec![callee];
+                children.extend(self.parse_arguments()?);
+                callee = ASTNode::new(Production::Call, &start).with_children(children);
+            } else if self.eat_symbol(&Symbol::LBracket) {
+                let index = self.parse_expression()?;
+                self.verify_next_symbol(Symbol::RBracket)?;
+                callee = ASTNode::new(Production::Index, &start).with_children(vec![callee, index]);
+            } else if self.eat_symbol(&Symbol::Question) {
+                // `r?.m` is a safe access; a member of a propagated result
+                // needs parentheses, `(r?).m`
+                callee = ASTNode::new(Production::Try, &start).with_children(vec![callee]);
+            } else if self.check_symbol(&Symbol::Dot) || self.check_symbol(&Symbol::SafeDot) {
+                let safe = self.next_token()?.lexeme == Lexeme::Symbol(Symbol::SafeDot);
+                let ident = self.parse_ident()?;
+                callee = ASTNode {
+                    production: Some(Production::Member),
+                    children: vec![callee],
+                    operator: if safe { Some(vec!['?', '.']) } else { None },
+                    ..ident
+                };
+            } else {
+                return Ok(callee);
+            }
+        }
+    }
+
+    /// `(` starts a function literal when followed by `)` or `name :`; `<`
+    /// can only start a generic one.
+    fn at_function_literal(&self) -> bool {
+        if self.check_symbol(&Symbol::RelationshipOp(vec!['<'])) {
+            return true;
+        }
+        if !self.check_symbol(&Symbol::LParen) {
+            return false;
+        }
+        matches!(
+            (self.peek_nth(1), self.peek_nth(2)),
+            (Some(Lexeme::Symbol(Symbol::RParen)), _)
+                | (
+                    Some(Lexeme::Identifier(_)),
+                    Some(Lexeme::Symbol(Symbol::Colon))
+                )
+        )
+    }
+
+    fn parse_primary(&mut self) -> Result<ASTNode, Diagnostic> {
+        if self.at_function_literal() {
+            return self.parse_function();
+        }
+        let current_node = self.next_token()?;
+        match &current_node.lexeme {
+            Lexeme::Number(value) => Ok(ASTNode::new(Production::NumberLiteral, &current_node)
+                .with_value(value.to_string().chars().collect())),
+            Lexeme::Word(value) => {
+                Ok(ASTNode::new(Production::WordLiteral, &current_node).with_value(value.clone()))
+            }
+            Lexeme::Boolean(value) => Ok(ASTNode::new(Production::BooleanLiteral, &current_node)
+                .with_value(value.to_string().chars().collect())),
+            Lexeme::Identifier(value) => {
+                Ok(ASTNode::new(Production::Ident, &current_node).with_value(value.clone()))
+            }
+            Lexeme::Keyword(Keyword::THIS) => Ok(ASTNode::new(Production::This, &current_node)),
+            Lexeme::Keyword(Keyword::NULL) => {
+                Ok(ASTNode::new(Production::NullLiteral, &current_node))
+            }
+            Lexeme::Keyword(Keyword::OK) | Lexeme::Keyword(Keyword::ERR) => {
+                let production = if current_node.lexeme == Lexeme::Keyword(Keyword::OK) {
+                    Production::Ok
+                } else {
+                    Production::Err
+                };
+                self.verify_next_symbol(Symbol::LParen)?;
+                let value = self.parse_expression()?;
+                self.verify_next_symbol(Symbol::RParen)?;
+                Ok(ASTNode::new(production, &current_node).with_children(vec![value]))
+            }
+            Lexeme::Keyword(Keyword::MATCH) => self.parse_match(&current_node),
+            Lexeme::Keyword(Keyword::SUPER) => {
+                if !self.check_symbol(&Symbol::Dot) {
+                    let found = self.next_token()?;
+                    return Err(self.throw_error("'.' after super", &found));
+                }
+                Ok(ASTNode::new(Production::Super, &current_node))
+            }
+            Lexeme::Keyword(Keyword::NEW) => {
+                let name = self.parse_qualified_name()?;
+                let arguments = self.parse_arguments()?;
+                Ok(ASTNode::new(Production::New, &current_node)
+                    .with_value(name)
+                    .with_children(arguments))
+            }
+            Lexeme::Symbol(Symbol::LParen) => {
+                let expression = self.parse_expression()?;
+                self.verify_next_symbol(Symbol::RParen)?;
+                Ok(expression)
+            }
+            Lexeme::Symbol(Symbol::LBracket) => {
+                let mut elements = vec![];
+                while !self.eat_symbol(&Symbol::RBracket) {
+                    if !elements.is_empty() {
+                        self.verify_next_symbol(Symbol::Comma)?;
+                    }
+                    elements.push(self.parse_expression()?);
+                }
+                Ok(ASTNode::new(Production::ListLiteral, &current_node).with_children(elements))
+            }
+            // blocks are only ever parsed where a statement or match arm
+            // expects one, so a curly brace in an expression always opens a
+            // map literal
+            Lexeme::Symbol(Symbol::LCurly) => {
+                let mut entries = vec![];
+                while !self.eat_symbol(&Symbol::RCurly) {
+                    if !entries.is_empty() {
+                        self.verify_next_symbol(Symbol::Comma)?;
+                    }
+                    let key = self.parse_expression()?;
+                    self.verify_next_symbol(Symbol::Colon)?;
+                    let value = self.parse_expression()?;
+                    entries.push(
+                        ASTNode::new(Production::MapEntry, &current_node)
+                            .with_children(vec![key, value]),
+                    );
+                }
+                Ok(ASTNode::new(Production::MapLiteral, &current_node).with_children(entries))
+            }
+            _ => Err(self.throw_error("expression", &current_node)),
+        }
+    }
+
+    /// `match (value) { pattern => expression, pattern => { block } }`, the
+    /// `match` keyword already consumed. Commas separate the arms and may be
+    /// left off after a block.
+    fn parse_match(&mut self, start: &lexer::Node) -> Result<ASTNode, Diagnostic> {
+        self.verify_next_symbol(Symbol::LParen)?;
+        let mut children = vec![self.parse_expression()?];
+        self.verify_next_symbol(Symbol::RParen)?;
+        self.verify_next_symbol(Symbol::LCurly)?;
+        while !self.eat_symbol(&Symbol::RCurly) {
+            let pattern = self.parse_pattern()?;
+            self.verify_next_symbol(Symbol::FatArrow)?;
+            let body = if self.check_symbol(&Symbol::LCurly) {
+                self.parse_block()?
+            } else {
+                self.parse_expression()?
+            };
+            let separated = self.eat_symbol(&Symbol::Comma);
+            if !(separated || body.is(Production::BlockBody) || self.check_symbol(&Symbol::RCurly))
+            {
+                let found = self.next_token()?;
+                return Err(self.throw_error("',' or '}'", &found));
+            }
+            children.push(ASTNode {
+                production: Some(Production::MatchArm),
+                children: vec![pattern.clone(), body],
+                value: None,
+                operator: None,
+                ..pattern
+            });
+        }
+        Ok(ASTNode::new(Production::Match, start).with_children(children))
+    }
+
+    /// A literal, `_`, a name to bind the value to, a variant written with
+    /// its enum name as in `Shape.Circle(r)`, or `Ok(p)` or `Err(p)`.
+    fn parse_pattern(&mut self) -> Result<ASTNode, Diagnostic> {
+        let start = self.here();
+        match &start.lexeme {
+            Lexeme::Keyword(keyword @ (Keyword::OK | Keyword::ERR)) => {
+                let name = if *keyword == Keyword::OK { "Ok" } else { "Err" };
+                self.next_token()?;
+                self.verify_next_symbol(Symbol::LParen)?;
+                let inner = self.parse_pattern()?;
+                self.verify_next_symbol(Symbol::RParen)?;
+                Ok(ASTNode::new(Production::VariantPattern, &start)
+                    .with_value(name.chars().collect())
+                    .with_children(vec![inner]))
+            }
+            Lexeme::Number(_) | Lexeme::Word(_) | Lexeme::Boolean(_) => self.parse_primary(),
+            Lexeme::Symbol(Symbol::BinOp('-')) => {
+                self.next_token()?;
+                let number = self.next_token()?;
+                match number.lexeme {
+                    Lexeme::Number(value) => Ok(ASTNode::new(Production::NumberLiteral, &start)
+                        .with_value(format!("-{}", value).chars().collect())),
+                    _ => Err(self.throw_error("number", &number)),
+                }
+            }
+            Lexeme::Identifier(name) if name[..] == ['_'] => {
+                self.next_token()?;
+                Ok(ASTNode::new(Production::WildcardPattern, &start))
+            }
+            Lexeme::Identifier(_) => {
+                let name = self.parse_qualified_name()?;
+                if !name.contains(&'.') {
+                    if self.check_symbol(&Symbol::LParen) {
+                        return Err(Diagnostic::error(
+                            String::from("Variants are matched with their enum name, as in `Shape.Circle(r)`"),
+                            start.line_number,
+                            start.start_col,
+                        ));
+                    }
+                    return Ok(ASTNode::new(Production::BindingPattern, &start).with_value(name));
+                }
+                let mut fields = vec![];
+                if self.eat_symbol(&Symbol::LParen) {
+                    while !self.eat_symbol(&Symbol::RParen) {
+                        if !fields.is_empty() {
+                            self.verify_next_symbol(Symbol::Comma)?;
                         }
-                        Lexeme::Identifier(value) => {}
-                        Lexeme::Symbol(value) => match value {
-                            _ => {}
-                        },
-                        _ => {}
+                        fields.push(self.parse_pattern()?);
+                    }
+                }
+                Ok(ASTNode::new(Production::VariantPattern, &start)
+                    .with_value(name)
+                    .with_children(fields))
+            }
+            _ => {
+                let found = self.next_token()?;
+                Err(self.throw_error("pattern", &found))
+            }
+        }
+    }
+
+    /// `Name` or `Section.Name`.
+    fn parse_qualified_name(&mut self) -> Result<Vec<char>, Diagnostic> {
+        let mut name = self.parse_ident()?.value.unwrap_or_default();
+        while self.eat_symbol(&Symbol::Dot) {
+            name.push('.');
+            name.extend(self.parse_ident()?.value.unwrap_or_default());
+        }
+        Ok(name)
+    }
+
+    /// `@level(lint, ...)` attributes, as many as there are. Each lint
+    /// named becomes an Attribute node.
+    fn parse_attributes(&mut self) -> Result<Vec<ASTNode>, Diagnostic> {
+        let mut attributes = vec![];
+        while self.eat_symbol(&Symbol::At) {
+            let level = self.parse_ident()?;
+            if !["allow", "warn", "deny"].contains(&level.name().as_str()) {
+                return Err(Diagnostic::at(
+                    format!(
+                        "Unknown lint level `{}`, expected `allow`, `warn` or `deny`",
+                        level.name()
+                    ),
+                    &level,
+                ));
+            }
+            self.verify_next_symbol(Symbol::LParen)?;
+            loop {
+                let lint = self.parse_ident()?;
+                attributes.push(ASTNode {
+                    production: Some(Production::Attribute),
+                    operator: level.value.clone(),
+                    ..lint
+                });
+                if !self.eat_symbol(&Symbol::Comma) {
+                    break;
+                }
+            }
+            self.verify_next_symbol(Symbol::RParen)?;
+        }
+        Ok(attributes)
+    }
+
+    /// `{ members }` of a class or snippet. Members are variable
+    /// declarations, which may be `private`, plus `include Snippet;` when
+    /// `allow_include` is set.
+    fn parse_member_block(&mut self, allow_include: bool) -> Result<ASTNode, Diagnostic> {
+        let open = self.verify_next_symbol(Symbol::LCurly)?;
+        let mut members = vec![];
+        while !self.eat_symbol(&Symbol::RCurly) {
+            let attributes = self.parse_attributes()?;
+            let mut current_node = self.next_token()?;
+            let private = current_node.lexeme == Lexeme::Keyword(Keyword::PRIVATE);
+            if private {
+                current_node = self.next_token()?;
+            }
+            let mut member = match &current_node.lexeme {
+                Lexeme::Keyword(Keyword::LET) => self.parse_vardec(false)?,
+                Lexeme::Keyword(Keyword::CONST) => self.parse_vardec(true)?,
+                _ if private => return Err(self.throw_error("let or const", &current_node)),
+                Lexeme::Keyword(Keyword::INCLUDE) if allow_include && attributes.is_empty() => {
+                    let name = self.parse_qualified_name()?;
+                    self.verify_next_symbol(Symbol::SemiColon)?;
+                    ASTNode::new(Production::Include, &current_node).with_value(name)
+                }
+                _ => return Err(self.throw_error("member declaration", &current_node)),
+            };
+            if private {
+                let modifiers = match member.operator.take() {
+                    Some(modifier) => format!("private {}", modifier.iter().collect::<String>()),
+                    None => String::from("private"),
+                };
+                member.operator = Some(modifiers.chars().collect());
+            }
+            member.children.extend(attributes);
+            members.push(member);
+        }
+        Ok(ASTNode::new(Production::BlockBody, &open).with_children(members))
+    }
+
+    /// `class Name { members }`, the `class` keyword already consumed.
+    fn parse_class(&mut self, start: &lexer::Node) -> Result<ASTNode, Diagnostic> {
+        let ident = self.parse_ident()?;
+        let mut children = vec![];
+        if self.check_symbol(&Symbol::RelationshipOp(vec!['<'])) {
+            children.push(self.parse_type_params()?);
+        }
+        if self.check_keyword(&Keyword::EXTENDS) {
+            let extends = self.next_token()?;
+            let parent = self.parse_qualified_name()?;
+            children.push(ASTNode::new(Production::Extends, &extends).with_value(parent));
+        }
+        if self.check_keyword(&Keyword::IMPLEMENTS) {
+            self.next_token()?;
+            loop {
+                let start = self.here();
+                let interface = self.parse_qualified_name()?;
+                children.push(ASTNode::new(Production::Implements, &start).with_value(interface));
+                if !self.eat_symbol(&Symbol::Comma) {
+                    break;
+                }
+            }
+        }
+        children.push(self.parse_member_block(true)?);
+        Ok(ASTNode::new(Production::ClassDec, start)
+            .with_value(ident.value.unwrap_or_default())
+            .with_children(children))
+    }
+
+    /// `snippet Name { members }`, the `snippet` keyword already consumed.
+    fn parse_snippet(&mut self, start: &lexer::Node) -> Result<ASTNode, Diagnostic> {
+        let ident = self.parse_ident()?;
+        let body = self.parse_member_block(false)?;
+        Ok(ASTNode::new(Production::SnippetDec, start)
+            .with_value(ident.value.unwrap_or_default())
+            .with_children(vec![body]))
+    }
+
+    /// `interface Name { name(params) ~Type; ... }`, the `interface`
+    /// keyword already consumed.
+    fn parse_interface(&mut self, start: &lexer::Node) -> Result<ASTNode, Diagnostic> {
+        let ident = self.parse_ident()?;
+        let open = self.verify_next_symbol(Symbol::LCurly)?;
+        let mut methods = vec![];
+        while !self.eat_symbol(&Symbol::RCurly) {
+            let name = self.parse_ident()?;
+            let mut children = vec![self.parse_params()?];
+            if self.eat_symbol(&Symbol::ReturnType) {
+                children.push(self.parse_type()?);
+            }
+            self.verify_next_symbol(Symbol::SemiColon)?;
+            methods.push(ASTNode {
+                production: Some(Production::MethodSig),
+                children,
+                ..name
+            });
+        }
+        Ok(ASTNode::new(Production::InterfaceDec, start)
+            .with_value(ident.value.unwrap_or_default())
+            .with_children(vec![
+                ASTNode::new(Production::BlockBody, &open).with_children(methods)
+            ]))
+    }
+
+    /// `enum Name { Variant, Variant(Type, ...), ... }`, the `enum` keyword
+    /// already consumed. A trailing comma is allowed.
+    fn parse_enum(&mut self, start: &lexer::Node) -> Result<ASTNode, Diagnostic> {
+        let ident = self.parse_ident()?;
+        let open = self.verify_next_symbol(Symbol::LCurly)?;
+        let mut variants = vec![];
+        while !self.eat_symbol(&Symbol::RCurly) {
+            let name = self.parse_ident()?;
+            let mut fields = vec![];
+            if self.eat_symbol(&Symbol::LParen) {
+                while !self.eat_symbol(&Symbol::RParen) {
+                    if !fields.is_empty() {
+                        self.verify_next_symbol(Symbol::Comma)?;
                     }
+                    fields.push(self.parse_type()?);
                 }
-                None => break,
             }
+            variants.push(ASTNode {
+                production: Some(Production::Variant),
+                children: fields,
+                ..name
+            });
+            if !self.eat_symbol(&Symbol::Comma) && !self.check_symbol(&Symbol::RCurly) {
+                let found = self.next_token()?;
+                return Err(self.throw_error("',' or '}'", &found));
+            }
+        }
+        Ok(ASTNode::new(Production::EnumDec, start)
+            .with_value(ident.value.unwrap_or_default())
+            .with_children(vec![
+                ASTNode::new(Production::BlockBody, &open).with_children(variants)
+            ]))
+    }
+
+    /// Declarations allowed both at the top level and inside sections.
+    fn parse_declaration(&mut self, node: &lexer::Node) -> Result<ASTNode, Diagnostic> {
+        match &node.lexeme {
+            Lexeme::Keyword(Keyword::CLASS) => self.parse_class(node),
+            Lexeme::Keyword(Keyword::SNIPPET) => self.parse_snippet(node),
+            Lexeme::Keyword(Keyword::INTERFACE) => self.parse_interface(node),
+            Lexeme::Keyword(Keyword::ENUM) => self.parse_enum(node),
+            _ => Err(self.throw_error("class, snippet, interface or enum declaration", node)),
+        }
+    }
+
+    /// `section Name { declarations }`, the `section` keyword already
+    /// consumed.
+    fn parse_section(&mut self, start: &lexer::Node) -> Result<ASTNode, Diagnostic> {
+        let ident = self.parse_ident()?;
+        let open = self.verify_next_symbol(Symbol::LCurly)?;
+        let mut declarations = vec![];
+        while !self.eat_symbol(&Symbol::RCurly) {
+            let attributes = self.parse_attributes()?;
+            let node = self.next_token()?;
+            let mut declaration = self.parse_declaration(&node)?;
+            declaration.children.extend(attributes);
+            declarations.push(declaration);
+        }
+        Ok(ASTNode::new(Production::SectionDec, start)
+            .with_value(ident.value.unwrap_or_default())
+            .with_children(vec![
+                ASTNode::new(Production::BlockBody, &open).with_children(declarations)
+            ]))
+    }
+
+    /// `import { Name, ... } from "path";`, the `import` keyword already
+    /// consumed.
+    fn parse_import(&mut self, start: &lexer::Node) -> Result<ASTNode, Diagnostic> {
+        self.verify_next_symbol(Symbol::LCurly)?;
+        let mut names = vec![];
+        loop {
+            names.push(self.parse_ident()?);
+            if !self.eat_symbol(&Symbol::Comma) {
+                break;
+            }
+        }
+        self.verify_next_symbol(Symbol::RCurly)?;
+        let from = self.next_token()?;
+        if from.lexeme != Lexeme::Keyword(Keyword::FROM) {
+            return Err(self.throw_error("from", &from));
         }
-        ast_node
+        let path = self.next_token()?;
+        let path = match path.lexeme {
+            Lexeme::Word(path) => path,
+            _ => return Err(self.throw_error("module path", &path)),
+        };
+        self.verify_next_symbol(Symbol::SemiColon)?;
+        Ok(ASTNode::new(Production::Import, start)
+            .with_value(path)
+            .with_children(names))
+    }
+
+    /// Parses a program (`.xo`) file.
+    pub fn parse(&mut self) -> Result<ASTNode, Diagnostic> {
+        while !self.tokens.is_empty() {
+            let attributes = self.parse_attributes()?;
+            let node = self.next_token()?;
+            let mut declaration = match &node.lexeme {
+                Lexeme::Keyword(Keyword::SECTION) => {
+                    return Err(Diagnostic::error(
+                        String::from("Sections can only be declared in .xs files"),
+                        node.line_number,
+                        node.start_col,
+                    ))
+                }
+                Lexeme::Keyword(Keyword::IMPORT) if attributes.is_empty() => {
+                    self.parse_import(&node)?
+                }
+                Lexeme::Keyword(Keyword::EXPORT) => {
+                    let declared = self.next_token()?;
+                    ASTNode {
+                        operator: Some("export".chars().collect()),
+                        ..self.parse_declaration(&declared)?
+                    }
+                }
+                _ => self.parse_declaration(&node)?,
+            };
+            declaration.children.extend(attributes);
+            self.root.children.push(declaration);
+        }
+        Ok(self.root.clone())
+    }
+
+    /// Parses a section (`.xs`) file, which holds nothing but sections.
+    pub fn parse_section_source(&mut self) -> Result<ASTNode, Diagnostic> {
+        while let Some(node) = self.tokens.pop() {
+            self.last_line = node.line_number;
+            let declaration = match &node.lexeme {
+                Lexeme::Keyword(Keyword::SECTION) => self.parse_section(&node)?,
+                _ => return Err(self.throw_error("section declaration", &node)),
+            };
+            self.root.children.push(declaration);
+        }
+        Ok(self.root.clone())
+    }
+}
+
+/// A parser over the tokens of `text`.
+#[cfg(test)]
+pub fn parser_for(text: &str) -> Parser {
+    let mut tokens = lexer::Lexer::new(&text.chars().collect()).lex();
+    tokens.reverse();
+    Parser::new(tokens)
+}
+
+/// `text` parsed as a program, which it has to be.
+#[cfg(test)]
+pub fn parse_text(text: &str) -> ASTNode {
+    parser_for(text).parse().unwrap()
+}
+
+/// `text` parsed as a section source, which it has to be.
+#[cfg(test)]
+pub fn parse_section_text(text: &str) -> ASTNode {
+    parser_for(text).parse_section_source().unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Result<ASTNode, Diagnostic> {
+        parser_for(text).parse()
+    }
+
+    #[test]
+    fn parse_snippet_members() {
+        let ast = parse("snippet Greeter { let name: Word = \"Frank\"; let greet: Function = (p1: Number) ~Number { return p1 + 1; } }").unwrap();
+        let snippet = &ast.children[0];
+        assert!(snippet.is(Production::SnippetDec));
+        assert_eq!(snippet.name(), "Greeter");
+        let names: Vec<String> = snippet.members().iter().map(|m| m.name()).collect();
+        assert_eq!(names, vec!["name", "greet"]);
+        assert!(snippet.members()[1].children[1].is(Production::FunctionDec));
+    }
+
+    #[test]
+    fn parse_class_include() {
+        let ast = parse("class Person { include Greeter; let age: Number = 12 + 13; }").unwrap();
+        let members = ast.children[0].members();
+        assert!(members[0].is(Production::Include));
+        assert_eq!(members[0].name(), "Greeter");
+        assert_eq!(members[1].children[1].operator, Some(vec!['+']));
+    }
+
+    #[test]
+    fn parse_extends_and_super() {
+        let ast = parse(
+            "class Student extends People.Person { let greet: Function = () ~Word { return super.greet(); } }",
+        )
+        .unwrap();
+        let class = &ast.children[0];
+        assert_eq!(
+            class.find(Production::Extends).unwrap().name(),
+            "People.Person"
+        );
+        assert_eq!(class.members().len(), 1);
+        assert!(parse("class A extends B { let f: Function = () { return super; } }").is_err());
+    }
+
+    #[test]
+    fn parse_interface() {
+        let ast = parse(
+            "interface Greeter { greet(other: Word) ~Word; wave(); }
+             class Person extends Base implements Greeter, Shapes.Named { }",
+        )
+        .unwrap();
+        let interface = &ast.children[0];
+        assert!(interface.is(Production::InterfaceDec));
+        let greet = &interface.members()[0];
+        assert!(greet.is(Production::MethodSig));
+        assert_eq!(greet.find(Production::TypeDec).unwrap().name(), "Word");
+        assert!(interface.members()[1].find(Production::TypeDec).is_none());
+        let implements: Vec<String> = ast.children[1]
+            .children
+            .iter()
+            .filter(|c| c.is(Production::Implements))
+            .map(|c| c.name())
+            .collect();
+        assert_eq!(implements, vec!["Greeter", "Shapes.Named"]);
+    }
+
+    #[test]
+    fn parse_lists() {
+        let ast = parse(
+            "class Bag {
+                let items: List<Number>= [1, 2, 3];
+                let grid: Number[][];
+                let sum: Function = (xs: Number[]) ~Number {
+                    for (x in xs) { this.items[0] = this.items[0] + x; }
+                    return this.items.length;
+                }
+            }",
+        )
+        .unwrap();
+        let members = ast.children[0].members();
+        let items_type = &members[0].children[0];
+        assert_eq!(items_type.name(), "List");
+        assert_eq!(items_type.children[0].name(), "Number");
+        assert_eq!(members[0].children[1].children.len(), 3);
+        let grid_type = &members[1].children[0];
+        assert_eq!(grid_type.children[0].name(), "List");
+        assert_eq!(grid_type.children[0].children[0].name(), "Number");
+        let body = members[2]
+            .initializer()
+            .unwrap()
+            .find(Production::BlockBody)
+            .unwrap();
+        let for_loop = &body.children[0];
+        assert!(for_loop.is(Production::For));
+        assert_eq!(for_loop.name(), "x");
+        let assign = &for_loop.children[1].children[0];
+        assert!(assign.children[0].is(Production::Index));
+        assert_eq!(body.children[1].children[0].name(), "length");
+    }
+
+    #[test]
+    fn parse_maps() {
+        let ast = parse(
+            "class Ages {
+                let ages: Map<Word, Number> = { \"Ada\": 36, \"Alan\": 41 };
+                let empty: Map<Number, Boolean> = {};
+                let update: Function = (name: Word) {
+                    if (this.ages.has(name)) { this.ages[name] = this.ages[name] + 1; }
+                    this.ages.remove(\"Alan\");
+                }
+            }",
+        )
+        .unwrap();
+        let members = ast.children[0].members();
+        let map_type = &members[0].children[0];
+        assert_eq!(map_type.name(), "Map");
+        assert_eq!(map_type.children.len(), 2);
+        let literal = &members[0].children[1];
+        assert!(literal.is(Production::MapLiteral));
+        assert_eq!(literal.children[1].children[0].name(), "Alan");
+        assert!(members[1].children[1].children.is_empty());
+    }
+
+    #[test]
+    fn parse_section_source() {
+        let ast = parse_section_text(
+            "section Shapes { class Circle { include Shapes.Round; } snippet Round { } }",
+        );
+        let section = &ast.children[0];
+        assert!(section.is(Production::SectionDec));
+        let names: Vec<String> = section.members().iter().map(|m| m.name()).collect();
+        assert_eq!(names, vec!["Circle", "Round"]);
+        assert_eq!(section.members()[0].members()[0].name(), "Shapes.Round");
+        assert!(parse("section Shapes { }").is_err());
+    }
+
+    #[test]
+    fn parse_methods_and_instances() {
+        let ast = parse(
+            "class Person {
+                let name: Word;
+                let init: Function = (name: Word) { this.name = name; }
+                let greet: Function = () ~Word { return new Person(\"Bob\").name; }
+            }",
+        )
+        .unwrap();
+        let members = ast.children[0].members();
+        assert!(!members[0].is_method());
+        assert!(members[1].is_method());
+        let init_body = members[1]
+            .initializer()
+            .unwrap()
+            .find(Production::BlockBody)
+            .unwrap();
+        let assign = &init_body.children[0];
+        assert!(assign.is(Production::Assign));
+        assert!(assign.children[0].is(Production::Member));
+        assert!(assign.children[0].children[0].is(Production::This));
+        let greet_body = members[2]
+            .initializer()
+            .unwrap()
+            .find(Production::BlockBody)
+            .unwrap();
+        let member = &greet_body.children[0].children[0];
+        assert_eq!(member.name(), "name");
+        assert!(member.children[0].is(Production::New));
+        assert!(parse("class A { let f: Function = () { f() = 1; } }").is_err());
+    }
+
+    #[test]
+    fn parse_enums_and_match() {
+        let ast = parse(
+            "enum Shape { Circle(Number), Rect(Number, Number), Empty, }
+             class Geometry {
+                let area: Function = (shape: Shape) ~Number {
+                    match (shape) { Shape.Empty => { print(\"empty\"); } _ => { } }
+                    return match (shape) {
+                        Shape.Circle(0) => 0,
+                        Shape.Rect(w, _) => w,
+                        -1 => 1
+                    };
+                }
+            }",
+        )
+        .unwrap();
+        let variants = ast.children[0].members();
+        assert!(variants[1].is(Production::Variant));
+        assert_eq!(variants[1].children.len(), 2);
+        assert!(variants[2].children.is_empty());
+        let body = ast.children[1].members()[0]
+            .initializer()
+            .unwrap()
+            .find(Production::BlockBody)
+            .unwrap();
+        assert!(body.children[0].children[1].children[1].is(Production::BlockBody));
+        let arms = &body.children[1].children[0].children[1..];
+        assert_eq!(arms[0].children[0].name(), "Shape.Circle");
+        assert!(arms[0].children[0].children[0].is(Production::NumberLiteral));
+        let rect = &arms[1].children[0];
+        assert!(rect.children[0].is(Production::BindingPattern));
+        assert!(rect.children[1].is(Production::WildcardPattern));
+        assert_eq!(arms[2].children[0].name(), "-1");
+        assert!(
+            parse("class A { let f: Function = () { match (1) { Circle(r) => 1 } } }").is_err()
+        );
+    }
+
+    #[test]
+    fn parse_optionals() {
+        let ast = parse(
+            "class Person {
+                let friend: Person?;
+                let nicknames: Word?[]?;
+                let ages: Option<Number[]>;
+                let describe: Function = () ~Word {
+                    if (this.friend != null) { return this.friend?.name ?? \"x\" ?? \"y\"; }
+                    return null;
+                }
+            }",
+        )
+        .unwrap();
+        let members = ast.children[0].members();
+        let friend = &members[0].children[0];
+        assert_eq!(friend.name(), "Option");
+        assert_eq!(friend.children[0].name(), "Person");
+        let nicknames = &members[1].children[0];
+        assert_eq!(nicknames.name(), "Option");
+        assert_eq!(nicknames.children[0].name(), "List");
+        assert_eq!(nicknames.children[0].children[0].name(), "Option");
+        assert_eq!(members[2].children[0].children[0].name(), "List");
+        let body = members[3]
+            .initializer()
+            .unwrap()
+            .find(Production::BlockBody)
+            .unwrap();
+        let condition = &body.children[0].children[0];
+        assert_eq!(condition.operator, Some(vec!['!', '=']));
+        assert!(condition.children[1].is(Production::NullLiteral));
+        let coalesce = &body.children[0].children[1].children[0].children[0];
+        assert_eq!(coalesce.operator, Some(vec!['?', '?']));
+        assert_eq!(coalesce.children[0].operator, Some(vec!['?', '.']));
+        assert_eq!(coalesce.children[1].operator, Some(vec!['?', '?']));
+    }
+
+    #[test]
+    fn parse_generics() {
+        let ast = parse(
+            "class Pair<A, B: Shapes.Named> extends Base {
+                let first: A;
+                let swap: Function = <T>(x: T) ~Pair<B, A> { return x; }
+            }",
+        )
+        .unwrap();
+        let class = &ast.children[0];
+        let params = class.find(Production::TypeParams).unwrap();
+        assert_eq!(params.children[0].name(), "A");
+        assert!(params.children[0].children.is_empty());
+        assert_eq!(params.children[1].children[0].name(), "Shapes.Named");
+        assert!(class.find(Production::Extends).is_some());
+        let swap = class.members()[1].initializer().unwrap();
+        assert!(swap.children[0].is(Production::TypeParams));
+        assert_eq!(swap.find(Production::TypeDec).unwrap().children.len(), 2);
+    }
+
+    #[test]
+    fn parse_results() {
+        let ast = parse(
+            "class Reader {
+                let read: Function = (name: Word) ~Result<Word, Number> {
+                    let size: Number = (this.size(name)?).length;
+                    match (this.open(name)) { Ok(file) => { return Ok(file); } Err(_) => { } }
+                    return Err(size);
+                }
+            }",
+        )
+        .unwrap();
+        let read = ast.children[0].members()[0].initializer().unwrap();
+        assert_eq!(read.find(Production::TypeDec).unwrap().children.len(), 2);
+        let body = read.find(Production::BlockBody).unwrap();
+        let length = &body.children[0].children[1];
+        assert!(length.children[0].is(Production::Try));
+        let arms = &body.children[1].children[1..];
+        assert_eq!(arms[0].children[0].name(), "Ok");
+        assert!(arms[1].children[0].children[0].is(Production::WildcardPattern));
+        assert!(body.children[2].children[0].is(Production::Err));
+    }
+
+    #[test]
+    fn parse_assignments() {
+        let ast = parse(
+            "class Counter {
+                const start: Number = 0;
+                let tick: Function = () { let n: Number = 1; n = 2; n *= 3; this.start -= n; }
+            }",
+        )
+        .unwrap();
+        let members = ast.children[0].members();
+        assert!(members[0].is_const());
+        assert!(!members[1].is_const());
+        let body = members[1]
+            .initializer()
+            .unwrap()
+            .find(Production::BlockBody)
+            .unwrap();
+        assert!(body.children[1].children[0].is(Production::Ident));
+        assert_eq!(body.children[1].operator, None);
+        assert_eq!(body.children[2].operator, Some(vec!['*']));
+        assert!(body.children[3].children[0].is(Production::Member));
+        assert!(parse("class A { let f: Function = () { const x: Number; } }").is_err());
+        let inferred = parse("class A { let x = 1; const y = \"a\"; }").unwrap();
+        assert!(inferred.children[0].members()[1]
+            .find(Production::TypeDec)
+            .is_none());
+        assert!(parse("class A { let x; }").is_err());
+    }
+
+    #[test]
+    fn parse_imports_and_exports() {
+        let ast = parse(
+            "import { Person, Greeter } from \"./person.xo\";
+             export class Student extends Person { }
+             class Helper { }",
+        )
+        .unwrap();
+        let import = &ast.children[0];
+        assert!(import.is(Production::Import));
+        assert_eq!(import.name(), "./person.xo");
+        let names: Vec<String> = import.children.iter().map(|n| n.name()).collect();
+        assert_eq!(names, vec!["Person", "Greeter"]);
+        assert!(ast.children[1].is_exported());
+        assert!(!ast.children[2].is_exported());
+        assert!(parse("import { } from \"./a.xo\";").is_err());
+        assert!(parse("import { A } \"./a.xo\";").is_err());
+    }
+
+    #[test]
+    fn parse_attributes_and_private_members() {
+        let ast = parse(
+            "@allow(naming) @deny(unused_variable, shadowing)
+             class a {
+                 @warn(unused_private_field) private const x: Number;
+                 let f: Function = () { @allow(unused_variable) let y = 1; };
+             }",
+        )
+        .unwrap();
+        let class = &ast.children[0];
+        let levels: Vec<(String, String)> = class
+            .attributes()
+            .map(|a| (a.name(), a.operator.iter().flatten().collect()))
+            .collect();
+        let level = |lint: &str, level: &str| (String::from(lint), String::from(level));
+        assert_eq!(
+            levels,
+            vec![
+                level("naming", "allow"),
+                level("unused_variable", "deny"),
+                level("shadowing", "deny"),
+            ]
+        );
+        let x = &class.members()[0];
+        assert!(x.is_private() && x.is_const());
+        assert!(x.initializer().is_none());
+        assert_eq!(x.attributes().count(), 1);
+        let f = &class.members()[1];
+        assert!(!f.is_private());
+        let y = &f
+            .initializer()
+            .unwrap()
+            .find(Production::BlockBody)
+            .unwrap()
+            .children[0];
+        assert_eq!(y.attributes().next().unwrap().name(), "unused_variable");
+        assert!(parse("class A { @allow(naming) include B; }").is_err());
+        assert!(parse("class A { private include B; }").is_err());
+        assert!(parse("@forbid(naming) class A { }").is_err());
+        assert!(parse("@allow(naming) import { A } from \"./a.xo\";").is_err());
+    }
+
+    #[test]
+    fn include_outside_class_is_an_error() {
+        assert!(parse("snippet A { include B; }").is_err());
+    }
+
+    #[test]
+    fn numbers_too_large_are_an_error() {
+        let error = parse("class A { let n: Number = 99999999999999999999; }").unwrap_err();
+        assert_eq!(error.message, "`99999999999999999999` overflows a `Number`");
+        assert_eq!(error.line_number, 1);
     }
 }
@@ -0,0 +1,462 @@
+use super::cfg;
+use super::checker;
+use super::codegen;
+use super::dataflow;
+use super::diagnostic::Diagnostic;
+use super::fold;
+use super::interpreter;
+use super::lexer;
+use super::lexer::Lexeme;
+use super::lexer::Symbol;
+use super::parser::ASTNode;
+use super::parser::Parser;
+use super::parser::Production;
+use super::resolver;
+use std::io;
+use std::io::BufRead;
+use std::io::Write;
+
+/// Class the statements typed so far are checked in, as the body of its
+/// `main` method.
+const SESSION_CLASS: &str = "__Repl";
+
+fn tokens(text: &str) -> Vec<lexer::Node> {
+    let mut tokens = lexer::Lexer::new(&text.chars().collect()).lex();
+    tokens.reverse();
+    tokens
+}
+
+/// How many more `{` than `}` there are in `text`.
+fn open_braces(text: &str) -> i32 {
+    tokens(text)
+        .iter()
+        .map(|token| match token.lexeme {
+            Lexeme::Symbol(Symbol::LCurly) => 1,
+            Lexeme::Symbol(Symbol::RCurly) => -1,
+            _ => 0,
+        })
+        .sum()
+}
+
+/// `text` parsed as one expression and nothing else.
+fn parse_expression(text: &str) -> Result<ASTNode, Diagnostic> {
+    let mut parser = Parser::new(tokens(text));
+    let expression = parser.parse_expression()?;
+    if !parser.is_done() {
+        return Err(Diagnostic::error(
+            String::from("Expected a single expression"),
+            expression.line_number,
+            expression.start_col,
+        ));
+    }
+    Ok(expression)
+}
+
+/// Runs the stages after parsing, stopping at the first that finds
+/// errors. Warnings are left out, they would be repeated for every input.
+fn compile(program: &ASTNode) -> Result<ASTNode, Vec<Diagnostic>> {
+    let errors = |diagnostics: Vec<Diagnostic>| {
+        let errors: Vec<Diagnostic> = diagnostics.into_iter().filter(|d| d.is_error()).collect();
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    };
+    errors(resolver::resolve(program))?;
+    let program = codegen::include_snippets(&codegen::flatten_sections(program));
+    errors(checker::check(&program))?;
+    errors(cfg::check(&program))?;
+    errors(dataflow::check(&program))?;
+    let program = codegen::lower_operators(&program);
+    let (program, diagnostics) = fold::fold(&program);
+    errors(diagnostics)?;
+    Ok(program)
+}
+
+/// A program assembled from what was typed so far and the input being
+/// tried, which starts at column 1 of line `first_line`.
+struct Attempt {
+    text: String,
+    first_line: i32,
+    lines: i32,
+    /// Line the statements added to the session's `main` start on.
+    start: i32,
+    /// Whether only the lines closing the program follow the input.
+    at_end: bool,
+}
+
+/// What has been typed in a REPL session. Each input is tried as part of a
+/// program made of the declarations accepted so far and a class whose
+/// `main` method holds the statements accepted so far, so the stages check
+/// it just as they would a file. Only the statements the input adds are
+/// run, with the variables the earlier ones left.
+#[derive(Default)]
+pub struct Session {
+    declarations: Vec<String>,
+    statements: Vec<String>,
+    locals: interpreter::Locals<'static>,
+}
+
+impl Session {
+    /// The program made of the session so far with `declaration` added to
+    /// its declarations, or `statement` to its statements.
+    fn attempt(&self, declaration: Option<&str>, statement: Option<&str>) -> Attempt {
+        let mut text = String::new();
+        for earlier in &self.declarations {
+            text.push_str(earlier);
+            text.push('\n');
+        }
+        let mut first_line = text.lines().count() as i32 + 1;
+        let mut start = 0;
+        if let Some(declaration) = declaration {
+            text.push_str(declaration);
+            text.push('\n');
+        }
+        text.push_str(&format!(
+            "class {} {{\nlet main: Function = () {{\n",
+            SESSION_CLASS
+        ));
+        for earlier in &self.statements {
+            text.push_str(earlier);
+            text.push('\n');
+        }
+        if let Some(statement) = statement {
+            first_line = text.lines().count() as i32 + 1;
+            start = first_line;
+            text.push_str(statement);
+            text.push('\n');
+        }
+        text.push_str("}\n}\n");
+        let lines = declaration.or(statement).unwrap_or("").lines().count() as i32;
+        Attempt {
+            text,
+            first_line,
+            lines,
+            start,
+            at_end: statement.is_some(),
+        }
+    }
+
+    /// The program made of the session so far with the expression `input`
+    /// added to its statements, on lines of its own between `opening` and
+    /// `closing`.
+    fn wrapped(&self, opening: &str, input: &str, closing: &str) -> Attempt {
+        let statement = format!("{}\n{}\n{}", opening, input, closing);
+        let attempt = self.attempt(None, Some(&statement));
+        Attempt {
+            first_line: attempt.first_line + 1,
+            lines: input.lines().count() as i32,
+            ..attempt
+        }
+    }
+
+    /// Renders diagnostics found in `attempt` with their lines counted from
+    /// the start of the input.
+    fn render(attempt: &Attempt, diagnostics: Vec<Diagnostic>) -> String {
+        let files = vec![String::from("<input>"), String::from("<earlier input>")];
+        let relocate = |line_number: i32| {
+            let line = line_number - attempt.first_line + 1;
+            if line >= 1 && (line <= attempt.lines || attempt.at_end) {
+                // past the input only in the lines closing the program,
+                // when the input leaves something open
+                (0, line.min(attempt.lines))
+            } else {
+                (1, line_number)
+            }
+        };
+        let mut text = String::new();
+        for mut diagnostic in diagnostics {
+            let (source, line) = relocate(diagnostic.line_number);
+            diagnostic.source = source;
+            diagnostic.line_number = line;
+            for note in diagnostic.notes.iter_mut() {
+                let (source, line) = relocate(note.line_number);
+                note.source = source;
+                note.line_number = line;
+            }
+            text.push_str(&diagnostic.render(&files));
+            text.push('\n');
+        }
+        text
+    }
+
+    fn parse(attempt: &Attempt) -> Result<ASTNode, String> {
+        Parser::new(tokens(&attempt.text))
+            .parse()
+            .map_err(|d| Session::render(attempt, vec![d]))
+    }
+
+    /// Checks the program of `attempt` and runs the statements it adds to
+    /// the session. Returns what they printed and the value they returned.
+    /// A runtime error comes after what was printed before it.
+    fn run(&mut self, attempt: &Attempt) -> Result<(String, Option<String>), String> {
+        let program = Session::parse(attempt)?;
+        let program = compile(&program).map_err(|d| Session::render(attempt, d))?;
+        // values the statements make point into the program, so it is kept
+        // for as long as the session's variables may hold them
+        let program: &'static ASTNode = Box::leak(Box::new(program));
+        let body = program
+            .children
+            .iter()
+            .find(|d| d.name() == SESSION_CLASS)
+            .and_then(|class| class.members().iter().find(|m| m.name() == "main"))
+            .and_then(|main| main.initializer())
+            .and_then(|function| function.find(Production::BlockBody))
+            .expect("the session class has a main method");
+        let added = body
+            .children
+            .iter()
+            .position(|statement| statement.line_number >= attempt.start)
+            .unwrap_or(body.children.len());
+        let mut out = vec![];
+        let value = interpreter::resume(
+            program,
+            SESSION_CLASS,
+            "main",
+            &body.children[added..],
+            &mut self.locals,
+            &mut out,
+        );
+        let printed = String::from_utf8_lossy(&out).into_owned();
+        match value {
+            Ok(value) => Ok((printed, value)),
+            Err(d) => Err(printed + &Session::render(attempt, vec![d])),
+        }
+    }
+
+    /// Type of the expression `input`, checked in the program of the
+    /// session.
+    fn type_of(&self, input: &str) -> Result<String, String> {
+        let attempt = self.wrapped("let it = (", input, ");");
+        let program = Session::parse(&attempt)?;
+        let program = compile(&program).map_err(|d| Session::render(&attempt, d))?;
+        let declared = attempt.first_line - 1;
+        checker::inferred_types(&program)
+            .into_iter()
+            .find(|i| i.line_number == declared)
+            .map(|i| i.inferred.to_string())
+            .ok_or_else(|| String::from("error: This expression has no value\n"))
+    }
+
+    /// Evaluates the expression `input`, printing its value and type. One
+    /// that gives nothing is run as a statement. The expression is not kept
+    /// among the session's statements: it declares nothing later inputs are
+    /// checked against.
+    fn evaluate(&mut self, input: &str) -> String {
+        let found = match self.type_of(input) {
+            Ok(found) => found,
+            Err(_) => return self.statement(&format!("{};", input.trim_end())),
+        };
+        let attempt = self.wrapped("return (", input, ");");
+        match self.run(&attempt) {
+            Ok((printed, value)) => {
+                let value = value.unwrap_or_else(|| String::from("nothing"));
+                format!("{}{}: {}\n", printed, value, found)
+            }
+            Err(error) => error,
+        }
+    }
+
+    fn statement(&mut self, input: &str) -> String {
+        let attempt = self.attempt(None, Some(input));
+        match self.run(&attempt) {
+            Ok((printed, _)) => {
+                self.statements.push(String::from(input));
+                printed
+            }
+            Err(error) => error,
+        }
+    }
+
+    fn declaration(&mut self, input: &str) -> String {
+        let attempt = self.attempt(Some(input), None);
+        match Session::parse(&attempt).and_then(|p| {
+            compile(&p)
+                .map(|_| ())
+                .map_err(|d| Session::render(&attempt, d))
+        }) {
+            Ok(()) => {
+                self.declarations.push(String::from(input));
+                String::new()
+            }
+            Err(error) => error,
+        }
+    }
+
+    /// Handles one complete input and returns what to show for it.
+    pub fn input(&mut self, input: &str) -> String {
+        let input = input.trim();
+        if input.is_empty() {
+            return String::new();
+        }
+        for (command, show) in [(":type", 0), (":ast", 1), (":tokens", 2)] {
+            if let Some(expression) = input.strip_prefix(command) {
+                let expression = expression.trim();
+                return match show {
+                    0 => self
+                        .type_of(expression)
+                        .map(|found| format!("{}\n", found))
+                        .unwrap_or_else(|error| error),
+                    1 => match parse_expression(expression) {
+                        Ok(ast) => format!("{:#?}\n", ast),
+                        Err(error) => format!("{}\n", error.render(&[String::from("<input>")])),
+                    },
+                    _ => {
+                        let mut tokens = tokens(expression);
+                        tokens.reverse();
+                        format!("{:#?}\n", tokens)
+                    }
+                };
+            }
+        }
+        if input.starts_with(':') {
+            return String::from("error: Unknown command; try :type, :ast, :tokens or :quit\n");
+        }
+        if Parser::new(tokens(input)).parse().is_ok() {
+            return self.declaration(input);
+        }
+        if parse_expression(input).is_ok() {
+            return self.evaluate(input);
+        }
+        if input.ends_with(';') || input.ends_with('}') {
+            self.statement(input)
+        } else {
+            // the semicolon ending a single statement can be left out
+            self.statement(&format!("{};", input))
+        }
+    }
+}
+
+/// Reads inputs from stdin until it ends or `:quit` is typed. An input goes
+/// on over the following lines while it has unclosed braces.
+pub fn repl() {
+    let stdin = io::stdin();
+    let mut session = Session::default();
+    let mut pending = String::new();
+    loop {
+        print!(
+            "{}",
+            if pending.is_empty() {
+                "xonyx> "
+            } else {
+                "  ...> "
+            }
+        );
+        let _ = io::stdout().flush();
+        let mut line = String::new();
+        match stdin.lock().read_line(&mut line) {
+            Ok(0) | Err(_) => break,
+            Ok(_) => pending.push_str(&line),
+        }
+        if open_braces(&pending) > 0 {
+            continue;
+        }
+        let input = std::mem::take(&mut pending);
+        if input.trim() == ":quit" {
+            break;
+        }
+        print!("{}", session.input(&input));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    /// What the session shows for each input, in a thread with a stack as
+    /// deep as the one the REPL runs in.
+    fn session(inputs: &'static [&'static str]) -> Vec<String> {
+        thread::Builder::new()
+            .stack_size(1 << 28)
+            .spawn(move || {
+                let mut session = Session::default();
+                inputs.iter().map(|input| session.input(input)).collect()
+            })
+            .unwrap()
+            .join()
+            .unwrap()
+    }
+
+    #[test]
+    fn keeps_definitions_across_inputs() {
+        let shown = session(&[
+            "class Counter {\n  let count: Number = 3;\n  let twice: Function = (x: Number) ~Number {\n    return x * 2;\n  }\n}",
+            "let c: Counter = new Counter();",
+            "c.twice(c.count)",
+            "print(\"set\"); c.count = 5;",
+            "c.count + 1",
+            "\"a\" + \"b\"",
+            "print(\"hi\")",
+            "[1, 2]",
+        ]);
+        assert_eq!(
+            shown,
+            vec![
+                "",
+                "",
+                "6: Number\n",
+                "set\n",
+                "6: Number\n",
+                "\"ab\": Word\n",
+                "hi\n",
+                "[1, 2]: List<Number>\n",
+            ]
+        );
+    }
+
+    #[test]
+    fn meta_commands_and_errors() {
+        let shown = session(&[
+            "let n: Number = 2;",
+            ":type n * 2",
+            ":type n + \"s\"",
+            ":ast n",
+            ":tokens n",
+            "missing + 1",
+            "n / 0",
+            "let m: Number = n;",
+            "m",
+            ":what",
+        ]);
+        assert_eq!(shown[1], "Number\n");
+        assert_eq!(shown[2], "Word\n");
+        assert!(shown[3].contains("Ident"));
+        assert!(shown[4].contains("Identifier"));
+        assert_eq!(shown[5], "<input>:1:1: error: Unknown name `missing`\n");
+        assert!(shown[6].contains("Division by zero"));
+        assert_eq!(shown[8], "2: Number\n");
+        assert!(shown[9].starts_with("error: Unknown command"));
+    }
+
+    #[test]
+    fn runs_each_input_once() {
+        let shown = session(&[
+            "class A { let f: Function = () ~Result<Number, Word> { return Ok(1); } }",
+            "new A().f()",
+            "1 + 1",
+            "let x: Number = 2;",
+            "print(\"before\"); print(x / (x - 2));",
+            "x",
+        ]);
+        assert_eq!(
+            shown,
+            vec![
+                "",
+                "Ok(1): Result<Number, Word>\n",
+                "2: Number\n",
+                "",
+                "before\n<input>:1:26: error: Division by zero\n",
+                "2: Number\n",
+            ]
+        );
+    }
+
+    #[test]
+    fn counts_unclosed_braces() {
+        assert_eq!(open_braces("class A {"), 1);
+        assert_eq!(open_braces("let f: Function = () { if (true) { }"), 1);
+        assert_eq!(open_braces("class A { }"), 0);
+    }
+}
@@ -0,0 +1,1467 @@
+use super::diagnostic::Diagnostic;
+use super::parser::ASTNode;
+use super::parser::Location;
+use super::parser::Production;
+use super::types::Type;
+use super::types::BUILTIN_TYPES;
+use std::collections::HashMap;
+
+/// Top level declarations of a program by qualified name. Classes and
+/// snippets declared inside a section are stored as `Section.Name`, and
+/// the names an imported module keeps to itself as `Name@source`.
+#[derive(Debug, Default)]
+pub struct SymbolTable<'a> {
+    pub declarations: HashMap<String, &'a ASTNode>,
+    pub sections: HashMap<String, &'a ASTNode>,
+    /// Qualified names of the private declarations of each source, by the
+    /// name they are declared with.
+    pub private: HashMap<usize, HashMap<String, String>>,
+}
+
+impl<'a> SymbolTable<'a> {
+    /// Qualified name of a top level declaration outside any section.
+    /// Exported names are shared by the whole project; the others are
+    /// private to their module, so they are qualified by its source unless
+    /// they belong to the entry file, which the program is run from.
+    pub fn qualified(declaration: &ASTNode) -> String {
+        if declaration.is_exported() || declaration.source == 0 {
+            declaration.name()
+        } else {
+            format!("{}@{}", declaration.name(), declaration.source)
+        }
+    }
+
+    /// Looks `name` up as written inside `section` of the file at `source`:
+    /// members of the enclosing section and private declarations of the file
+    /// are visible unqualified, everything else needs its qualified name.
+    /// Returns the qualified name alongside the declaration.
+    pub fn lookup(
+        &self,
+        source: usize,
+        section: Option<&str>,
+        name: &str,
+    ) -> Option<(String, &'a ASTNode)> {
+        if let Some(section) = section {
+            let qualified = format!("{}.{}", section, name);
+            if let Some(node) = self.declarations.get(&qualified) {
+                return Some((qualified, *node));
+            }
+        }
+        let qualified = self
+            .private
+            .get(&source)
+            .and_then(|names| names.get(name))
+            .map_or(name, String::as_str);
+        self.declarations
+            .get(qualified)
+            .map(|node| (String::from(qualified), *node))
+    }
+
+    /// Name a private declaration is written with, `Helper` for `Helper@1`.
+    pub fn written(qualified: &str) -> &str {
+        qualified.split('@').next().unwrap_or(qualified)
+    }
+
+    /// Section a qualified name belongs to, `Shapes` for `Shapes.Circle`.
+    pub fn section_of(qualified: &str) -> Option<&str> {
+        qualified.rfind('.').map(|dot| &qualified[..dot])
+    }
+
+    /// Members declared by a class followed by those of the snippets it
+    /// includes.
+    pub fn members_of(&self, class: &'a ASTNode, section: Option<&str>) -> Vec<&'a ASTNode> {
+        let mut members = vec![];
+        for member in class.members() {
+            if !member.is(Production::Include) {
+                members.push(member);
+            } else if let Some((_, snippet)) = self.lookup(member.source, section, &member.name()) {
+                members.extend(snippet.members());
+            }
+        }
+        members
+    }
+
+    /// Class named by the `extends` clause of `class`, with its qualified
+    /// name.
+    pub fn parent(&self, class: &ASTNode, section: Option<&str>) -> Option<(String, &'a ASTNode)> {
+        let extends = class.find(Production::Extends)?;
+        self.lookup(extends.source, section, &extends.name())
+            .filter(|(_, parent)| parent.is(Production::ClassDec))
+    }
+
+    /// Ancestors of a class, nearest first. The walk stops before visiting a
+    /// class twice so that inheritance cycles terminate.
+    pub fn ancestors(&self, class: &ASTNode, section: Option<&str>) -> Vec<(String, &'a ASTNode)> {
+        let mut ancestors: Vec<(String, &'a ASTNode)> = vec![];
+        let mut current = self.parent(class, section);
+        while let Some((name, parent)) = current {
+            if std::ptr::eq(parent, class) || ancestors.iter().any(|(n, _)| *n == name) {
+                break;
+            }
+            current = self.parent(parent, SymbolTable::section_of(&name));
+            ancestors.push((name, parent));
+        }
+        ancestors
+    }
+
+    /// Names of every member reachable through an instance of `class`,
+    /// inherited ones included.
+    pub fn member_names(&self, class: &'a ASTNode, section: Option<&str>) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .members_of(class, section)
+            .iter()
+            .map(|m| m.name())
+            .collect();
+        for (name, ancestor) in self.ancestors(class, section) {
+            let members = self.members_of(ancestor, SymbolTable::section_of(&name));
+            names.extend(members.iter().map(|m| m.name()));
+        }
+        names
+    }
+
+    /// Constructor run by `new` for `class`, which may be inherited.
+    pub fn constructor(&self, class: &'a ASTNode, section: Option<&str>) -> Option<&'a ASTNode> {
+        let own = self.members_of(class, section);
+        let inherited = self
+            .ancestors(class, section)
+            .into_iter()
+            .flat_map(|(name, ancestor)| self.members_of(ancestor, SymbolTable::section_of(&name)));
+        own.into_iter()
+            .chain(inherited)
+            .find(|m| m.name() == "init" && m.is_method())
+    }
+}
+
+/// Members `this.` and `super.` can reach inside the methods of a class.
+struct ClassContext {
+    members: Vec<String>,
+    parent_members: Option<Vec<String>>,
+}
+
+/// A function literal using locals of the functions it is nested in, found
+/// by where it was parsed.
+///
+/// Captures are by reference: the closure and the function declaring a
+/// captured local share one variable, so an assignment on either side is
+/// seen by the other, and the variable lives as long as the longest lived
+/// closure capturing it. `this` is captured like a local.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Capture {
+    pub source: usize,
+    pub line_number: i32,
+    pub start_col: usize,
+    /// Captured names in order of first use.
+    pub names: Vec<String>,
+}
+
+/// A use of a local, parameter or loop variable and where it was declared.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Reference {
+    pub source: usize,
+    pub line_number: i32,
+    pub start_col: usize,
+    pub declaration: Location,
+}
+
+/// Names declared in one block so far, and the locals it declares further
+/// down, which cannot be used yet.
+#[derive(Debug, Default)]
+struct Scope<'a> {
+    declared: Vec<(String, &'a ASTNode)>,
+    later: Vec<&'a ASTNode>,
+}
+
+/// Scopes of a function being walked, innermost block last, and the locals
+/// of enclosing functions it uses. The program, class and snippet scopes
+/// around it are the symbol table and the members of the declaration.
+#[derive(Debug, Default)]
+struct Frame<'a> {
+    scopes: Vec<Scope<'a>>,
+    captures: Vec<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct Resolver<'a> {
+    pub symbols: SymbolTable<'a>,
+    /// Type parameters of the generic classes and functions being walked.
+    type_params: Vec<String>,
+    /// Functions being walked, outermost first.
+    frames: Vec<Frame<'a>>,
+    pub captures: Vec<Capture>,
+    pub references: Vec<Reference>,
+    /// Names each source imports.
+    imports: HashMap<usize, Vec<String>>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Resolver<'a> {
+    pub fn new() -> Resolver<'a> {
+        Resolver::default()
+    }
+
+    /// Looks `name` up for `node` like `SymbolTable::lookup`, reporting a
+    /// declaration of another module that `node`'s file did not import or
+    /// that its module keeps private.
+    fn lookup(
+        &mut self,
+        section: Option<&str>,
+        name: &str,
+        node: &ASTNode,
+    ) -> Option<(String, &'a ASTNode)> {
+        let found = self.symbols.lookup(node.source, section, name);
+        if found.is_none() {
+            let private = self
+                .symbols
+                .private
+                .values()
+                .find_map(|names| names.get(name))
+                .and_then(|qualified| self.symbols.declarations.get_key_value(qualified))
+                .map(|(qualified, declaration)| (qualified.clone(), *declaration));
+            if let Some((qualified, declaration)) = private {
+                self.diagnostics.push(
+                    Diagnostic::at(format!("`{}` is private to another module", name), node)
+                        .with_note(String::from("declared here"), declaration),
+                );
+                return Some((qualified, declaration));
+            }
+        }
+        if let Some((qualified, declaration)) = &found {
+            let imported = self
+                .imports
+                .get(&node.source)
+                .is_some_and(|names| names.contains(qualified));
+            if declaration.source != node.source
+                && SymbolTable::section_of(qualified).is_none()
+                && !imported
+            {
+                self.diagnostics.push(
+                    Diagnostic::at(
+                        format!(
+                            "`{}` is declared in another module and has to be imported",
+                            qualified
+                        ),
+                        node,
+                    )
+                    .with_note(String::from("declared here"), declaration),
+                );
+            }
+        }
+        found
+    }
+
+    fn duplicate(&mut self, message: String, node: &ASTNode, previous: &ASTNode) {
+        self.diagnostics.push(
+            Diagnostic::at(message, node).with_note(String::from("first declared here"), previous),
+        );
+    }
+
+    fn declare(&mut self, name: String, node: &'a ASTNode) {
+        if BUILTIN_TYPES.contains(&node.name().as_str()) {
+            self.diagnostics.push(Diagnostic::at(
+                format!("`{}` is the name of a built in type", node.name()),
+                node,
+            ));
+            return;
+        }
+        if let Some(previous) = self.symbols.declarations.get(&name).copied() {
+            self.duplicate(
+                format!(
+                    "`{}` is declared more than once",
+                    SymbolTable::written(&name)
+                ),
+                node,
+                previous,
+            );
+            return;
+        }
+        if let Some(section) = self.symbols.sections.get(&name).copied() {
+            self.duplicate(
+                format!("`{}` is already the name of a section", name),
+                node,
+                section,
+            );
+            return;
+        }
+        self.symbols.declarations.insert(name, node);
+    }
+
+    /// Reports members of one class or snippet body, or variants of one enum,
+    /// sharing a name.
+    fn check_duplicate_members(&mut self, declaration: &'a ASTNode) {
+        let mut seen: HashMap<String, &ASTNode> = HashMap::new();
+        for member in declaration.members() {
+            if member.is(Production::Include) {
+                continue;
+            }
+            if let Some(previous) = seen.get(&member.name()).copied() {
+                self.duplicate(
+                    format!(
+                        "`{}` already has a {} named `{}`",
+                        declaration.name(),
+                        if declaration.is(Production::EnumDec) {
+                            "variant"
+                        } else {
+                            "member"
+                        },
+                        member.name()
+                    ),
+                    member,
+                    previous,
+                );
+            } else {
+                seen.insert(member.name(), member);
+            }
+        }
+    }
+
+    /// Resolves the `include` members of a class and reports members that
+    /// more than one included snippet provides. A member declared in the
+    /// class itself takes precedence and so settles the conflict.
+    fn resolve_includes(&mut self, class: &'a ASTNode, section: Option<&str>) {
+        let own: Vec<String> = class
+            .members()
+            .iter()
+            .filter(|m| !m.is(Production::Include))
+            .map(|m| m.name())
+            .collect();
+        let mut provided: HashMap<String, (&ASTNode, &ASTNode)> = HashMap::new();
+        let mut included: Vec<String> = vec![];
+        for include in class.members().iter().filter(|m| m.is(Production::Include)) {
+            let (name, snippet) = match self.lookup(section, &include.name(), include) {
+                Some((name, node)) if node.is(Production::SnippetDec) => (name, node),
+                Some((name, _)) => {
+                    self.diagnostics.push(Diagnostic::at(
+                        format!("`{}` is not a snippet and cannot be included", name),
+                        include,
+                    ));
+                    continue;
+                }
+                None => {
+                    self.diagnostics.push(Diagnostic::at(
+                        format!("Unknown snippet `{}`", include.name()),
+                        include,
+                    ));
+                    continue;
+                }
+            };
+            if included.contains(&name) {
+                self.diagnostics.push(Diagnostic::at(
+                    format!("Snippet `{}` is included more than once", name),
+                    include,
+                ));
+                continue;
+            }
+            included.push(name);
+            for member in snippet.members() {
+                if own.contains(&member.name()) {
+                    continue;
+                }
+                if let Some((other, previous)) = provided.get(&member.name()).copied() {
+                    self.diagnostics.push(
+                        Diagnostic::at(
+                            format!(
+                                "`{}` gets member `{}` from both snippet `{}` and snippet `{}`",
+                                class.name(),
+                                member.name(),
+                                other.name(),
+                                snippet.name()
+                            ),
+                            include,
+                        )
+                        .with_note(format!("defined in `{}` here", other.name()), previous)
+                        .with_note(format!("defined in `{}` here", snippet.name()), member),
+                    );
+                } else {
+                    provided.insert(member.name(), (snippet, member));
+                }
+            }
+        }
+    }
+
+    /// Reports a missing or non-class parent and classes that end up
+    /// inheriting from themselves.
+    fn resolve_extends(&mut self, class: &'a ASTNode, name: &str, section: Option<&str>) {
+        let extends = match class.find(Production::Extends) {
+            Some(extends) => extends,
+            None => return,
+        };
+        match self.lookup(section, &extends.name(), extends) {
+            Some((parent, node)) if node.find(Production::TypeParams).is_some() => {
+                self.diagnostics.push(Diagnostic::at(
+                    format!("`{}` is generic and cannot be extended", parent),
+                    extends,
+                ));
+                return;
+            }
+            Some((_, parent)) if parent.is(Production::ClassDec) => {}
+            Some((parent, _)) => {
+                self.diagnostics.push(Diagnostic::at(
+                    format!("`{}` is not a class and cannot be extended", parent),
+                    extends,
+                ));
+                return;
+            }
+            None => {
+                self.diagnostics.push(Diagnostic::at(
+                    format!("Unknown class `{}`", extends.name()),
+                    extends,
+                ));
+                return;
+            }
+        }
+        let mut path = vec![String::from(name)];
+        let mut current = self.symbols.parent(class, section);
+        while let Some((parent_name, parent)) = current {
+            path.push(parent_name.clone());
+            if parent_name == name {
+                self.diagnostics.push(Diagnostic::at(
+                    format!("`{}` inherits from itself: {}", name, path.join(" -> ")),
+                    extends,
+                ));
+                return;
+            }
+            if path[..path.len() - 1].contains(&parent_name) {
+                return;
+            }
+            current = self
+                .symbols
+                .parent(parent, SymbolTable::section_of(&parent_name));
+        }
+    }
+
+    /// Checks that members redeclaring an inherited one are methods
+    /// overriding a method with the same number of parameters. Constructors
+    /// are not inherited members and may differ freely.
+    fn check_overrides(&mut self, class: &'a ASTNode, section: Option<&str>) {
+        let ancestors = self.symbols.ancestors(class, section);
+        for member in self.symbols.members_of(class, section) {
+            if member.name() == "init" {
+                continue;
+            }
+            let inherited = ancestors.iter().find_map(|(name, ancestor)| {
+                self.symbols
+                    .members_of(ancestor, SymbolTable::section_of(name))
+                    .into_iter()
+                    .find(|m| m.name() == member.name())
+                    .map(|m| (name, m))
+            });
+            let (parent, overridden) = match inherited {
+                Some(inherited) => inherited,
+                None => continue,
+            };
+            if !(member.is_method() && overridden.is_method()) {
+                self.diagnostics.push(
+                    Diagnostic::at(
+                        format!(
+                            "`{}.{}` redeclares a member inherited from `{}`; only methods can be overridden",
+                            class.name(),
+                            member.name(),
+                            parent
+                        ),
+                        member,
+                    )
+                    .with_note(String::from("inherited member declared here"), overridden),
+                );
+                continue;
+            }
+            let arity = |m: &ASTNode| {
+                m.initializer()
+                    .and_then(|f| f.find(Production::Params))
+                    .map_or(0, |params| params.children.len())
+            };
+            if arity(member) != arity(overridden) {
+                self.diagnostics.push(
+                    Diagnostic::at(
+                        format!(
+                            "`{}.{}` takes {} parameter(s) but overrides a method of `{}` taking {}",
+                            class.name(),
+                            member.name(),
+                            arity(member),
+                            parent,
+                            arity(overridden)
+                        ),
+                        member,
+                    )
+                    .with_note(String::from("overridden method declared here"), overridden),
+                );
+            }
+        }
+    }
+
+    /// Reports `implements` clauses naming something other than an
+    /// interface.
+    fn resolve_implements(&mut self, class: &'a ASTNode, section: Option<&str>) {
+        for implements in class
+            .children
+            .iter()
+            .filter(|c| c.is(Production::Implements))
+        {
+            match self.lookup(section, &implements.name(), implements) {
+                Some((_, node)) if node.is(Production::InterfaceDec) => {}
+                Some((name, _)) => self.diagnostics.push(Diagnostic::at(
+                    format!("`{}` is not an interface and cannot be implemented", name),
+                    implements,
+                )),
+                None => self.diagnostics.push(Diagnostic::at(
+                    format!("Unknown interface `{}`", implements.name()),
+                    implements,
+                )),
+            }
+        }
+    }
+
+    /// Reports type annotations naming neither a built in type nor a class
+    /// or interface.
+    fn resolve_type(&mut self, node: &'a ASTNode, section: Option<&str>) {
+        if BUILTIN_TYPES.contains(&node.name().as_str()) {
+            let arity = Type::arity(&node.name());
+            if node.children.len() != arity {
+                self.diagnostics.push(Diagnostic::at(
+                    format!(
+                        "`{}` takes {} type argument(s) but {} were given",
+                        node.name(),
+                        arity,
+                        node.children.len()
+                    ),
+                    node,
+                ));
+            }
+            return;
+        }
+        if self.type_params.contains(&node.name()) {
+            if !node.children.is_empty() {
+                self.diagnostics.push(Diagnostic::at(
+                    format!(
+                        "`{}` is a type parameter and does not take type arguments",
+                        node.name()
+                    ),
+                    node,
+                ));
+            }
+            return;
+        }
+        let arity = match self.lookup(section, &node.name(), node) {
+            Some((_, declaration)) if declaration.is(Production::ClassDec) => declaration
+                .find(Production::TypeParams)
+                .map_or(0, |params| params.children.len()),
+            Some((_, declaration))
+                if declaration.is(Production::InterfaceDec)
+                    || declaration.is(Production::EnumDec) =>
+            {
+                0
+            }
+            Some((name, _)) => {
+                self.diagnostics
+                    .push(Diagnostic::at(format!("`{}` is not a type", name), node));
+                return;
+            }
+            None => {
+                self.diagnostics.push(Diagnostic::at(
+                    format!("Unknown type `{}`", node.name()),
+                    node,
+                ));
+                return;
+            }
+        };
+        if node.children.len() == arity {
+            return;
+        }
+        let message = if arity == 0 {
+            format!("`{}` does not take type arguments", node.name())
+        } else {
+            format!(
+                "`{}` takes {} type argument(s) but {} were given",
+                node.name(),
+                arity,
+                node.children.len()
+            )
+        };
+        self.diagnostics.push(Diagnostic::at(message, node));
+    }
+
+    /// Reports type parameters declared twice and bounds that are not
+    /// interfaces, then brings the parameters into scope. Returns how many
+    /// were added so the caller can drop them again.
+    fn enter_type_params(&mut self, params: Option<&'a ASTNode>, section: Option<&str>) -> usize {
+        let params = match params {
+            Some(params) => params,
+            None => return 0,
+        };
+        let mut seen: HashMap<String, &ASTNode> = HashMap::new();
+        for param in &params.children {
+            if let Some(previous) = seen.get(&param.name()).copied() {
+                self.duplicate(
+                    format!(
+                        "Type parameter `{}` is declared more than once",
+                        param.name()
+                    ),
+                    param,
+                    previous,
+                );
+            }
+            seen.insert(param.name(), param);
+            let bound = match param.children.first() {
+                Some(bound) => bound,
+                None => continue,
+            };
+            match self.lookup(section, &bound.name(), bound) {
+                Some((_, interface)) if interface.is(Production::InterfaceDec) => {}
+                Some((name, _)) => self.diagnostics.push(Diagnostic::at(
+                    format!(
+                        "`{}` is not an interface and cannot bound `{}`",
+                        name,
+                        param.name()
+                    ),
+                    bound,
+                )),
+                None => self.diagnostics.push(Diagnostic::at(
+                    format!("Unknown interface `{}`", bound.name()),
+                    bound,
+                )),
+            }
+        }
+        self.type_params
+            .extend(params.children.iter().map(|p| p.name()));
+        params.children.len()
+    }
+
+    fn leave_type_params(&mut self, count: usize) {
+        self.type_params.truncate(self.type_params.len() - count);
+    }
+
+    /// Checks `init`, the constructor called by `new`: it has to be a method
+    /// and cannot return a value.
+    fn check_constructor(&mut self, class: &'a ASTNode) {
+        if let Some(init) = class.members().iter().find(|m| m.name() == "init") {
+            if !init.is_method() {
+                self.diagnostics.push(Diagnostic::at(
+                    format!("`{}.init` has to be a method", class.name()),
+                    init,
+                ));
+            } else if let Some(return_type) =
+                init.initializer().and_then(|f| f.find(Production::TypeDec))
+            {
+                self.diagnostics.push(Diagnostic::at(
+                    format!("`{}.init` cannot declare a return type", class.name()),
+                    return_type,
+                ));
+            }
+        }
+    }
+
+    fn resolve_new(&mut self, node: &'a ASTNode, section: Option<&str>) {
+        let (name, class) = match self.lookup(section, &node.name(), node) {
+            Some((name, class)) if class.is(Production::ClassDec) => (name, class),
+            Some((name, _)) => {
+                self.diagnostics.push(Diagnostic::at(
+                    format!("`{}` is not a class and cannot be instantiated", name),
+                    node,
+                ));
+                return;
+            }
+            None => {
+                self.diagnostics.push(Diagnostic::at(
+                    format!("Unknown class `{}`", node.name()),
+                    node,
+                ));
+                return;
+            }
+        };
+        let expected = self
+            .symbols
+            .constructor(class, SymbolTable::section_of(&name))
+            .and_then(|init| init.initializer())
+            .and_then(|f| f.find(Production::Params))
+            .map_or(0, |params| params.children.len());
+        if expected != node.children.len() {
+            self.diagnostics.push(Diagnostic::at(
+                format!(
+                    "`{}` is constructed with {} argument(s) but {} were given",
+                    node.name(),
+                    expected,
+                    node.children.len()
+                ),
+                node,
+            ));
+        }
+    }
+
+    /// Enum named by an `Ident` or `Member` chain such as `Geo.Shape`.
+    fn enum_named(&self, node: &ASTNode, section: Option<&str>) -> Option<(String, &'a ASTNode)> {
+        self.symbols
+            .lookup(node.source, section, &node.path()?)
+            .filter(|(_, declaration)| declaration.is(Production::EnumDec))
+    }
+
+    /// Checks that `enum_name` is an enum with a variant called `variant`
+    /// carrying `given` values. `node` is the construction or pattern using
+    /// the variant.
+    fn resolve_variant(
+        &mut self,
+        enum_name: &str,
+        variant: &str,
+        given: usize,
+        node: &'a ASTNode,
+        section: Option<&str>,
+    ) {
+        let declaration = match self.lookup(section, enum_name, node) {
+            Some((_, declaration)) if declaration.is(Production::EnumDec) => declaration,
+            Some((name, _)) => {
+                self.diagnostics
+                    .push(Diagnostic::at(format!("`{}` is not an enum", name), node));
+                return;
+            }
+            None => {
+                self.diagnostics.push(Diagnostic::at(
+                    format!("Unknown enum `{}`", enum_name),
+                    node,
+                ));
+                return;
+            }
+        };
+        match declaration.members().iter().find(|v| v.name() == variant) {
+            Some(found) if found.children.len() != given => {
+                self.diagnostics.push(
+                    Diagnostic::at(
+                        format!(
+                            "`{}.{}` holds {} value(s) but {} were given",
+                            enum_name,
+                            variant,
+                            found.children.len(),
+                            given
+                        ),
+                        node,
+                    )
+                    .with_note(String::from("variant declared here"), found),
+                );
+            }
+            Some(_) => {}
+            None => self.diagnostics.push(Diagnostic::at(
+                format!("`{}` has no variant named `{}`", enum_name, variant),
+                node,
+            )),
+        }
+    }
+
+    /// Opens the scope of `block`, which may be a `BlockBody`, a `MatchArm`
+    /// or a `For`.
+    fn enter_scope(&mut self, block: &'a ASTNode) {
+        let later = block
+            .children
+            .iter()
+            .filter(|c| c.is(Production::Vardec))
+            .collect();
+        if let Some(frame) = self.frames.last_mut() {
+            frame.scopes.push(Scope {
+                declared: vec![],
+                later,
+            });
+        }
+    }
+
+    fn leave_scope(&mut self) {
+        if let Some(frame) = self.frames.last_mut() {
+            frame.scopes.pop();
+        }
+    }
+
+    fn declare_local(&mut self, name: String, node: &'a ASTNode) {
+        let scope = match self.frames.last_mut().and_then(|f| f.scopes.last_mut()) {
+            Some(scope) => scope,
+            None => return,
+        };
+        match scope.declared.iter().find(|(n, _)| *n == name) {
+            Some((_, previous)) => {
+                let previous = *previous;
+                self.duplicate(
+                    format!("`{}` is declared more than once in this scope", name),
+                    node,
+                    previous,
+                );
+            }
+            None => scope.declared.push((name, node)),
+        }
+    }
+
+    /// Records a use of `name` and returns where it was declared. A local of
+    /// an enclosing function is captured by every function between it and
+    /// the use, as each has to hand it on to the next.
+    fn use_local(&mut self, name: &str) -> Option<&'a ASTNode> {
+        let (declared_in, declaration) =
+            self.frames
+                .iter()
+                .enumerate()
+                .rev()
+                .find_map(|(i, frame)| {
+                    frame.scopes.iter().rev().find_map(|scope| {
+                        scope
+                            .declared
+                            .iter()
+                            .find(|(n, _)| n == name)
+                            .map(|(_, node)| (i, *node))
+                    })
+                })?;
+        for frame in &mut self.frames[declared_in + 1..] {
+            if !frame.captures.iter().any(|n| n == name) {
+                frame.captures.push(String::from(name));
+            }
+        }
+        Some(declaration)
+    }
+
+    /// Resolves an identifier used as a value to the local, parameter or
+    /// loop variable it names, or else to a top level declaration such as
+    /// an enum.
+    fn resolve_ident(&mut self, node: &'a ASTNode, section: Option<&str>) {
+        let name = node.name();
+        if let Some(declaration) = self.use_local(&name) {
+            self.references.push(Reference {
+                source: node.source,
+                line_number: node.line_number,
+                start_col: node.start_col,
+                declaration: (
+                    declaration.source,
+                    declaration.line_number,
+                    declaration.start_col,
+                ),
+            });
+            return;
+        }
+        let later = self
+            .frames
+            .iter()
+            .flat_map(|f| f.scopes.iter())
+            .flat_map(|s| s.later.iter())
+            .find(|vardec| vardec.name() == name)
+            .copied();
+        if let Some(later) = later {
+            self.diagnostics.push(
+                Diagnostic::at(format!("`{}` is used before it is declared", name), node)
+                    .with_note(String::from("declared here"), later),
+            );
+            return;
+        }
+        if self.lookup(section, &name, node).is_none() {
+            self.diagnostics
+                .push(Diagnostic::at(format!("Unknown name `{}`", name), node));
+        }
+    }
+
+    /// Walks the body of a member. `class` is `None` inside snippets, whose
+    /// including class is not known.
+    fn resolve_body(
+        &mut self,
+        node: &'a ASTNode,
+        section: Option<&str>,
+        class: Option<&ClassContext>,
+        in_method: bool,
+    ) {
+        match &node.production {
+            Some(Production::This) | Some(Production::Super) if !in_method => {
+                self.diagnostics.push(Diagnostic::at(
+                    format!(
+                        "`{}` can only be used inside methods",
+                        if node.is(Production::This) {
+                            "this"
+                        } else {
+                            "super"
+                        }
+                    ),
+                    node,
+                ));
+            }
+            Some(Production::Member) if node.children[0].is(Production::This) && in_method => {
+                // snippet members have no class here; the checker sees them
+                // in each class that includes them
+                if let Some(class) = class {
+                    if !class.members.contains(&node.name()) {
+                        self.diagnostics.push(Diagnostic::at(
+                            format!("`this` has no member named `{}`", node.name()),
+                            node,
+                        ));
+                    }
+                }
+            }
+            Some(Production::Member) if node.children[0].is(Production::Super) && in_method => {
+                match class.and_then(|c| c.parent_members.as_ref()) {
+                    Some(parent_members) if !parent_members.contains(&node.name()) => {
+                        self.diagnostics.push(Diagnostic::at(
+                            format!("`super` has no member named `{}`", node.name()),
+                            node,
+                        ));
+                    }
+                    Some(_) => {}
+                    None => self.diagnostics.push(Diagnostic::at(
+                        String::from(
+                            "`super` can only be used in classes that extend another class",
+                        ),
+                        node,
+                    )),
+                }
+            }
+            Some(Production::Call)
+                if node.children[0].is(Production::Member)
+                    && self
+                        .enum_named(&node.children[0].children[0], section)
+                        .is_some() =>
+            {
+                let variant = &node.children[0];
+                let enum_name = variant.children[0].path().unwrap_or_default();
+                self.resolve_variant(
+                    &enum_name,
+                    &variant.name(),
+                    node.children.len() - 1,
+                    variant,
+                    section,
+                );
+                for argument in &node.children[1..] {
+                    self.resolve_body(argument, section, class, in_method);
+                }
+                return;
+            }
+            Some(Production::Member) if self.enum_named(&node.children[0], section).is_some() => {
+                let enum_name = node.children[0].path().unwrap_or_default();
+                self.resolve_variant(&enum_name, &node.name(), 0, node, section);
+                return;
+            }
+            // `Ok` and `Err` are built in and always hold one value
+            Some(Production::VariantPattern) if node.name() != "Ok" && node.name() != "Err" => {
+                let name = node.name();
+                let (enum_name, variant) = name.split_at(name.rfind('.').unwrap_or(0));
+                self.resolve_variant(
+                    enum_name,
+                    variant.trim_start_matches('.'),
+                    node.children.len(),
+                    node,
+                    section,
+                );
+            }
+            Some(Production::New) => self.resolve_new(node, section),
+            Some(Production::TypeDec) => self.resolve_type(node, section),
+            // resolved on entering their class or function
+            Some(Production::TypeParams) => return,
+            Some(Production::Ident) => self.resolve_ident(node, section),
+            Some(Production::This) => {
+                self.use_local("this");
+            }
+            Some(Production::For) => {
+                self.resolve_body(&node.children[0], section, class, in_method);
+                self.enter_scope(node);
+                self.declare_local(node.name(), node);
+                self.resolve_body(&node.children[1], section, class, in_method);
+                self.leave_scope();
+                return;
+            }
+            // declared first so that a local function can call itself
+            Some(Production::Vardec)
+                if node
+                    .initializer()
+                    .is_some_and(|i| i.is(Production::FunctionDec)) =>
+            {
+                self.declare_local(node.name(), node)
+            }
+            _ => {}
+        }
+        let in_method = in_method || node.is(Production::FunctionDec);
+        let type_params = if node.is(Production::FunctionDec) {
+            let outermost = self.frames.is_empty();
+            self.frames.push(Frame {
+                scopes: vec![Scope {
+                    declared: if outermost {
+                        vec![(String::from("this"), node)]
+                    } else {
+                        vec![]
+                    },
+                    later: vec![],
+                }],
+                captures: vec![],
+            });
+            self.enter_type_params(node.find(Production::TypeParams), section)
+        } else {
+            0
+        };
+        let scoped = node.is(Production::BlockBody) || node.is(Production::MatchArm);
+        if scoped {
+            self.enter_scope(node);
+        }
+        for child in &node.children {
+            self.resolve_body(child, section, class, in_method);
+        }
+        if scoped {
+            self.leave_scope();
+        }
+        self.leave_type_params(type_params);
+        match &node.production {
+            Some(Production::FunctionDec) => {
+                let frame = self.frames.pop().unwrap_or_default();
+                if !frame.captures.is_empty() {
+                    self.captures.push(Capture {
+                        source: node.source,
+                        line_number: node.line_number,
+                        start_col: node.start_col,
+                        names: frame.captures,
+                    });
+                }
+            }
+            Some(Production::Vardec)
+                if !node
+                    .initializer()
+                    .is_some_and(|i| i.is(Production::FunctionDec)) =>
+            {
+                self.declare_local(node.name(), node)
+            }
+            Some(Production::Param) | Some(Production::BindingPattern) => {
+                self.declare_local(node.name(), node)
+            }
+            _ => {}
+        }
+    }
+
+    fn resolve_declaration(&mut self, declaration: &'a ASTNode, section: Option<&str>) {
+        self.check_duplicate_members(declaration);
+        let class = if declaration.is(Production::ClassDec) {
+            let name = match section {
+                Some(section) => format!("{}.{}", section, declaration.name()),
+                None => declaration.name(),
+            };
+            self.resolve_includes(declaration, section);
+            self.resolve_extends(declaration, &name, section);
+            self.resolve_implements(declaration, section);
+            self.check_constructor(declaration);
+            self.check_overrides(declaration, section);
+            Some(ClassContext {
+                members: self.symbols.member_names(declaration, section),
+                parent_members: self
+                    .symbols
+                    .parent(declaration, section)
+                    .map(|(name, parent)| {
+                        self.symbols
+                            .member_names(parent, SymbolTable::section_of(&name))
+                    }),
+            })
+        } else {
+            None
+        };
+        let type_params = self.enter_type_params(declaration.find(Production::TypeParams), section);
+        for member in declaration.members() {
+            self.resolve_body(member, section, class.as_ref(), false);
+        }
+        self.leave_type_params(type_params);
+    }
+
+    pub fn resolve(&mut self, program: &'a ASTNode) {
+        for declaration in &program.children {
+            if declaration.is(Production::SectionDec) {
+                self.symbols
+                    .sections
+                    .insert(declaration.name(), declaration);
+            } else if declaration.is(Production::Import) {
+                self.imports
+                    .entry(declaration.source)
+                    .or_default()
+                    .extend(declaration.children.iter().map(|name| name.name()));
+            }
+        }
+        for declaration in &program.children {
+            if declaration.is(Production::Import) {
+                continue;
+            }
+            if declaration.is(Production::SectionDec) {
+                for member in declaration.members() {
+                    self.declare(format!("{}.{}", declaration.name(), member.name()), member);
+                }
+            } else {
+                let qualified = SymbolTable::qualified(declaration);
+                if qualified != declaration.name() {
+                    self.symbols
+                        .private
+                        .entry(declaration.source)
+                        .or_default()
+                        .insert(declaration.name(), qualified.clone());
+                }
+                self.declare(qualified, declaration);
+            }
+        }
+        for declaration in program
+            .children
+            .iter()
+            .filter(|d| !d.is(Production::Import))
+        {
+            if declaration.is(Production::SectionDec) {
+                let section = declaration.name();
+                for member in declaration.members() {
+                    self.resolve_declaration(member, Some(&section));
+                }
+            } else {
+                self.resolve_declaration(declaration, None);
+            }
+        }
+    }
+}
+
+/// Resolves a parsed program, returning every problem found.
+pub fn resolve(program: &ASTNode) -> Vec<Diagnostic> {
+    let mut resolver = Resolver::new();
+    resolver.resolve(program);
+    resolver.diagnostics
+}
+
+/// Function literals of a program that capture locals, see `Capture`.
+pub fn captures(program: &ASTNode) -> Vec<Capture> {
+    let mut resolver = Resolver::new();
+    resolver.resolve(program);
+    resolver.captures
+}
+
+/// Uses of locals, parameters and loop variables with their declarations.
+pub fn references(program: &ASTNode) -> Vec<Reference> {
+    let mut resolver = Resolver::new();
+    resolver.resolve(program);
+    resolver.references
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_section_text;
+    use crate::parser::parse_text;
+    use crate::section;
+
+    fn parse(text: &str) -> ASTNode {
+        parse_text(text)
+    }
+
+    fn diagnostics(text: &str) -> Vec<Diagnostic> {
+        resolve(&parse(text))
+    }
+
+    #[test]
+    fn unknown_snippet() {
+        let found = diagnostics("class A { include Missing; }");
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].message, "Unknown snippet `Missing`");
+    }
+
+    #[test]
+    fn conflicting_snippets() {
+        let found = diagnostics(
+            "snippet A { let name: Word; }
+             snippet B { let name: Word; }
+             class C { include A; include B; }",
+        );
+        assert_eq!(found.len(), 1);
+        assert!(found[0]
+            .message
+            .contains("both snippet `A` and snippet `B`"));
+        assert_eq!(found[0].notes.len(), 2);
+    }
+
+    #[test]
+    fn class_member_settles_conflict() {
+        let found = diagnostics(
+            "snippet A { let name: Word; }
+             snippet B { let name: Word; }
+             class C { include A; include B; let name: Word; }",
+        );
+        assert!(found.is_empty());
+    }
+
+    #[test]
+    fn this_and_new() {
+        let found = diagnostics(
+            "snippet Named { let name: Word; }
+             class A {
+                include Named;
+                let age: Number;
+                let copy: Number = this.age;
+                let init: Function = (age: Number) { this.age = age; this.name = \"a\"; this.height = 1; }
+                let make: Function = () ~A { return new A(1, 2); }
+             }",
+        );
+        let messages: Vec<&str> = found.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "`this` can only be used inside methods",
+                "`this` has no member named `height`",
+                "`A` is constructed with 1 argument(s) but 2 were given",
+            ]
+        );
+    }
+
+    #[test]
+    fn inheritance() {
+        let found = diagnostics(
+            "class Person {
+                let name: Word;
+                let greet: Function = (other: Person) ~Word { return this.name; }
+                let init: Function = (name: Word) { this.name = name; }
+             }
+             class Student extends Person {
+                let name: Number;
+                let greet: Function = () ~Word { return super.greet(this) + super.missing; }
+             }
+             class Teacher extends Person { let f: Function = () ~Person { return new Teacher(); } }",
+        );
+        let messages: Vec<&str> = found.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "`Student.name` redeclares a member inherited from `Person`; only methods can be overridden",
+                "`Student.greet` takes 0 parameter(s) but overrides a method of `Person` taking 1",
+                "`super` has no member named `missing`",
+                "`Teacher` is constructed with 1 argument(s) but 0 were given",
+            ]
+        );
+    }
+
+    #[test]
+    fn interfaces_and_types() {
+        let found = diagnostics(
+            "interface Greeter { greet(other: Person) ~Wrd; }
+             snippet S { }
+             class Person implements Greeter, S, Missing { let s: S; let l: List; let p: Person<Word>; }
+             class Student extends Greeter { }
+             enum Option { Some, None }",
+        );
+        let messages: Vec<&str> = found.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "`Option` is the name of a built in type",
+                "Unknown type `Wrd`",
+                "`S` is not an interface and cannot be implemented",
+                "Unknown interface `Missing`",
+                "`S` is not a type",
+                "`List` takes 1 type argument(s) but 0 were given",
+                "`Person` does not take type arguments",
+                "`Greeter` is not a class and cannot be extended",
+            ]
+        );
+    }
+
+    #[test]
+    fn enum_variants() {
+        let found = diagnostics(
+            "enum Shape { Circle(Number), Empty, Circle }
+             class A {
+                let empty: Shape = Shape.Empty;
+                let f: Function = (s: Shape) ~Number {
+                    let c: Shape = Shape.Circle();
+                    return match (s) {
+                        Shape.Circle(r, q) => r,
+                        Shape.Square => 1,
+                        A.Empty => 0,
+                        Color.Red => 0,
+                    };
+                }
+             }",
+        );
+        let messages: Vec<&str> = found.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "`Shape` already has a variant named `Circle`",
+                "`Shape.Circle` holds 1 value(s) but 0 were given",
+                "`Shape.Circle` holds 1 value(s) but 2 were given",
+                "`Shape` has no variant named `Square`",
+                "`A` is not an enum",
+                "Unknown enum `Color`",
+            ]
+        );
+    }
+
+    #[test]
+    fn generics() {
+        let found = diagnostics(
+            "interface Named { name() ~Word; }
+             class Box<T, T: Box> {
+                let value: T;
+                let other: T<Word>;
+                let boxes: List<Box<Word>>;
+                let raw: Box;
+                let map: Function = <U: Named>(f: U) ~Box<U, T> { let x: U = f; return this; }
+                let leak: U;
+             }
+             class Crate extends Box { }
+             class Plain { let p: Plain<Word>; }",
+        );
+        let messages: Vec<&str> = found.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "Type parameter `T` is declared more than once",
+                "`Box` is not an interface and cannot bound `T`",
+                "`T` is a type parameter and does not take type arguments",
+                "`Box` takes 2 type argument(s) but 1 were given",
+                "`Box` takes 2 type argument(s) but 0 were given",
+                "Unknown type `U`",
+                "`Box` is generic and cannot be extended",
+                "`Plain` does not take type arguments",
+            ]
+        );
+    }
+
+    #[test]
+    fn inheritance_cycle() {
+        let found = diagnostics(
+            "class A extends C { } class B extends A { } class C extends B { } class D extends A { }",
+        );
+        let messages: Vec<&str> = found.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "`A` inherits from itself: A -> C -> B -> A",
+                "`B` inherits from itself: B -> A -> C -> B",
+                "`C` inherits from itself: C -> B -> A -> C",
+            ]
+        );
+    }
+
+    #[test]
+    fn duplicate_across_section_files() {
+        let first =
+            parse_section_text("section S { snippet Round { } class A { include Round; } }");
+        let mut second = parse_section_text("section S { class A { } }");
+        second.set_source(1);
+        let program = section::merge(vec![first, second, parse("class B { include S.Round; }")]);
+        let found = resolve(&program);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].message, "`S.A` is declared more than once");
+        assert_eq!((found[0].source, found[0].notes[0].source), (1, 0));
+    }
+
+    #[test]
+    fn imports_between_modules() {
+        let mut person = parse("export class Person { } export class Coach { } class Secret { }");
+        person.set_source(1);
+        let program = section::merge(vec![
+            person,
+            parse(
+                "import { Person } from \"./person.xo\";
+                 class Team { let lead: Person; let kept: Secret; let coach: Coach; }
+                 class Club extends Person { }",
+            ),
+        ]);
+        let found = resolve(&program);
+        let messages: Vec<&str> = found.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "`Secret` is private to another module",
+                "`Coach` is declared in another module and has to be imported"
+            ]
+        );
+        assert_eq!((found[0].source, found[0].notes[0].source), (0, 1));
+    }
+
+    #[test]
+    fn modules_keep_unexported_names_to_themselves() {
+        let mut lib =
+            parse("class Helper { } export class Util { let helper: Helper; } class Main { }");
+        lib.set_source(1);
+        let mut quiet = parse("class Quiet { } class Helper { } class Helper { }");
+        quiet.set_source(2);
+        let program = section::merge(vec![
+            lib,
+            quiet,
+            parse(
+                "import { Util } from \"./lib.xo\";
+                 class Helper { let util: Util; }
+                 class Main { let helper: Helper; let quiet: Quiet; }",
+            ),
+        ]);
+        let found = resolve(&program);
+        let messages: Vec<&str> = found.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "`Helper` is declared more than once",
+                "`Quiet` is private to another module"
+            ]
+        );
+        assert_eq!(found[0].source, 2);
+    }
+
+    #[test]
+    fn scoped_names() {
+        let program = parse(
+            "enum Light { Red, Green }
+             class Lamp {
+                let glow: Number = brightness;
+                let switch: Function = (on: Boolean, on: Boolean) ~Number {
+                    let level: Number = later + 1;
+                    let later: Number = 2;
+                    if (on) {
+                        let level: Number = 3;
+                        let level: Number = missing;
+                    }
+                    for (step in [level]) { let copy: Number = step; }
+                    let light: Light = Light.Red;
+                    return level + step;
+                }
+             }",
+        );
+        let found = resolve(&program);
+        let messages: Vec<(&str, i32)> = found
+            .iter()
+            .map(|d| (d.message.as_str(), d.line_number))
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                ("Unknown name `brightness`", 3),
+                ("`on` is declared more than once in this scope", 4),
+                ("`later` is used before it is declared", 5),
+                ("Unknown name `missing`", 9),
+                ("`level` is declared more than once in this scope", 9),
+                ("Unknown name `step`", 13),
+            ]
+        );
+        assert_eq!(found[1].notes[0].line_number, 4);
+        assert_eq!(found[2].notes[0].line_number, 6);
+        let uses: Vec<(i32, i32)> = references(&program)
+            .into_iter()
+            .map(|r| (r.line_number, r.declaration.1))
+            .collect();
+        assert_eq!(uses, vec![(7, 4), (11, 5), (11, 11), (13, 5)]);
+    }
+
+    #[test]
+    fn closure_captures() {
+        let program = parse(
+            "class Counter {
+                let step: Number = 1;
+                let make: Function = (start: Number) ~Function {
+                    let count: Number = start;
+                    let unused: Number = 0;
+                    let tick: Function = () ~Number {
+                        let local: Number = count;
+                        return local + this.step;
+                    };
+                    let nested: Function = (by: Number) ~Function {
+                        for (i in [by]) {
+                            let inner: Function = () ~Number { return i + start + by; };
+                        }
+                        return () ~Number { return count; };
+                    };
+                    return tick;
+                }
+             }",
+        );
+        let found: Vec<(i32, Vec<String>)> = captures(&program)
+            .into_iter()
+            .map(|c| (c.line_number, c.names))
+            .collect();
+        let names = |names: &[&str]| names.iter().map(|n| String::from(*n)).collect();
+        assert_eq!(
+            found,
+            vec![
+                (6, names(&["count", "this"])),
+                (12, names(&["i", "start", "by"])),
+                (14, names(&["count"])),
+                (10, names(&["start", "count"])),
+            ]
+        );
+    }
+}
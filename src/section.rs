@@ -0,0 +1,53 @@
+use super::parser::ASTNode;
+use super::parser::Production;
+
+/// Combines the programs parsed from every input file into one. Sections
+/// with the same name share one namespace, so their parts are merged into
+/// the first declaration of the section; clashing members are left for the
+/// resolver to report.
+pub fn merge(programs: Vec<ASTNode>) -> ASTNode {
+    let mut merged = ASTNode::root();
+    for declaration in programs.into_iter().flat_map(|program| program.children) {
+        let existing = merged.children.iter_mut().find(|d| {
+            d.is(Production::SectionDec)
+                && declaration.is(Production::SectionDec)
+                && d.name() == declaration.name()
+        });
+        match existing {
+            Some(section) => {
+                let members = declaration
+                    .children
+                    .into_iter()
+                    .flat_map(|body| body.children);
+                section.children[0].children.extend(members);
+            }
+            None => merged.children.push(declaration),
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_section_text;
+
+    fn parse_section_source(text: &str, source: usize) -> ASTNode {
+        let mut program = parse_section_text(text);
+        program.set_source(source);
+        program
+    }
+
+    #[test]
+    fn sections_with_the_same_name_are_merged() {
+        let merged = merge(vec![
+            parse_section_source("section Shapes { class Circle { } }", 0),
+            parse_section_source("section Shapes { class Square { } } section Colors { }", 1),
+        ]);
+        let names: Vec<String> = merged.children.iter().map(|d| d.name()).collect();
+        assert_eq!(names, vec!["Shapes", "Colors"]);
+        let members = merged.children[0].members();
+        assert_eq!(members.len(), 2);
+        assert_eq!(members[1].source, 1);
+    }
+}
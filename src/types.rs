@@ -0,0 +1,164 @@
+use super::parser::ASTNode;
+use std::collections::HashMap;
+use std::fmt;
+
+/// Names of the types every program can use without declaring them.
+pub const BUILTIN_TYPES: [&str; 8] = [
+    "Number", "Word", "Boolean", "Function", "List", "Map", "Option", "Result",
+];
+
+/// Static type of a Xonyx value.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Type {
+    Number,
+    Word,
+    Boolean,
+    /// Any function, what `Function` annotations declare.
+    Function,
+    /// A function whose parameter and return types are known, as inferred
+    /// for a function literal. Fits where `Function` is expected.
+    FunctionOf(Vec<Type>, Box<Type>),
+    /// A class, interface, enum or type parameter, by qualified name, with
+    /// the type arguments of a generic class.
+    Named(String, Vec<Type>),
+    /// Growable list of elements of one type.
+    List(Box<Type>),
+    /// Map from keys of the first type to values of the second.
+    Map(Box<Type>, Box<Type>),
+    /// A value of the inner type or null, written `T?` or `Option<T>`.
+    Option(Box<Type>),
+    /// Type of the `null` literal, which fits every optional type.
+    Null,
+    /// Outcome of something that can fail: `Ok` with a value of the first
+    /// type or `Err` with an error of the second.
+    Result(Box<Type>, Box<Type>),
+    /// No value, what functions without a `~Type` return.
+    Void,
+}
+
+impl Type {
+    /// Number of type arguments a built in type takes.
+    pub fn arity(name: &str) -> usize {
+        match name {
+            "List" | "Option" => 1,
+            "Map" | "Result" => 2,
+            _ => 0,
+        }
+    }
+
+    /// Whether values of this type can be used as map keys. Keys are
+    /// compared by value, which only the primitive types support.
+    pub fn is_map_key(&self) -> bool {
+        matches!(self, Type::Number | Type::Word | Type::Boolean)
+    }
+
+    /// Whether the value may be null, and so has to be checked before use.
+    pub fn is_nullable(&self) -> bool {
+        matches!(self, Type::Option(_) | Type::Null)
+    }
+
+    /// Members lists, maps and words come with, as the parameter types of
+    /// methods (`None` for fields) and the type of the field or of what the
+    /// method returns.
+    pub fn builtin_member(&self, name: &str) -> Option<(Option<Vec<Type>>, Type)> {
+        match (self, name) {
+            (Type::List(_), "length") | (Type::Map(..), "length") | (Type::Word, "length") => {
+                Some((None, Type::Number))
+            }
+            (Type::List(element), "push") => Some((Some(vec![*element.clone()]), Type::Void)),
+            (Type::Map(key, _), "has") => Some((Some(vec![*key.clone()]), Type::Boolean)),
+            (Type::Map(key, _), "remove") => Some((Some(vec![*key.clone()]), Type::Void)),
+            (Type::Map(key, _), "keys") => Some((Some(vec![]), Type::List(key.clone()))),
+            _ => None,
+        }
+    }
+
+    /// Replaces the type parameters named in `arguments` by their types.
+    pub fn substitute(&self, arguments: &HashMap<String, Type>) -> Type {
+        let all = |types: &[Type]| types.iter().map(|t| t.substitute(arguments)).collect();
+        match self {
+            Type::Named(name, args) if args.is_empty() && arguments.contains_key(name) => {
+                arguments[name].clone()
+            }
+            Type::Named(name, args) => Type::Named(name.clone(), all(args)),
+            Type::List(element) => Type::List(Box::new(element.substitute(arguments))),
+            Type::Map(key, value) => Type::Map(
+                Box::new(key.substitute(arguments)),
+                Box::new(value.substitute(arguments)),
+            ),
+            Type::Option(inner) => Type::Option(Box::new(inner.substitute(arguments))),
+            Type::Result(value, error) => Type::Result(
+                Box::new(value.substitute(arguments)),
+                Box::new(error.substitute(arguments)),
+            ),
+            Type::FunctionOf(params, returns) => {
+                Type::FunctionOf(all(params), Box::new(returns.substitute(arguments)))
+            }
+            other => other.clone(),
+        }
+    }
+
+    /// Type written by a `TypeDec`, `Void` when there is none.
+    pub fn from_type_dec(node: Option<&ASTNode>) -> Type {
+        let node = match node {
+            Some(node) => node,
+            None => return Type::Void,
+        };
+        match node.name().as_str() {
+            "Number" => Type::Number,
+            "Word" => Type::Word,
+            "Boolean" => Type::Boolean,
+            "Function" => Type::Function,
+            "List" => Type::List(Box::new(Type::from_type_dec(node.children.first()))),
+            "Map" => Type::Map(
+                Box::new(Type::from_type_dec(node.children.first())),
+                Box::new(Type::from_type_dec(node.children.get(1))),
+            ),
+            "Result" => Type::Result(
+                Box::new(Type::from_type_dec(node.children.first())),
+                Box::new(Type::from_type_dec(node.children.get(1))),
+            ),
+            // a value is either there or not, so `T??` is just `T?`
+            "Option" => match Type::from_type_dec(node.children.first()) {
+                Type::Option(inner) => Type::Option(inner),
+                inner => Type::Option(Box::new(inner)),
+            },
+            name => Type::Named(
+                String::from(name),
+                node.children
+                    .iter()
+                    .map(|t| Type::from_type_dec(Some(t)))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Type::Number => write!(f, "Number"),
+            Type::Word => write!(f, "Word"),
+            Type::Boolean => write!(f, "Boolean"),
+            Type::Function => write!(f, "Function"),
+            Type::FunctionOf(params, returns) => {
+                let params: Vec<String> = params.iter().map(|p| p.to_string()).collect();
+                match &**returns {
+                    Type::Void => write!(f, "({})", params.join(", ")),
+                    returns => write!(f, "({}) ~{}", params.join(", "), returns),
+                }
+            }
+            Type::Named(name, args) if args.is_empty() => write!(f, "{}", name),
+            Type::Named(name, args) => {
+                let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+                write!(f, "{}<{}>", name, args.join(", "))
+            }
+            Type::List(element) => write!(f, "List<{}>", element),
+            Type::Map(key, value) => write!(f, "Map<{}, {}>", key, value),
+            Type::Option(inner) => write!(f, "{}?", inner),
+            Type::Null => write!(f, "null"),
+            Type::Result(value, error) => write!(f, "Result<{}, {}>", value, error),
+            Type::Void => write!(f, "nothing"),
+        }
+    }
+}
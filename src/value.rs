@@ -0,0 +1,292 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+/// How a way of running programs represents instances and functions. The
+/// rest of a value, and what the built in operators and methods do with
+/// it, is the same for the interpreter and the virtual machine.
+pub trait Runtime {
+    type Instance;
+    type Function;
+
+    /// Name of the class `instance` was made from.
+    fn class_name(instance: &Self::Instance) -> String;
+
+    /// Name `function` is shown with.
+    fn function_name(function: &Self::Function) -> String;
+}
+
+/// A value of a running Xonyx program. Lists, maps, instances and
+/// functions are shared, so copying a value copies a reference to them.
+pub enum Value<R: Runtime> {
+    /// What calling a function without a `~Type` gives.
+    Nothing,
+    Null,
+    Number(i64),
+    Word(Rc<str>),
+    Boolean(bool),
+    List(Rc<RefCell<Vec<Value<R>>>>),
+    /// Entries in the order their keys were first added.
+    Map(Rc<RefCell<Vec<Entry<R>>>>),
+    Instance(Rc<R::Instance>),
+    Function(Rc<R::Function>),
+    /// A variant of an enum, `Ok` or `Err`, and the values it carries.
+    Variant(Rc<Variant<R>>),
+}
+
+/// A key of a map and the value stored under it.
+pub type Entry<R> = (Value<R>, Value<R>);
+
+// not derived, which would need the runtime itself to be `Clone`
+impl<R: Runtime> Clone for Value<R> {
+    fn clone(&self) -> Value<R> {
+        match self {
+            Value::Nothing => Value::Nothing,
+            Value::Null => Value::Null,
+            Value::Number(number) => Value::Number(*number),
+            Value::Word(word) => Value::Word(word.clone()),
+            Value::Boolean(boolean) => Value::Boolean(*boolean),
+            Value::List(list) => Value::List(list.clone()),
+            Value::Map(map) => Value::Map(map.clone()),
+            Value::Instance(instance) => Value::Instance(instance.clone()),
+            Value::Function(function) => Value::Function(function.clone()),
+            Value::Variant(variant) => Value::Variant(variant.clone()),
+        }
+    }
+}
+
+pub struct Variant<R: Runtime> {
+    /// `Enum.Variant` as the enum was declared, or `Ok` or `Err`.
+    pub name: Rc<str>,
+    /// Position of the variant in its enum, see `codegen::EnumLayout`.
+    pub tag: usize,
+    pub values: Vec<Value<R>>,
+}
+
+impl<R: Runtime> Value<R> {
+    pub fn word(text: String) -> Value<R> {
+        Value::Word(Rc::from(text))
+    }
+
+    pub fn list(values: Vec<Value<R>>) -> Value<R> {
+        Value::List(Rc::new(RefCell::new(values)))
+    }
+
+    pub fn variant(name: Rc<str>, tag: usize, values: Vec<Value<R>>) -> Value<R> {
+        Value::Variant(Rc::new(Variant { name, tag, values }))
+    }
+
+    /// Whether two values are equal as `==` compares them: primitives and
+    /// variants by value, everything else by identity.
+    pub fn equals(&self, other: &Value<R>) -> bool {
+        match (self, other) {
+            (Value::Nothing, Value::Nothing) | (Value::Null, Value::Null) => true,
+            (Value::Number(a), Value::Number(b)) => a == b,
+            (Value::Word(a), Value::Word(b)) => a == b,
+            (Value::Boolean(a), Value::Boolean(b)) => a == b,
+            (Value::List(a), Value::List(b)) => Rc::ptr_eq(a, b),
+            (Value::Map(a), Value::Map(b)) => Rc::ptr_eq(a, b),
+            (Value::Instance(a), Value::Instance(b)) => Rc::ptr_eq(a, b),
+            (Value::Function(a), Value::Function(b)) => Rc::ptr_eq(a, b),
+            (Value::Variant(a), Value::Variant(b)) => {
+                a.name == b.name
+                    && a.values.len() == b.values.len()
+                    && a.values.iter().zip(&b.values).all(|(a, b)| a.equals(b))
+            }
+            _ => false,
+        }
+    }
+
+    /// How the value is written inside a list, map or variant, where words
+    /// are quoted.
+    pub fn nested(&self) -> String {
+        match self {
+            Value::Word(word) => format!("{:?}", word),
+            other => other.to_string(),
+        }
+    }
+
+    /// The value as the condition of an `if`, a loop or `&&` and `||`.
+    pub fn condition(&self) -> Result<bool, String> {
+        match self {
+            Value::Boolean(holds) => Ok(*holds),
+            other => Err(format!("Expected a `Boolean` condition, found `{}`", other)),
+        }
+    }
+
+    /// `-value`.
+    pub fn negate(&self) -> Result<Value<R>, String> {
+        match self {
+            Value::Number(number) => number
+                .checked_neg()
+                .map(Value::Number)
+                .ok_or_else(|| format!("`-{}` overflows a `Number`", number)),
+            other => Err(format!("Cannot negate `{}`", other)),
+        }
+    }
+
+    /// `!value`.
+    pub fn not(&self) -> Result<Value<R>, String> {
+        self.condition().map(|holds| Value::Boolean(!holds))
+    }
+
+    /// The member `name` lists, maps and words have, `None` if the value has
+    /// no such member built in.
+    pub fn builtin_member(&self, name: &str) -> Option<Value<R>> {
+        let length = match self {
+            Value::List(list) => list.borrow().len(),
+            Value::Map(map) => map.borrow().len(),
+            Value::Word(word) => word.chars().count(),
+            _ => return None,
+        };
+        Some(Value::Number(length as i64)).filter(|_| name == "length")
+    }
+
+    /// Calls the method `name` lists and maps have built in, `None` if the
+    /// value has no such method.
+    pub fn call_builtin(&self, name: &str, mut arguments: Vec<Value<R>>) -> Option<Value<R>> {
+        match self {
+            Value::List(list) if name == "push" => {
+                list.borrow_mut().append(&mut arguments);
+                Some(Value::Nothing)
+            }
+            Value::Map(map) if ["has", "remove", "keys"].contains(&name) => {
+                let key = arguments.into_iter().next().unwrap_or(Value::Nothing);
+                let position = map.borrow().iter().position(|(k, _)| k.equals(&key));
+                Some(match name {
+                    "has" => Value::Boolean(position.is_some()),
+                    "remove" => {
+                        if let Some(position) = position {
+                            map.borrow_mut().remove(position);
+                        }
+                        Value::Nothing
+                    }
+                    _ => Value::list(map.borrow().iter().map(|(k, _)| k.clone()).collect()),
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// `self[key]`.
+    pub fn get_index(&self, key: &Value<R>) -> Result<Value<R>, String> {
+        match self {
+            Value::List(list) => {
+                let list = list.borrow();
+                Ok(list[position(key, list.len())?].clone())
+            }
+            Value::Map(map) => map
+                .borrow()
+                .iter()
+                .find(|(k, _)| k.equals(key))
+                .map(|(_, v)| v.clone())
+                .ok_or_else(|| format!("The map has no entry for {}", key.nested())),
+            other => Err(format!("Cannot index `{}`", other)),
+        }
+    }
+
+    /// `self[key] = value`. A map gets a new entry for a key it does not
+    /// have.
+    pub fn set_index(&self, key: Value<R>, value: Value<R>) -> Result<(), String> {
+        match self {
+            Value::List(list) => {
+                let mut list = list.borrow_mut();
+                let position = position(&key, list.len())?;
+                list[position] = value;
+            }
+            Value::Map(map) => {
+                let mut map = map.borrow_mut();
+                match map.iter_mut().find(|(k, _)| k.equals(&key)) {
+                    Some((_, slot)) => *slot = value,
+                    None => map.push((key, value)),
+                }
+            }
+            other => return Err(format!("Cannot index `{}`", other)),
+        }
+        Ok(())
+    }
+}
+
+/// Position in a list of `length` elements `key` stands for.
+fn position<R: Runtime>(key: &Value<R>, length: usize) -> Result<usize, String> {
+    match key {
+        Value::Number(index) if *index >= 0 && (*index as usize) < length => Ok(*index as usize),
+        other => Err(format!(
+            "Index {} is out of bounds for a list of {} element(s)",
+            other, length
+        )),
+    }
+}
+
+/// Applies a built in binary operator other than `&&`, `||` and `??`,
+/// which do not always evaluate their right operand.
+pub fn binary<R: Runtime>(
+    operator: &str,
+    lhs: Value<R>,
+    rhs: Value<R>,
+) -> Result<Value<R>, String> {
+    let overflow = || format!("`{} {} {}` overflows a `Number`", lhs, operator, rhs);
+    let value = match (operator, &lhs, &rhs) {
+        ("/", Value::Number(_), Value::Number(0)) => return Err(String::from("Division by zero")),
+        ("+", Value::Number(a), Value::Number(b)) => {
+            Value::Number(a.checked_add(*b).ok_or_else(overflow)?)
+        }
+        ("-", Value::Number(a), Value::Number(b)) => {
+            Value::Number(a.checked_sub(*b).ok_or_else(overflow)?)
+        }
+        ("*", Value::Number(a), Value::Number(b)) => {
+            Value::Number(a.checked_mul(*b).ok_or_else(overflow)?)
+        }
+        ("/", Value::Number(a), Value::Number(b)) => {
+            Value::Number(a.checked_div(*b).ok_or_else(overflow)?)
+        }
+        ("+", Value::Word(_), _) | ("+", _, Value::Word(_)) => {
+            Value::word(format!("{}{}", lhs, rhs))
+        }
+        ("<", Value::Number(a), Value::Number(b)) => Value::Boolean(a < b),
+        (">", Value::Number(a), Value::Number(b)) => Value::Boolean(a > b),
+        ("<=", Value::Number(a), Value::Number(b)) => Value::Boolean(a <= b),
+        (">=", Value::Number(a), Value::Number(b)) => Value::Boolean(a >= b),
+        ("==", ..) => Value::Boolean(lhs.equals(&rhs)),
+        ("!=", ..) => Value::Boolean(!lhs.equals(&rhs)),
+        _ => {
+            return Err(format!(
+                "Cannot apply `{}` to `{}` and `{}`",
+                operator, lhs, rhs
+            ))
+        }
+    };
+    Ok(value)
+}
+
+impl<R: Runtime> fmt::Display for Value<R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let join = |values: Vec<String>| values.join(", ");
+        match self {
+            Value::Nothing => write!(f, "nothing"),
+            Value::Null => write!(f, "null"),
+            Value::Number(number) => write!(f, "{}", number),
+            Value::Word(word) => write!(f, "{}", word),
+            Value::Boolean(boolean) => write!(f, "{}", boolean),
+            Value::List(list) => {
+                let elements = list.borrow().iter().map(|e| e.nested()).collect();
+                write!(f, "[{}]", join(elements))
+            }
+            Value::Map(map) => {
+                let entries = map
+                    .borrow()
+                    .iter()
+                    .map(|(k, v)| format!("{}: {}", k.nested(), v.nested()))
+                    .collect();
+                write!(f, "{{{}}}", join(entries))
+            }
+            Value::Instance(instance) => write!(f, "<{}>", R::class_name(instance)),
+            Value::Function(function) => write!(f, "<function {}>", R::function_name(function)),
+            Value::Variant(variant) if variant.values.is_empty() => write!(f, "{}", variant.name),
+            Value::Variant(variant) => {
+                let values = variant.values.iter().map(|v| v.nested()).collect();
+                write!(f, "{}({})", variant.name, join(values))
+            }
+        }
+    }
+}
@@ -0,0 +1,913 @@
+use super::bytecode::Capture;
+use super::bytecode::Class;
+use super::bytecode::Constant;
+use super::bytecode::Function;
+use super::bytecode::Op;
+use super::bytecode::Pattern;
+use super::bytecode::Program;
+use super::bytecode::Var;
+use super::diagnostic::Diagnostic;
+use super::diagnostic::Note;
+use super::interpreter::MAX_DEPTH;
+use super::parser::Location;
+use super::value;
+use super::value::Runtime;
+use std::cell::RefCell;
+use std::io::Write;
+use std::rc::Rc;
+
+/// Values of a program running on the machine, whose instances and
+/// functions point into the compiled program.
+pub struct Compiled;
+
+impl Runtime for Compiled {
+    type Instance = Instance;
+    type Function = Closure;
+
+    fn class_name(instance: &Instance) -> String {
+        instance.class.name.clone()
+    }
+
+    fn function_name(closure: &Closure) -> String {
+        closure.function.name.clone()
+    }
+}
+
+type Value = value::Value<Compiled>;
+
+/// An instance of a class, its fields in the slots of the class's layout.
+pub struct Instance {
+    class: Rc<Class>,
+    fields: RefCell<Vec<Value>>,
+}
+
+/// A function literal along with the cells it captured, or a method bound
+/// to an instance.
+pub struct Closure {
+    function: Rc<Function>,
+    cells: Vec<Rc<RefCell<Value>>>,
+    this: Option<Value>,
+}
+
+/// What a frame leaves on the stack of its caller when it returns.
+#[derive(PartialEq, Clone, Copy)]
+enum Returns {
+    Value,
+    /// The instance it was called on, for `init`.
+    This,
+    /// Nothing at all, for field initialisers.
+    Nothing,
+}
+
+/// A call being run.
+struct Frame {
+    function: Rc<Function>,
+    /// The closure being run, whose cells `GetUpvalue` reads.
+    closure: Option<Rc<Closure>>,
+    cells: Vec<Option<Rc<RefCell<Value>>>>,
+    ip: usize,
+    /// Where the slots of the call start on the stack.
+    base: usize,
+    returns: Returns,
+    /// Where it was called, for stack traces.
+    site: Option<Location>,
+}
+
+type Run<T> = Result<T, Diagnostic>;
+
+/// Runs compiled programs with a stack of values and a stack of call
+/// frames, dispatching one instruction at a time.
+struct Machine<'p, 'o> {
+    program: &'p Program,
+    stack: Vec<Value>,
+    frames: Vec<Frame>,
+    /// Number of frames shown in stack traces, which is how deeply the
+    /// program's calls are nested.
+    depth: usize,
+    out: &'o mut dyn Write,
+}
+
+impl<'p, 'o> Machine<'p, 'o> {
+    fn frame(&self) -> &Frame {
+        self.frames.last().expect("no frame running")
+    }
+
+    fn frame_mut(&mut self) -> &mut Frame {
+        self.frames.last_mut().expect("no frame running")
+    }
+
+    /// A runtime error at the instruction being run, with the calls that
+    /// led to it as notes, innermost first.
+    fn error(&self, message: String) -> Diagnostic {
+        let frame = self.frame();
+        let (source, line_number, start_col) = frame.function.locations[frame.ip - 1];
+        let mut trace = vec![];
+        let shown: Vec<&Frame> = self.frames.iter().filter(|f| !f.function.hidden).collect();
+        for (caller, callee) in shown.iter().zip(shown.iter().skip(1)).rev() {
+            if let Some((source, line_number, start_col)) = callee.site {
+                trace.push(Note {
+                    message: format!(
+                        "`{}` called from `{}`",
+                        callee.function.name, caller.function.name
+                    ),
+                    source,
+                    line_number,
+                    start_col,
+                });
+            }
+        }
+        Diagnostic {
+            source,
+            ..Diagnostic::error(message, line_number, start_col)
+        }
+        .with_trace(trace)
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack.pop().expect("the stack is empty")
+    }
+
+    fn peek(&self) -> &Value {
+        self.stack.last().expect("the stack is empty")
+    }
+
+    fn slot(&mut self, slot: u16) -> &mut Value {
+        let base = self.frame().base;
+        &mut self.stack[base + slot as usize]
+    }
+
+    fn cell(&self, cell: u16) -> Rc<RefCell<Value>> {
+        self.frame().cells[cell as usize]
+            .clone()
+            .expect("cells are made before they are read")
+    }
+
+    fn upvalue(&self, upvalue: u16) -> Rc<RefCell<Value>> {
+        let closure = self.frame().closure.as_ref().expect("no closure running");
+        closure.cells[upvalue as usize].clone()
+    }
+
+    fn constant(&self, constant: u16) -> &Constant {
+        &self.frame().function.constants[constant as usize]
+    }
+
+    fn name(&self, constant: u16) -> Rc<str> {
+        match self.constant(constant) {
+            Constant::Word(word) => word.clone(),
+            _ => Rc::from(""),
+        }
+    }
+
+    /// Starts running `function` on the `argument_count` values on top of the
+    /// stack, preceded by `this` for methods, from `base`.
+    fn call(
+        &mut self,
+        function: Rc<Function>,
+        closure: Option<Rc<Closure>>,
+        argument_count: usize,
+        returns: Returns,
+    ) -> Run<()> {
+        if function.arity != argument_count {
+            return Err(self.error(format!(
+                "`{}` takes {} argument(s) but {} were given",
+                function.name, function.arity, argument_count
+            )));
+        }
+        if self.depth >= MAX_DEPTH && !function.hidden {
+            return Err(self.error(format!(
+                "Stack overflow: calls nested more than {} deep",
+                MAX_DEPTH
+            )));
+        }
+        let base = self.stack.len() - argument_count - usize::from(function.method);
+        let site = self
+            .frames
+            .last()
+            .map(|f| f.function.locations[f.ip - 1])
+            .filter(|_| !self.frames.iter().all(|f| f.function.hidden));
+        self.stack.resize(base + function.slots, Value::Null);
+        self.depth += usize::from(!function.hidden);
+        self.frames.push(Frame {
+            cells: vec![None; function.cells],
+            function,
+            closure,
+            ip: 0,
+            base,
+            returns,
+            site,
+        });
+        Ok(())
+    }
+
+    /// Calls the value below the `argument_count` values on top of the
+    /// stack, which replaces it.
+    fn call_value(&mut self, argument_count: usize) -> Run<()> {
+        let position = self.stack.len() - argument_count - 1;
+        let closure = match &self.stack[position] {
+            Value::Function(closure) => closure.clone(),
+            other => return Err(self.error(format!("`{}` is not a function", other))),
+        };
+        match &closure.this {
+            Some(this) => self.stack[position] = this.clone(),
+            None => {
+                self.stack.remove(position);
+            }
+        }
+        self.call(
+            closure.function.clone(),
+            Some(closure),
+            argument_count,
+            Returns::Value,
+        )
+    }
+
+    fn bind(&self, method: usize, this: Value) -> Value {
+        Value::Function(Rc::new(Closure {
+            function: self.program.methods[method].clone(),
+            cells: vec![],
+            this: Some(this),
+        }))
+    }
+
+    /// Calls the method `name` of the value below the `argument_count`
+    /// values on top of the stack.
+    fn invoke(&mut self, name: &str, argument_count: usize) -> Run<()> {
+        let position = self.stack.len() - argument_count - 1;
+        let object = self.stack[position].clone();
+        match &object {
+            Value::List(_) | Value::Map(_) => {
+                let arguments = self.stack.split_off(position + 1);
+                match object.call_builtin(name, arguments) {
+                    Some(result) => {
+                        self.stack[position] = result;
+                        Ok(())
+                    }
+                    None => Err(self.error(format!("`{}` has no method `{}`", object, name))),
+                }
+            }
+            Value::Instance(instance) => {
+                if let Some(field) = instance.class.fields.get(name) {
+                    self.stack[position] = instance.fields.borrow()[*field].clone();
+                    return self.call_value(argument_count);
+                }
+                match instance.class.methods.get(name) {
+                    Some(slot) => {
+                        let method = self.program.methods[instance.class.vtable[*slot]].clone();
+                        self.call(method, None, argument_count, Returns::Value)
+                    }
+                    None => Err(self.error(format!(
+                        "`{}` has no method `{}`",
+                        instance.class.name, name
+                    ))),
+                }
+            }
+            other => Err(self.error(format!("`{}` has no method `{}`", other, name))),
+        }
+    }
+
+    /// Leaves the running frame with `value`. Returns the value once the
+    /// frame the machine was started with is left.
+    fn leave(&mut self, value: Value) -> Option<Value> {
+        let frame = self.frames.pop().expect("no frame running");
+        self.depth -= usize::from(!frame.function.hidden);
+        let result = match frame.returns {
+            Returns::Value => Some(value),
+            Returns::This => Some(self.stack[frame.base].clone()),
+            Returns::Nothing => None,
+        };
+        self.stack.truncate(frame.base);
+        if self.frames.is_empty() {
+            return Some(result.unwrap_or(Value::Nothing));
+        }
+        self.stack.extend(result);
+        None
+    }
+
+    fn member(&self, object: Value, name: &str) -> Run<Value> {
+        match &object {
+            Value::Instance(instance) => {
+                if let Some(field) = instance.class.fields.get(name) {
+                    return Ok(instance.fields.borrow()[*field].clone());
+                }
+                match instance.class.methods.get(name) {
+                    Some(slot) => Ok(self.bind(instance.class.vtable[*slot], object.clone())),
+                    None => Err(self.error(format!(
+                        "`{}` has no member `{}`",
+                        instance.class.name, name
+                    ))),
+                }
+            }
+            other => other
+                .builtin_member(name)
+                .ok_or_else(|| self.error(format!("`{}` has no member `{}`", other, name))),
+        }
+    }
+
+    /// Applies a built in binary operator other than `&&`, `||` and `??`.
+    fn binary(&self, op: Op, lhs: Value, rhs: Value) -> Run<Value> {
+        let operator = match op {
+            Op::Add => "+",
+            Op::Subtract => "-",
+            Op::Multiply => "*",
+            Op::Divide => "/",
+            Op::Less => "<",
+            Op::Greater => ">",
+            Op::LessEqual => "<=",
+            Op::GreaterEqual => ">=",
+            Op::Equal => "==",
+            _ => "!=",
+        };
+        value::binary(operator, lhs, rhs).map_err(|message| self.error(message))
+    }
+
+    fn condition(&self, value: &Value) -> Run<bool> {
+        value.condition().map_err(|message| self.error(message))
+    }
+
+    /// Whether `value` fits `pattern`, setting the names the pattern binds
+    /// if so.
+    fn matches(&mut self, pattern: &Pattern, value: &Value) -> bool {
+        match (pattern, value) {
+            (Pattern::Wildcard, _) => true,
+            (Pattern::Binding(Var::Slot(slot)), _) => {
+                *self.slot(*slot) = value.clone();
+                true
+            }
+            (Pattern::Binding(Var::Cell(cell)), _) => {
+                self.frame_mut().cells[*cell as usize] = Some(Rc::new(RefCell::new(value.clone())));
+                true
+            }
+            (Pattern::Number(a), Value::Number(b)) => a == b,
+            (Pattern::Word(a), Value::Word(b)) => a == b,
+            (Pattern::Boolean(a), Value::Boolean(b)) => a == b,
+            (Pattern::Variant(name, patterns), Value::Variant(variant)) => {
+                *name == variant.name
+                    && patterns.len() == variant.values.len()
+                    && patterns
+                        .iter()
+                        .zip(&variant.values)
+                        .all(|(p, v)| self.matches(p, v))
+            }
+            _ => false,
+        }
+    }
+
+    /// Runs instructions until the frame the machine was started with
+    /// returns.
+    fn run(&mut self) -> Run<Value> {
+        loop {
+            let frame = self.frames.last_mut().expect("no frame running");
+            let op = frame.function.code[frame.ip];
+            frame.ip += 1;
+            match op {
+                Op::Constant(constant) => {
+                    let value = match self.constant(constant) {
+                        Constant::Number(number) => Value::Number(*number),
+                        Constant::Word(word) => Value::Word(word.clone()),
+                        Constant::Function(_) => Value::Nothing,
+                    };
+                    self.stack.push(value);
+                }
+                Op::Nothing => self.stack.push(Value::Nothing),
+                Op::Null => self.stack.push(Value::Null),
+                Op::True => self.stack.push(Value::Boolean(true)),
+                Op::False => self.stack.push(Value::Boolean(false)),
+                Op::Pop => {
+                    self.pop();
+                }
+                Op::Dup => self.stack.push(self.peek().clone()),
+                Op::Dup2 => {
+                    let length = self.stack.len();
+                    self.stack.extend_from_within(length - 2..);
+                }
+                Op::GetLocal(slot) => {
+                    let value = self.slot(slot).clone();
+                    self.stack.push(value);
+                }
+                Op::SetLocal(slot) => {
+                    let value = self.pop();
+                    *self.slot(slot) = value;
+                }
+                Op::NewCell(cell) => {
+                    let value = self.pop();
+                    self.frame_mut().cells[cell as usize] = Some(Rc::new(RefCell::new(value)));
+                }
+                Op::GetCell(cell) => {
+                    let value = self.cell(cell).borrow().clone();
+                    self.stack.push(value);
+                }
+                Op::SetCell(cell) => {
+                    let value = self.pop();
+                    *self.cell(cell).borrow_mut() = value;
+                }
+                Op::GetUpvalue(upvalue) => {
+                    let value = self.upvalue(upvalue).borrow().clone();
+                    self.stack.push(value);
+                }
+                Op::SetUpvalue(upvalue) => {
+                    let value = self.pop();
+                    *self.upvalue(upvalue).borrow_mut() = value;
+                }
+                Op::GetField(slot) => match self.pop() {
+                    Value::Instance(instance) => {
+                        let value = instance.fields.borrow()[slot as usize].clone();
+                        self.stack.push(value);
+                    }
+                    other => return Err(self.error(format!("`{}` has no fields", other))),
+                },
+                Op::SetField(slot) => {
+                    let value = self.pop();
+                    match self.pop() {
+                        Value::Instance(instance) => {
+                            instance.fields.borrow_mut()[slot as usize] = value;
+                        }
+                        other => return Err(self.error(format!("`{}` has no fields", other))),
+                    }
+                }
+                Op::GetMember(name) => {
+                    let object = self.pop();
+                    let value = self.member(object, &self.name(name))?;
+                    self.stack.push(value);
+                }
+                Op::SetMember(name) => {
+                    let value = self.pop();
+                    let name = self.name(name);
+                    match self.pop() {
+                        Value::Instance(instance) if instance.class.fields.contains_key(&*name) => {
+                            let slot = instance.class.fields[&*name];
+                            instance.fields.borrow_mut()[slot] = value;
+                        }
+                        other => {
+                            return Err(self.error(format!("Cannot set `{}` of `{}`", name, other)))
+                        }
+                    }
+                }
+                Op::GetIndex => {
+                    let key = self.pop();
+                    let value = self.pop().get_index(&key);
+                    let value = value.map_err(|message| self.error(message))?;
+                    self.stack.push(value);
+                }
+                Op::SetIndex => {
+                    let value = self.pop();
+                    let key = self.pop();
+                    let set = self.pop().set_index(key, value);
+                    set.map_err(|message| self.error(message))?;
+                }
+                Op::Add
+                | Op::Subtract
+                | Op::Multiply
+                | Op::Divide
+                | Op::Less
+                | Op::Greater
+                | Op::LessEqual
+                | Op::GreaterEqual
+                | Op::Equal
+                | Op::NotEqual => {
+                    let rhs = self.pop();
+                    let lhs = self.pop();
+                    let value = self.binary(op, lhs, rhs)?;
+                    self.stack.push(value);
+                }
+                Op::Negate => {
+                    let value = self.pop().negate();
+                    let value = value.map_err(|message| self.error(message))?;
+                    self.stack.push(value);
+                }
+                Op::Not => {
+                    let value = self.pop().not();
+                    let value = value.map_err(|message| self.error(message))?;
+                    self.stack.push(value);
+                }
+                Op::Condition => {
+                    self.condition(self.peek())?;
+                }
+                Op::Jump(target) => self.frame_mut().ip = target as usize,
+                Op::JumpIfFalse(target) => {
+                    let value = self.pop();
+                    if !self.condition(&value)? {
+                        self.frame_mut().ip = target as usize;
+                    }
+                }
+                Op::JumpIfFalseKeep(target) | Op::JumpIfTrueKeep(target) => {
+                    let stops = matches!(op, Op::JumpIfTrueKeep(_));
+                    if let Value::Boolean(holds) = self.peek() {
+                        if *holds == stops {
+                            self.frame_mut().ip = target as usize;
+                            continue;
+                        }
+                    }
+                    self.pop();
+                }
+                Op::JumpIfNotNullKeep(target) => {
+                    if let Value::Null = self.peek() {
+                        self.pop();
+                    } else {
+                        self.frame_mut().ip = target as usize;
+                    }
+                }
+                Op::JumpIfNull(target) => {
+                    if let Value::Null = self.peek() {
+                        self.frame_mut().ip = target as usize;
+                    }
+                }
+                Op::Iterate(slot) => match self.pop() {
+                    Value::List(list) => {
+                        *self.slot(slot) = Value::list(list.borrow().clone());
+                        *self.slot(slot + 1) = Value::Number(0);
+                    }
+                    other => return Err(self.error(format!("Cannot loop over `{}`", other))),
+                },
+                Op::Next(slot, exit) => {
+                    let index = match self.slot(slot + 1) {
+                        Value::Number(index) => *index as usize,
+                        _ => 0,
+                    };
+                    let element = match self.slot(slot) {
+                        Value::List(list) => list.borrow().get(index).cloned(),
+                        _ => None,
+                    };
+                    match element {
+                        Some(element) => {
+                            *self.slot(slot + 1) = Value::Number(index as i64 + 1);
+                            self.stack.push(element);
+                        }
+                        None => self.frame_mut().ip = exit as usize,
+                    }
+                }
+                Op::MakeList(count) => {
+                    let elements = self.stack.split_off(self.stack.len() - count as usize);
+                    self.stack.push(Value::list(elements));
+                }
+                Op::MakeMap(count) => {
+                    let values = self.stack.split_off(self.stack.len() - 2 * count as usize);
+                    let mut entries: Vec<(Value, Value)> = vec![];
+                    for pair in values.chunks(2) {
+                        let (key, value) = (pair[0].clone(), pair[1].clone());
+                        match entries.iter_mut().find(|(k, _)| k.equals(&key)) {
+                            Some((_, slot)) => *slot = value,
+                            None => entries.push((key, value)),
+                        }
+                    }
+                    self.stack.push(Value::Map(Rc::new(RefCell::new(entries))));
+                }
+                Op::MakeVariant(name, tag, count) => {
+                    let values = self.stack.split_off(self.stack.len() - count as usize);
+                    let name = self.name(name);
+                    self.stack.push(Value::variant(name, tag as usize, values));
+                }
+                Op::Closure(constant) => {
+                    let function = match self.constant(constant) {
+                        Constant::Function(function) => function.clone(),
+                        _ => continue,
+                    };
+                    let cells = function
+                        .captures
+                        .iter()
+                        .map(|capture| match capture {
+                            Capture::Cell(cell) => self.cell(*cell),
+                            Capture::Upvalue(upvalue) => self.upvalue(*upvalue),
+                        })
+                        .collect();
+                    self.stack.push(Value::Function(Rc::new(Closure {
+                        function,
+                        cells,
+                        this: None,
+                    })));
+                }
+                Op::Bind(method) => {
+                    let this = self.pop();
+                    self.stack.push(self.bind(method as usize, this));
+                }
+                Op::Call(count) => self.call_value(count as usize)?,
+                Op::Invoke(name, count) => self.invoke(&self.name(name), count as usize)?,
+                Op::InvokeVirtual(slot, count) => {
+                    let position = self.stack.len() - count as usize - 1;
+                    let method = match &self.stack[position] {
+                        Value::Instance(instance) => instance.class.vtable[slot as usize],
+                        other => return Err(self.error(format!("`{}` has no methods", other))),
+                    };
+                    let method = self.program.methods[method].clone();
+                    self.call(method, None, count as usize, Returns::Value)?;
+                }
+                Op::InvokeStatic(method, count) => {
+                    let method = self.program.methods[method as usize].clone();
+                    self.call(method, None, count as usize, Returns::Value)?;
+                }
+                Op::New(class, count) => {
+                    let class = self.program.classes[class as usize].clone();
+                    let instance = Value::Instance(Rc::new(Instance {
+                        fields: RefCell::new(vec![Value::Null; class.field_count]),
+                        class: class.clone(),
+                    }));
+                    let position = self.stack.len() - count as usize;
+                    self.stack.insert(position, instance.clone());
+                    if let Some(initializer) = &class.initializer {
+                        self.stack.push(instance);
+                        self.call(initializer.clone(), None, 0, Returns::Nothing)?;
+                    }
+                }
+                Op::Init(count) => {
+                    let position = self.stack.len() - count as usize - 1;
+                    let class = match &self.stack[position] {
+                        Value::Instance(instance) => instance.class.clone(),
+                        _ => continue,
+                    };
+                    match class.methods.get("init") {
+                        Some(slot) => {
+                            let init = self.program.methods[class.vtable[*slot]].clone();
+                            self.call(init, None, count as usize, Returns::This)?;
+                        }
+                        None if count > 0 => {
+                            return Err(self.error(format!(
+                                "`{}` takes no arguments but {} were given",
+                                class.name, count
+                            )))
+                        }
+                        None => {}
+                    }
+                }
+                Op::Try => match self.pop() {
+                    Value::Variant(variant) if &*variant.name == "Ok" => {
+                        self.stack.push(variant.values[0].clone())
+                    }
+                    Value::Variant(variant) if &*variant.name == "Err" => {
+                        if let Some(value) = self.leave(Value::Variant(variant)) {
+                            return Ok(value);
+                        }
+                    }
+                    other => return Err(self.error(format!("`{}` is not a `Result`", other))),
+                },
+                Op::Match(pattern, next) => {
+                    let function = self.frame().function.clone();
+                    let value = self.peek().clone();
+                    if self.matches(&function.patterns[pattern as usize], &value) {
+                        self.pop();
+                    } else {
+                        self.frame_mut().ip = next as usize;
+                    }
+                }
+                Op::JumpTable(table) => {
+                    let function = self.frame().function.clone();
+                    let table = &function.tables[table as usize];
+                    let target = match self.peek() {
+                        Value::Variant(variant)
+                            if table.variants.get(variant.tag) == Some(&variant.name) =>
+                        {
+                            Some(table.targets[variant.tag])
+                        }
+                        _ => None,
+                    };
+                    match target {
+                        Some(target) => {
+                            self.pop();
+                            self.frame_mut().ip = target as usize;
+                        }
+                        None => self.frame_mut().ip = table.default as usize,
+                    }
+                }
+                Op::NoMatch => {
+                    return Err(self.error(format!("No arm matches `{}`", self.peek())));
+                }
+                Op::Print => {
+                    let value = self.pop();
+                    if let Err(error) = writeln!(self.out, "{}", value) {
+                        return Err(self.error(format!("Cannot print: {}", error)));
+                    }
+                }
+                Op::Return => {
+                    let value = self.pop();
+                    if let Some(value) = self.leave(value) {
+                        return Ok(value);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Runs a compiled program, writing what it prints to `out`. A runtime
+/// error is returned with the calls that led to it as notes.
+pub fn run(program: &Program, out: &mut dyn Write) -> Result<(), Diagnostic> {
+    let mut machine = Machine {
+        program,
+        stack: vec![],
+        frames: vec![],
+        depth: 0,
+        out,
+    };
+    machine.call(program.entry.clone(), None, 0, Returns::Value)?;
+    machine.run().map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bytecode;
+    use crate::codegen;
+    use crate::interpreter;
+    use crate::parser::parse_text;
+
+    type Failure = (String, i32, Vec<(String, i32)>);
+
+    fn failure(d: Diagnostic) -> Failure {
+        (
+            d.message,
+            d.line_number,
+            d.notes
+                .into_iter()
+                .map(|n| (n.message, n.line_number))
+                .collect(),
+        )
+    }
+
+    /// What running `text` on the machine prints, or its runtime error with
+    /// the lines of its notes, after checking that the interpreter gives
+    /// the same. Runs on a thread with a stack as deep as `main` gives the
+    /// interpreter.
+    fn run_text(text: &str) -> Result<String, Failure> {
+        let program = parse_text(text);
+        let program = codegen::include_snippets(&codegen::flatten_sections(&program));
+        let program = codegen::lower_operators(&program);
+        std::thread::Builder::new()
+            .stack_size(1 << 28)
+            .spawn(move || {
+                let mut out = vec![];
+                let interpreted = interpreter::run(&program, &mut out)
+                    .map(|()| String::from_utf8(out).unwrap())
+                    .map_err(failure);
+                let mut out = vec![];
+                let compiled = bytecode::compile(&program).unwrap();
+                let ran = run(&compiled, &mut out)
+                    .map(|()| String::from_utf8(out).unwrap())
+                    .map_err(failure);
+                assert_eq!(ran, interpreted);
+                ran
+            })
+            .unwrap()
+            .join()
+            .unwrap()
+    }
+
+    #[test]
+    fn runs_programs_as_the_interpreter_does() {
+        let output = run_text(
+            "snippet Named { let name: Word; }
+             enum Day { Mon, Tue, Wed }
+             enum Shape { Circle(Number), Square(Number), Empty }
+             class Animal {
+                include Named;
+                let tricks: List<Word> = [];
+                let init: Function = (name: Word) { this.name = name; }
+                let sound: Function = () ~Word { return \"...\"; }
+                let speak: Function = () ~Word { return this.name + \" says \" + this.sound(); }
+             }
+             class Dog extends Animal {
+                let sound: Function = () ~Word { return \"woof, \" + super.sound(); }
+             }
+             class Main {
+                let friend: Animal? = null;
+                let make: Function = (start: Number) ~Function {
+                    let count: Number = start;
+                    return () ~Number { count += 1; return count; };
+                }
+                let half: Function = (n: Number) ~Result<Number, Word> {
+                    if (n / 2 * 2 != n) { return Err(\"odd: \" + n); }
+                    return Ok(n / 2);
+                }
+                let quarter: Function = (n: Number) ~Result<Number, Word> {
+                    return Ok(this.half(this.half(n)?)?);
+                }
+                let weekend: Function = (day: Day) ~Boolean {
+                    return match (day) { Day.Mon => false, Day.Tue => false, _ => true };
+                }
+                let area: Function = (shape: Shape) ~Number {
+                    return match (shape) {
+                        Shape.Circle(r) => 3 * r * r,
+                        Shape.Square(1) => 1,
+                        Shape.Square(side) => side * side,
+                        _ => 0
+                    };
+                }
+                let main: Function = () {
+                    let dog: Dog = new Dog(\"Rex\");
+                    print(dog.speak());
+                    dog.tricks.push(\"sit\");
+                    print(dog.tricks);
+                    print(new Dog(\"Fido\").tricks.length);
+                    let next: Function = this.make(10);
+                    next();
+                    print(next());
+                    print(this.friend?.name ?? \"nobody\");
+                    print(this.quarter(12));
+                    print(this.quarter(6));
+                    print(this.weekend(Day.Tue) || this.weekend(Day.Wed));
+                    print([this.area(Shape.Circle(2)), this.area(Shape.Square(1)), this.area(Shape.Square(3)), this.area(Shape.Empty)]);
+                    let counters: List<Function> = [];
+                    let total: Number = 0;
+                    for (n in [1, 2, 3, 4, 5]) {
+                        if (n == 2) { continue; }
+                        if (n == 5) { break; }
+                        total += n;
+                        counters.push(() ~Number { return n; });
+                    }
+                    print(total);
+                    print(counters[0]() + counters[2]());
+                    let ages: Map<Word, Number> = {\"a\": 1};
+                    ages[\"b\"] = 2;
+                    ages[\"a\"] += 10;
+                    print(ages);
+                    print(ages.has(\"b\") && ages.keys().length == 2);
+                    print(!ages.has(\"c\") && !(1 > 2));
+                    let countdown: Function = (n: Number) ~Number {
+                        if (n == 0) { return 0; }
+                        return countdown(n - 1);
+                    };
+                    print(countdown(3));
+                }
+             }",
+        );
+        assert_eq!(
+            output,
+            Ok(String::from(
+                "Rex says woof, ...\n[\"sit\"]\n0\n12\nnobody\nOk(3)\nErr(\"odd: 3\")\ntrue\n[12, 1, 9, 0]\n8\n5\n{\"a\": 11, \"b\": 2}\ntrue\ntrue\n0\n"
+            ))
+        );
+    }
+
+    #[test]
+    fn runtime_errors_carry_a_stack_trace() {
+        let program = |statement: &str| {
+            format!(
+                "class Main {{
+                    let callback: Function;
+                    let inner: Function = (a: Number, b: Number) ~Number {{
+                        {}
+                        return 0;
+                    }}
+                    let outer: Function = (n: Number) ~Number {{
+                        return this.inner(n, 0);
+                    }}
+                    let main: Function = () {{ print(this.outer(1)); }}
+                 }}",
+                statement
+            )
+        };
+        let trace = vec![
+            (String::from("`Main.inner` called from `Main.outer`"), 8),
+            (String::from("`Main.outer` called from `Main.main`"), 10),
+        ];
+        assert_eq!(
+            run_text(&program("return a / b;")),
+            Err((String::from("Division by zero"), 4, trace.clone()))
+        );
+        assert_eq!(
+            run_text(&program("this.callback();")),
+            Err((String::from("`null` is not a function"), 4, trace.clone()))
+        );
+        assert_eq!(
+            run_text(&program("let xs: List<Number> = [a]; print(xs[b + 1]);")),
+            Err((
+                String::from("Index 1 is out of bounds for a list of 1 element(s)"),
+                4,
+                trace
+            ))
+        );
+        assert_eq!(
+            run_text("class Main { let main: Function = () { this.main(); } }").map_err(|e| e.0),
+            Err(String::from(
+                "Stack overflow: calls nested more than 1000 deep"
+            ))
+        );
+        assert_eq!(
+            run_text(
+                "class Main {
+                    let down: Function = (n: Number) ~Number { return this.down(n - 1); }
+                    let main: Function = () { print(this.down(5000)); }
+                 }"
+            )
+            .map_err(|e| e.2.len()),
+            Err(3)
+        );
+    }
+
+    #[test]
+    fn lists_and_calls_past_255_values() {
+        let numbers = vec!["1"; 300].join(", ");
+        let params: Vec<String> = (0..300).map(|i| format!("p{}: Number", i)).collect();
+        let output = run_text(&format!(
+            "class Main {{
+                let last: Function = ({}) ~Number {{ return p299; }}
+                let main: Function = () {{
+                    let xs: List<Number> = [{}];
+                    print(xs.length);
+                    print(this.last({}));
+                }}
+             }}",
+            params.join(", "),
+            numbers,
+            numbers
+        ));
+        assert_eq!(output, Ok(String::from("300\n1\n")));
+    }
+}